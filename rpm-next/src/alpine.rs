@@ -0,0 +1,265 @@
+//! Alpine Repository Adapter
+//!
+//! Connects to Alpine Linux package repositories.
+//! Supports the APKINDEX.tar.gz package index format.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+
+use crate::{Dependency, PackageFormat, PackageInfo, PkgError, apk, repository};
+
+/// Official Alpine CDN
+pub const ALPINE_MIRROR: &str = "https://dl-cdn.alpinelinux.org/alpine";
+pub const ALPINE_BRANCH: &str = "v3.20";
+pub const ALPINE_REPOS: &[&str] = &["main", "community"];
+
+/// APKINDEX entry
+#[derive(Debug, Clone, Default)]
+pub struct AlpinePackage {
+    pub name: String,
+    pub version: String,
+    pub arch: String,
+    pub size: u64,
+    pub installed_size: u64,
+    pub description: String,
+    pub url: String,
+    pub license: String,
+    pub origin: String,
+    pub maintainer: String,
+    pub build_time: u64,
+    pub depends: Vec<Dependency>,
+    pub conflicts: Vec<String>,
+    pub provides: Vec<String>,
+    /// Control segment checksum (`Q1` + base64 SHA-1)
+    pub checksum: String,
+    /// Repository the entry came from (main, community, ...)
+    pub repo: String,
+}
+
+/// Parse an APKINDEX file (blank-line separated records of `X:value` lines)
+pub fn parse_apkindex(content: &str) -> Vec<AlpinePackage> {
+    let mut packages = Vec::new();
+    let mut current = AlpinePackage::default();
+
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            if !current.name.is_empty() {
+                packages.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+
+        match key {
+            "P" => current.name = value.to_string(),
+            "V" => current.version = value.to_string(),
+            "A" => current.arch = value.to_string(),
+            "S" => current.size = value.parse().unwrap_or(0),
+            "I" => current.installed_size = value.parse().unwrap_or(0),
+            "T" => current.description = value.to_string(),
+            "U" => current.url = value.to_string(),
+            "L" => current.license = value.to_string(),
+            "o" => current.origin = value.to_string(),
+            "m" => current.maintainer = value.to_string(),
+            "t" => current.build_time = value.parse().unwrap_or(0),
+            "C" => current.checksum = value.to_string(),
+            "D" => {
+                let (depends, conflicts) = apk::parse_depends(value);
+                current.depends = depends;
+                current.conflicts = conflicts;
+            }
+            "p" => {
+                current.provides = value.split_whitespace().map(|s| s.to_string()).collect();
+            }
+            _ => {}
+        }
+    }
+
+    if !current.name.is_empty() {
+        packages.push(current);
+    }
+
+    packages
+}
+
+/// Extract the APKINDEX file from APKINDEX.tar.gz
+///
+/// The archive is a signature tarball followed by the index tarball, each in
+/// its own gzip stream.
+pub fn read_apkindex(data: &[u8]) -> Result<String, PkgError> {
+    let mut archive = tar::Archive::new(flate2::read::MultiGzDecoder::new(data));
+
+    for entry in archive.entries().map_err(PkgError::IoError)? {
+        let mut entry = entry.map_err(PkgError::IoError)?;
+        if entry.path().map_err(PkgError::IoError)?.as_os_str() == "APKINDEX" {
+            let mut content = String::new();
+            entry
+                .read_to_string(&mut content)
+                .map_err(PkgError::IoError)?;
+            return Ok(content);
+        }
+    }
+
+    Err(PkgError::ParseError(
+        "APKINDEX.tar.gz has no APKINDEX".to_string(),
+    ))
+}
+
+impl From<AlpinePackage> for PackageInfo {
+    fn from(apk: AlpinePackage) -> Self {
+        PackageInfo {
+            name: apk.name,
+            version: apk.version,
+            release: 1,
+            arch: apk.arch,
+            format: PackageFormat::Apk,
+            description: apk.description,
+            maintainer: apk.maintainer,
+            license: apk.license,
+            homepage: apk.url,
+            size: apk.size,
+            installed_size: apk.installed_size,
+            dependencies: apk.depends,
+            conflicts: apk.conflicts,
+            provides: apk.provides,
+            replaces: Vec::new(),
+            files: Vec::new(),
+            file_entries: Vec::new(),
+            checksum: apk.checksum,
+            scripts: Vec::new(),
+            triggers: Vec::new(),
+        }
+    }
+}
+
+/// Alpine repository manager
+pub struct AlpineRepository {
+    /// Mirror URL
+    mirror: String,
+    /// Release branch (v3.20, edge, ...)
+    branch: String,
+    /// Repositories to use (main, community, testing)
+    repos: Vec<String>,
+    /// Architecture
+    arch: String,
+    /// Package cache
+    packages: HashMap<String, Vec<AlpinePackage>>,
+}
+
+impl AlpineRepository {
+    pub fn new(mirror: &str, branch: &str) -> Self {
+        Self {
+            mirror: mirror.trim_end_matches('/').to_string(),
+            branch: branch.to_string(),
+            repos: ALPINE_REPOS.iter().map(|s| s.to_string()).collect(),
+            arch: "x86_64".to_string(),
+            packages: HashMap::new(),
+        }
+    }
+
+    /// Get index URL for a repository
+    pub fn index_url(&self, repo: &str) -> String {
+        format!(
+            "{}/{}/{}/{}/APKINDEX.tar.gz",
+            self.mirror, self.branch, repo, self.arch
+        )
+    }
+
+    /// Sync all repositories
+    pub fn sync(&mut self) -> Result<(), PkgError> {
+        let mut packages: HashMap<String, Vec<AlpinePackage>> = HashMap::new();
+
+        for repo in &self.repos {
+            let data = repository::fetch(&self.index_url(repo))?;
+            for mut pkg in parse_apkindex(&read_apkindex(&data)?) {
+                pkg.repo = repo.clone();
+                packages.entry(pkg.name.clone()).or_default().push(pkg);
+            }
+        }
+
+        self.packages = packages;
+        Ok(())
+    }
+
+    /// Search for packages
+    pub fn search(&self, query: &str) -> Vec<&AlpinePackage> {
+        let query_lower = query.to_lowercase();
+        let mut results = Vec::new();
+
+        for versions in self.packages.values() {
+            for pkg in versions {
+                if pkg.name.to_lowercase().contains(&query_lower)
+                    || pkg.description.to_lowercase().contains(&query_lower)
+                {
+                    results.push(pkg);
+                }
+            }
+        }
+
+        results
+    }
+
+    /// Get the highest version of a package across all repositories
+    pub fn get(&self, name: &str) -> Option<&AlpinePackage> {
+        self.packages
+            .get(name)?
+            .iter()
+            .max_by(|a, b| apk::compare_versions(&a.version, &b.version))
+    }
+
+    /// Every synced package version
+    pub fn packages(&self) -> impl Iterator<Item = &AlpinePackage> {
+        self.packages.values().flatten()
+    }
+
+    /// Get download URL for a package
+    pub fn get_download_url(&self, pkg: &AlpinePackage) -> String {
+        format!(
+            "{}/{}/{}/{}/{}-{}.apk",
+            self.mirror, self.branch, pkg.repo, pkg.arch, pkg.name, pkg.version
+        )
+    }
+}
+
+/// Check a downloaded package against the `C:` checksum of its index entry
+///
+/// Packages without an index checksum, such as local files, pass.
+pub fn verify_download(pkg: &PackageInfo, path: &Path) -> Result<(), PkgError> {
+    if !pkg.checksum.starts_with("Q1") {
+        return Ok(());
+    }
+    let actual = apk::control_checksum(path)?;
+    if actual != pkg.checksum {
+        return Err(PkgError::DownloadError(format!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            pkg.name, pkg.checksum, actual
+        )));
+    }
+    Ok(())
+}
+
+impl Default for AlpineRepository {
+    fn default() -> Self {
+        Self::new(ALPINE_MIRROR, ALPINE_BRANCH)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_returns_the_highest_version_whatever_the_repository_order() {
+        let index = "P:busybox\nV:1.36.1-r29\n\nP:busybox\nV:1.36.1-r5\n\n\
+                     P:busybox\nV:1.36.1_rc1-r30\n";
+        let mut repo = AlpineRepository::default();
+        for pkg in parse_apkindex(index) {
+            repo.packages.entry(pkg.name.clone()).or_default().push(pkg);
+        }
+        assert_eq!(repo.get("busybox").unwrap().version, "1.36.1-r29");
+    }
+}
@@ -0,0 +1,903 @@
+//! Android APK Adapter
+//!
+//! Inspects Android packages (.apk): a ZIP archive with a binary XML
+//! `AndroidManifest.xml`, optional native code under `lib/<abi>/`, and an
+//! APK Signing Block (v2/v3) in front of the central directory.
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::archive;
+use crate::extract::{Attributes, Extractor};
+use crate::playstore::FDroidPackage;
+use crate::reader::PackageReader;
+use crate::{PackageFormat, PackageInfo, PkgError};
+
+/// Largest manifest we are willing to decode
+const MAX_MANIFEST_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Largest signing block we are willing to read
+const MAX_SIGNING_BLOCK_SIZE: u64 = 64 * 1024 * 1024;
+
+const EOCD_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const ZIP64_EOCD_SIGNATURE: u32 = 0x0606_4b50;
+const SIGNING_BLOCK_MAGIC: &[u8; 16] = b"APK Sig Block 42";
+
+/// Signing block entry IDs
+const SIGNATURE_SCHEME_V2: u32 = 0x7109_871a;
+const SIGNATURE_SCHEME_V3: u32 = 0xf053_68c0;
+const SIGNATURE_SCHEME_V31: u32 = 0x1b93_ad61;
+
+/// Binary XML chunk types
+mod chunk {
+    pub const STRING_POOL: u16 = 0x0001;
+    pub const XML: u16 = 0x0003;
+    pub const XML_START_ELEMENT: u16 = 0x0102;
+    pub const XML_RESOURCE_MAP: u16 = 0x0180;
+}
+
+/// `android:` attribute resource IDs, used when attribute names are stripped
+mod attr {
+    pub const NAME: u32 = 0x0101_0003;
+    pub const MIN_SDK_VERSION: u32 = 0x0101_020c;
+    pub const VERSION_CODE: u32 = 0x0101_021b;
+    pub const VERSION_NAME: u32 = 0x0101_021c;
+    pub const TARGET_SDK_VERSION: u32 = 0x0101_0270;
+}
+
+/// Typed attribute value kinds
+mod value_type {
+    pub const STRING: u8 = 0x03;
+    pub const INT_DEC: u8 = 0x10;
+    pub const INT_HEX: u8 = 0x11;
+}
+
+/// Metadata of a local Android package
+#[derive(Debug, Clone, Default)]
+pub struct AndroidApk {
+    pub package: String,
+    pub version_code: u32,
+    pub version_name: String,
+    pub min_sdk: u32,
+    pub target_sdk: u32,
+    pub permissions: Vec<String>,
+    /// ABIs with native libraries (`lib/<abi>/`)
+    pub native_code: Vec<String>,
+    /// Signature schemes found in the signing block (2, 3)
+    pub signature_schemes: Vec<u32>,
+    /// SHA-256 fingerprints of the signer certificates (DER), hex
+    pub signers: Vec<String>,
+    pub apk_name: String,
+    pub size: u64,
+    /// SHA-256 of the whole file, hex
+    pub hash: String,
+}
+
+/// Reader for Android packages
+pub struct AndroidReader;
+
+impl PackageReader for AndroidReader {
+    fn read(&self, path: &Path) -> Result<PackageInfo, PkgError> {
+        parse_android(path)
+    }
+
+    fn extract(&self, path: &Path, extractor: &mut Extractor) -> Result<(), PkgError> {
+        extract_android(path, extractor)
+    }
+}
+
+/// Parse an Android .apk
+pub fn parse_android(path: &Path) -> Result<PackageInfo, PkgError> {
+    read_android(path).map(PackageInfo::from)
+}
+
+/// Read manifest, native code and signer information from an Android .apk
+pub fn read_android(path: &Path) -> Result<AndroidApk, PkgError> {
+    let file = File::open(path).map_err(PkgError::IoError)?;
+    let size = file.metadata().map_err(PkgError::IoError)?.len();
+    let mut zip = zip::ZipArchive::new(file).map_err(zip_error)?;
+
+    let manifest = {
+        let entry = zip.by_name("AndroidManifest.xml").map_err(zip_error)?;
+        if entry.size() > MAX_MANIFEST_SIZE {
+            return Err(PkgError::ExtractionError(
+                "AndroidManifest.xml is too large".to_string(),
+            ));
+        }
+        let mut data = Vec::new();
+        entry
+            .take(MAX_MANIFEST_SIZE)
+            .read_to_end(&mut data)
+            .map_err(PkgError::IoError)?;
+        data
+    };
+
+    let mut apk = parse_manifest(&manifest)?;
+
+    let abis: BTreeSet<String> = zip
+        .file_names()
+        .filter_map(|name| {
+            let rest = name.strip_prefix("lib/")?;
+            let (abi, lib) = rest.split_once('/')?;
+            (!abi.is_empty() && !lib.is_empty()).then(|| abi.to_string())
+        })
+        .collect();
+    apk.native_code = abis.into_iter().collect();
+
+    let mut file = zip.into_inner();
+    for (scheme, certificates) in read_signing_block(&mut file)? {
+        if !apk.signature_schemes.contains(&scheme) {
+            apk.signature_schemes.push(scheme);
+        }
+        for cert in certificates {
+            let fingerprint = format!("{:x}", Sha256::digest(&cert));
+            if !apk.signers.contains(&fingerprint) {
+                apk.signers.push(fingerprint);
+            }
+        }
+    }
+
+    apk.apk_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    apk.size = size;
+    apk.hash = archive::sha256_file(path)?;
+
+    Ok(apk)
+}
+
+/// Install an Android .apk
+///
+/// Like Android, the APK is copied as-is into its app directory.
+pub fn extract_android(path: &Path, extractor: &mut Extractor) -> Result<(), PkgError> {
+    let mtime = std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .map_err(PkgError::IoError)?;
+    let attrs = Attributes {
+        mode: 0o644,
+        user: "root".to_string(),
+        group: "root".to_string(),
+        mtime: mtime
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        ..Default::default()
+    };
+
+    for file in parse_android(path)?.files {
+        let mut apk = std::fs::File::open(path).map_err(PkgError::IoError)?;
+        extractor.write_file(file.trim_start_matches('/'), &mut apk, &attrs)?;
+    }
+    Ok(())
+}
+
+fn zip_error(e: zip::result::ZipError) -> PkgError {
+    match e {
+        zip::result::ZipError::Io(e) => PkgError::IoError(e),
+        e => PkgError::ExtractionError(format!("zip: {}", e)),
+    }
+}
+
+/// Decode the binary XML manifest
+pub fn parse_manifest(data: &[u8]) -> Result<AndroidApk, PkgError> {
+    let reader = ByteReader::new(data);
+    if reader.u16_at(0)? != chunk::XML {
+        return Err(PkgError::ParseError(
+            "AndroidManifest.xml is not binary XML".to_string(),
+        ));
+    }
+    let header_size = reader.u16_at(2)? as usize;
+    let total = (reader.u32_at(4)? as usize).min(data.len());
+
+    let mut strings = Vec::new();
+    let mut resource_ids = Vec::new();
+    let mut apk = AndroidApk::default();
+    let mut offset = header_size;
+
+    while offset + 8 <= total {
+        let chunk_type = reader.u16_at(offset)?;
+        let chunk_header = reader.u16_at(offset + 2)? as usize;
+        let chunk_size = reader.u32_at(offset + 4)? as usize;
+        if chunk_size < 8 || offset + chunk_size > total {
+            return Err(PkgError::ParseError(
+                "Invalid binary XML chunk size".to_string(),
+            ));
+        }
+
+        match chunk_type {
+            chunk::STRING_POOL => strings = read_string_pool(&data[offset..offset + chunk_size])?,
+            chunk::XML_RESOURCE_MAP => {
+                resource_ids = (offset + chunk_header..offset + chunk_size)
+                    .step_by(4)
+                    .map(|pos| reader.u32_at(pos))
+                    .collect::<Result<_, _>>()?;
+            }
+            chunk::XML_START_ELEMENT => {
+                let element = read_element(&reader, offset, chunk_header, &strings, &resource_ids)?;
+                apply_element(&mut apk, &element);
+            }
+            _ => {}
+        }
+
+        offset += chunk_size;
+    }
+
+    if apk.package.is_empty() {
+        return Err(PkgError::ParseError(
+            "AndroidManifest.xml has no package name".to_string(),
+        ));
+    }
+
+    Ok(apk)
+}
+
+/// A start tag with its decoded attributes
+struct Element {
+    name: String,
+    attributes: Vec<Attribute>,
+}
+
+struct Attribute {
+    name: String,
+    resource_id: Option<u32>,
+    value: AttributeValue,
+}
+
+enum AttributeValue {
+    String(String),
+    Int(u32),
+    Other,
+}
+
+impl Element {
+    /// Find an attribute by resource ID, falling back to its name
+    fn get(&self, resource_id: u32, name: &str) -> Option<&AttributeValue> {
+        self.attributes
+            .iter()
+            .find(|a| a.resource_id == Some(resource_id))
+            .or_else(|| self.attributes.iter().find(|a| a.name == name))
+            .map(|a| &a.value)
+    }
+
+    fn get_int(&self, resource_id: u32, name: &str) -> Option<u32> {
+        match self.get(resource_id, name)? {
+            AttributeValue::Int(n) => Some(*n),
+            AttributeValue::String(s) => s.parse().ok(),
+            AttributeValue::Other => None,
+        }
+    }
+
+    fn get_string(&self, resource_id: u32, name: &str) -> Option<String> {
+        match self.get(resource_id, name)? {
+            AttributeValue::String(s) => Some(s.clone()),
+            AttributeValue::Int(n) => Some(n.to_string()),
+            AttributeValue::Other => None,
+        }
+    }
+}
+
+fn apply_element(apk: &mut AndroidApk, element: &Element) {
+    match element.name.as_str() {
+        "manifest" => {
+            // `package` has no resource ID
+            if let Some(AttributeValue::String(package)) = element
+                .attributes
+                .iter()
+                .find(|a| a.name == "package")
+                .map(|a| &a.value)
+            {
+                apk.package = package.clone();
+            }
+            apk.version_code = element
+                .get_int(attr::VERSION_CODE, "versionCode")
+                .unwrap_or(0);
+            apk.version_name = element
+                .get_string(attr::VERSION_NAME, "versionName")
+                .unwrap_or_default();
+        }
+        "uses-sdk" => {
+            apk.min_sdk = element
+                .get_int(attr::MIN_SDK_VERSION, "minSdkVersion")
+                .unwrap_or(1);
+            // targetSdkVersion defaults to minSdkVersion
+            apk.target_sdk = element
+                .get_int(attr::TARGET_SDK_VERSION, "targetSdkVersion")
+                .unwrap_or(apk.min_sdk);
+        }
+        "uses-permission" | "uses-permission-sdk-23" => {
+            if let Some(name) = element.get_string(attr::NAME, "name")
+                && !apk.permissions.contains(&name)
+            {
+                apk.permissions.push(name);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn read_element(
+    reader: &ByteReader,
+    start: usize,
+    header_size: usize,
+    strings: &[String],
+    resource_ids: &[u32],
+) -> Result<Element, PkgError> {
+    let string = |index: u32| strings.get(index as usize).cloned().unwrap_or_default();
+
+    // ns, name, attributeStart, attributeSize, attributeCount
+    let body = start + header_size;
+    let name = string(reader.u32_at(body + 4)?);
+    let attribute_start = reader.u16_at(body + 8)? as usize;
+    let attribute_size = reader.u16_at(body + 10)? as usize;
+    let attribute_count = reader.u16_at(body + 12)? as usize;
+    if attribute_size < 20 {
+        return Err(PkgError::ParseError(
+            "Invalid binary XML attribute size".to_string(),
+        ));
+    }
+
+    let mut attributes = Vec::with_capacity(attribute_count);
+    for i in 0..attribute_count {
+        let pos = body + attribute_start + i * attribute_size;
+        let name_index = reader.u32_at(pos + 4)?;
+        let raw_value = reader.u32_at(pos + 8)?;
+        let data_type = reader.u8_at(pos + 15)?;
+        let data = reader.u32_at(pos + 16)?;
+
+        let value = match data_type {
+            value_type::STRING => AttributeValue::String(string(data)),
+            value_type::INT_DEC | value_type::INT_HEX => AttributeValue::Int(data),
+            _ if raw_value != u32::MAX => AttributeValue::String(string(raw_value)),
+            _ => AttributeValue::Other,
+        };
+
+        attributes.push(Attribute {
+            name: string(name_index),
+            resource_id: resource_ids.get(name_index as usize).copied(),
+            value,
+        });
+    }
+
+    Ok(Element { name, attributes })
+}
+
+/// Decode a string pool chunk (UTF-8 or UTF-16)
+fn read_string_pool(data: &[u8]) -> Result<Vec<String>, PkgError> {
+    const UTF8_FLAG: u32 = 1 << 8;
+
+    let reader = ByteReader::new(data);
+    let string_count = reader.u32_at(8)? as usize;
+    let flags = reader.u32_at(16)?;
+    let strings_start = reader.u32_at(20)? as usize;
+    let header_size = reader.u16_at(2)? as usize;
+
+    if string_count > data.len() / 4 {
+        return Err(PkgError::ParseError(
+            "Invalid binary XML string count".to_string(),
+        ));
+    }
+
+    let mut strings = Vec::with_capacity(string_count);
+    for i in 0..string_count {
+        let offset = strings_start + reader.u32_at(header_size + i * 4)? as usize;
+        let string = if flags & UTF8_FLAG != 0 {
+            // UTF-16 length, then UTF-8 length, each 1 or 2 bytes
+            let (_, skip) = utf8_length(&reader, offset)?;
+            let (len, skip2) = utf8_length(&reader, offset + skip)?;
+            let start = offset + skip + skip2;
+            String::from_utf8_lossy(reader.slice(start, len)?).into_owned()
+        } else {
+            let mut len = reader.u16_at(offset)? as usize;
+            let mut start = offset + 2;
+            if len & 0x8000 != 0 {
+                len = ((len & 0x7fff) << 16) | reader.u16_at(offset + 2)? as usize;
+                start += 2;
+            }
+            let units: Vec<u16> = reader
+                .slice(start, len * 2)?
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        };
+        strings.push(string);
+    }
+
+    Ok(strings)
+}
+
+fn utf8_length(reader: &ByteReader, offset: usize) -> Result<(usize, usize), PkgError> {
+    let first = reader.u8_at(offset)? as usize;
+    if first & 0x80 != 0 {
+        let second = reader.u8_at(offset + 1)? as usize;
+        Ok((((first & 0x7f) << 8) | second, 2))
+    } else {
+        Ok((first, 1))
+    }
+}
+
+/// DER-encoded certificates of one signer
+type Certificates = Vec<Vec<u8>>;
+
+/// Read the v2/v3 signer certificates from the APK Signing Block
+///
+/// Returns `(scheme version, certificates)` per signer; empty when the APK
+/// has no signing block (v1-only or unsigned).
+fn read_signing_block<R: Read + Seek>(file: &mut R) -> Result<Vec<(u32, Certificates)>, PkgError> {
+    let cd_offset = central_directory_offset(file)?;
+    if cd_offset < 32 {
+        return Ok(Vec::new());
+    }
+
+    // Footer: u64 size, 16-byte magic, directly before the central directory
+    let mut footer = [0u8; 24];
+    file.seek(SeekFrom::Start(cd_offset - 24))
+        .map_err(PkgError::IoError)?;
+    file.read_exact(&mut footer).map_err(PkgError::IoError)?;
+    if &footer[8..24] != SIGNING_BLOCK_MAGIC {
+        return Ok(Vec::new());
+    }
+
+    let block_size = u64::from_le_bytes(footer[0..8].try_into().unwrap());
+    if !(24..=MAX_SIGNING_BLOCK_SIZE).contains(&block_size) || block_size + 8 > cd_offset {
+        return Err(PkgError::ParseError(
+            "Invalid APK signing block size".to_string(),
+        ));
+    }
+
+    // The block is prefixed by the same size; pairs sit between it and the footer
+    let mut block = vec![0u8; block_size as usize - 24];
+    file.seek(SeekFrom::Start(cd_offset - block_size))
+        .map_err(PkgError::IoError)?;
+    file.read_exact(&mut block).map_err(PkgError::IoError)?;
+
+    let mut signers = Vec::new();
+    let mut pairs = ByteReader::new(&block);
+    while pairs.remaining() >= 12 {
+        let len = pairs.read_u64()? as usize;
+        let id = pairs.read_u32()?;
+        let value = pairs.take(len.checked_sub(4).ok_or_else(invalid_block)?)?;
+
+        let scheme = match id {
+            SIGNATURE_SCHEME_V2 => 2,
+            SIGNATURE_SCHEME_V3 | SIGNATURE_SCHEME_V31 => 3,
+            _ => continue,
+        };
+        for certificates in read_scheme_signers(value)? {
+            signers.push((scheme, certificates));
+        }
+    }
+
+    Ok(signers)
+}
+
+/// Certificates of each signer in a v2/v3 scheme block
+///
+/// Both layouts start signed data with digests followed by certificates.
+fn read_scheme_signers(value: &[u8]) -> Result<Vec<Certificates>, PkgError> {
+    let mut outer = ByteReader::new(value);
+    let mut signer_list = ByteReader::new(outer.read_prefixed()?);
+    let mut result = Vec::new();
+
+    while signer_list.remaining() > 0 {
+        let mut signer = ByteReader::new(signer_list.read_prefixed()?);
+        let mut signed_data = ByteReader::new(signer.read_prefixed()?);
+        let _digests = signed_data.read_prefixed()?;
+        let mut cert_list = ByteReader::new(signed_data.read_prefixed()?);
+
+        let mut certificates = Vec::new();
+        while cert_list.remaining() > 0 {
+            certificates.push(cert_list.read_prefixed()?.to_vec());
+        }
+        result.push(certificates);
+    }
+
+    Ok(result)
+}
+
+/// Locate the central directory via the (ZIP64) end of central directory record
+fn central_directory_offset<R: Read + Seek>(file: &mut R) -> Result<u64, PkgError> {
+    let len = file.seek(SeekFrom::End(0)).map_err(PkgError::IoError)?;
+    // EOCD is 22 bytes plus a comment of up to 64 KiB
+    let tail_len = len.min(22 + 0xffff);
+    let mut tail = vec![0u8; tail_len as usize];
+    file.seek(SeekFrom::Start(len - tail_len))
+        .map_err(PkgError::IoError)?;
+    file.read_exact(&mut tail).map_err(PkgError::IoError)?;
+
+    let eocd = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| u32::from_le_bytes(tail[i..i + 4].try_into().unwrap()) == EOCD_SIGNATURE)
+        .ok_or_else(|| {
+            PkgError::ExtractionError("Missing ZIP end of central directory".to_string())
+        })?;
+
+    let offset = u32::from_le_bytes(tail[eocd + 16..eocd + 20].try_into().unwrap());
+    if offset != u32::MAX {
+        return Ok(offset as u64);
+    }
+
+    // ZIP64: the locator sits right before the EOCD
+    let locator = (len - tail_len + eocd as u64)
+        .checked_sub(20)
+        .ok_or_else(invalid_block)?;
+    let mut buf = [0u8; 20];
+    file.seek(SeekFrom::Start(locator))
+        .map_err(PkgError::IoError)?;
+    file.read_exact(&mut buf).map_err(PkgError::IoError)?;
+    if u32::from_le_bytes(buf[0..4].try_into().unwrap()) != ZIP64_LOCATOR_SIGNATURE {
+        return Err(PkgError::ExtractionError(
+            "Missing ZIP64 end of central directory locator".to_string(),
+        ));
+    }
+
+    let record = u64::from_le_bytes(buf[8..16].try_into().unwrap());
+    let mut buf = [0u8; 56];
+    file.seek(SeekFrom::Start(record))
+        .map_err(PkgError::IoError)?;
+    file.read_exact(&mut buf).map_err(PkgError::IoError)?;
+    if u32::from_le_bytes(buf[0..4].try_into().unwrap()) != ZIP64_EOCD_SIGNATURE {
+        return Err(PkgError::ExtractionError(
+            "Invalid ZIP64 end of central directory".to_string(),
+        ));
+    }
+    Ok(u64::from_le_bytes(buf[48..56].try_into().unwrap()))
+}
+
+fn invalid_block() -> PkgError {
+    PkgError::ParseError("Truncated APK signing block".to_string())
+}
+
+/// Little-endian reader over a byte slice with bounds checks
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.pos)
+    }
+
+    fn slice(&self, offset: usize, len: usize) -> Result<&'a [u8], PkgError> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| PkgError::ParseError("Unexpected end of data".to_string()))
+    }
+
+    fn u8_at(&self, offset: usize) -> Result<u8, PkgError> {
+        Ok(self.slice(offset, 1)?[0])
+    }
+
+    fn u16_at(&self, offset: usize) -> Result<u16, PkgError> {
+        let b = self.slice(offset, 2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32_at(&self, offset: usize) -> Result<u32, PkgError> {
+        let b = self.slice(offset, 4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], PkgError> {
+        let slice = self.slice(self.pos, len)?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, PkgError> {
+        let value = self.u32_at(self.pos)?;
+        self.pos += 4;
+        Ok(value)
+    }
+
+    fn read_u64(&mut self) -> Result<u64, PkgError> {
+        let b = self.take(8)?;
+        Ok(u64::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    /// A u32 length followed by that many bytes
+    fn read_prefixed(&mut self) -> Result<&'a [u8], PkgError> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+}
+
+impl From<AndroidApk> for PackageInfo {
+    fn from(apk: AndroidApk) -> Self {
+        // Installed like Android does: one directory per package
+        let files = vec![format!("/data/app/{}/base.apk", apk.package)];
+
+        PackageInfo {
+            name: apk.package,
+            version: apk.version_name,
+            release: apk.version_code,
+            arch: if apk.native_code.is_empty() {
+                "any".to_string()
+            } else {
+                apk.native_code.join(",")
+            },
+            format: PackageFormat::Android,
+            description: String::new(),
+            maintainer: String::new(),
+            license: String::new(),
+            homepage: String::new(),
+            size: apk.size,
+            installed_size: 0,
+            dependencies: Vec::new(),
+            conflicts: Vec::new(),
+            provides: Vec::new(),
+            replaces: Vec::new(),
+            files,
+            file_entries: Vec::new(),
+            checksum: apk.hash,
+            scripts: Vec::new(),
+            triggers: Vec::new(),
+        }
+    }
+}
+
+impl From<AndroidApk> for FDroidPackage {
+    fn from(apk: AndroidApk) -> Self {
+        FDroidPackage {
+            version_name: apk.version_name,
+            version_code: apk.version_code,
+            apk_name: apk.apk_name,
+            hash: apk.hash,
+            hash_type: "sha256".to_string(),
+            size: apk.size,
+            min_sdk: apk.min_sdk,
+            target_sdk: apk.target_sdk,
+            native_code: apk.native_code,
+            permissions: apk.permissions,
+            signer: apk.signers.first().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A string pool chunk, UTF-8 or UTF-16
+    fn string_pool(strings: &[&str], utf8: bool) -> Vec<u8> {
+        let mut offsets = Vec::new();
+        let mut data = Vec::new();
+        for s in strings {
+            offsets.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            if utf8 {
+                data.push(s.chars().count() as u8);
+                data.push(s.len() as u8);
+                data.extend_from_slice(s.as_bytes());
+                data.push(0);
+            } else {
+                let units: Vec<u16> = s.encode_utf16().collect();
+                data.extend_from_slice(&(units.len() as u16).to_le_bytes());
+                for unit in units {
+                    data.extend_from_slice(&unit.to_le_bytes());
+                }
+                data.extend_from_slice(&[0, 0]);
+            }
+        }
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+
+        let strings_start = 28 + offsets.len();
+        let mut chunk = Vec::new();
+        chunk.extend_from_slice(&chunk::STRING_POOL.to_le_bytes());
+        chunk.extend_from_slice(&28u16.to_le_bytes());
+        chunk.extend_from_slice(&((strings_start + data.len()) as u32).to_le_bytes());
+        chunk.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(&0u32.to_le_bytes());
+        chunk.extend_from_slice(&(if utf8 { 1u32 << 8 } else { 0 }).to_le_bytes());
+        chunk.extend_from_slice(&(strings_start as u32).to_le_bytes());
+        chunk.extend_from_slice(&0u32.to_le_bytes());
+        chunk.extend_from_slice(&offsets);
+        chunk.extend_from_slice(&data);
+        chunk
+    }
+
+    /// A start tag; attributes are `(name, type, data)` with string data
+    /// given as a string pool index
+    fn start_element(name: u32, attributes: &[(u32, u8, u32)]) -> Vec<u8> {
+        let mut chunk = Vec::new();
+        chunk.extend_from_slice(&chunk::XML_START_ELEMENT.to_le_bytes());
+        chunk.extend_from_slice(&16u16.to_le_bytes());
+        chunk.extend_from_slice(&((36 + 20 * attributes.len()) as u32).to_le_bytes());
+        chunk.extend_from_slice(&1u32.to_le_bytes()); // line number
+        chunk.extend_from_slice(&u32::MAX.to_le_bytes()); // comment
+        chunk.extend_from_slice(&u32::MAX.to_le_bytes()); // namespace
+        chunk.extend_from_slice(&name.to_le_bytes());
+        chunk.extend_from_slice(&20u16.to_le_bytes());
+        chunk.extend_from_slice(&20u16.to_le_bytes());
+        chunk.extend_from_slice(&(attributes.len() as u16).to_le_bytes());
+        chunk.extend_from_slice(&[0; 6]); // id, class and style indexes
+        for &(name, data_type, data) in attributes {
+            let raw = if data_type == value_type::STRING {
+                data
+            } else {
+                u32::MAX
+            };
+            chunk.extend_from_slice(&u32::MAX.to_le_bytes());
+            chunk.extend_from_slice(&name.to_le_bytes());
+            chunk.extend_from_slice(&raw.to_le_bytes());
+            chunk.extend_from_slice(&8u16.to_le_bytes());
+            chunk.push(0);
+            chunk.push(data_type);
+            chunk.extend_from_slice(&data.to_le_bytes());
+        }
+        chunk
+    }
+
+    fn xml(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body = chunks.concat();
+        let mut data = Vec::new();
+        data.extend_from_slice(&chunk::XML.to_le_bytes());
+        data.extend_from_slice(&8u16.to_le_bytes());
+        data.extend_from_slice(&((8 + body.len()) as u32).to_le_bytes());
+        data.extend_from_slice(&body);
+        data
+    }
+
+    fn manifest(utf8: bool) -> Vec<u8> {
+        let strings = [
+            "manifest",
+            "package",
+            "org.example.app",
+            "versionCode",
+            "versionName",
+            "1.2-ü",
+            "uses-sdk",
+            "minSdkVersion",
+            "uses-permission",
+            "name",
+            "android.permission.INTERNET",
+        ];
+        xml(&[
+            string_pool(&strings, utf8),
+            start_element(
+                0,
+                &[
+                    (1, value_type::STRING, 2),
+                    (3, value_type::INT_DEC, 42),
+                    (4, value_type::STRING, 5),
+                ],
+            ),
+            start_element(6, &[(7, value_type::INT_DEC, 21)]),
+            start_element(8, &[(9, value_type::STRING, 10)]),
+        ])
+    }
+
+    #[test]
+    fn manifest_is_decoded_from_either_string_pool_encoding() {
+        for utf8 in [true, false] {
+            let apk = parse_manifest(&manifest(utf8)).unwrap();
+            assert_eq!(apk.package, "org.example.app");
+            assert_eq!(apk.version_code, 42);
+            assert_eq!(apk.version_name, "1.2-ü");
+            assert_eq!(apk.min_sdk, 21);
+            // targetSdkVersion defaults to minSdkVersion
+            assert_eq!(apk.target_sdk, 21);
+            assert_eq!(apk.permissions, ["android.permission.INTERNET"]);
+        }
+
+        let mut truncated = manifest(true);
+        truncated.truncate(truncated.len() - 10);
+        let len = truncated.len() as u32;
+        truncated[4..8].copy_from_slice(&len.to_le_bytes());
+        assert!(matches!(
+            parse_manifest(&truncated),
+            Err(PkgError::ParseError(_))
+        ));
+    }
+
+    fn prefixed(data: &[u8]) -> Vec<u8> {
+        let mut out = (data.len() as u32).to_le_bytes().to_vec();
+        out.extend_from_slice(data);
+        out
+    }
+
+    /// A v2 scheme value with one signer holding `certificate`
+    fn v2_signers(certificate: &[u8]) -> Vec<u8> {
+        let signed_data = [prefixed(&[]), prefixed(&prefixed(certificate))].concat();
+        let signer = [prefixed(&signed_data), prefixed(&[]), prefixed(&[])].concat();
+        prefixed(&prefixed(&signer))
+    }
+
+    /// ZIP tail: local entries stand-in, signing block, empty central
+    /// directory and its end record
+    fn apk_tail(pairs: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let mut data = vec![0u8; 64];
+        if !pairs.is_empty() {
+            let mut block = Vec::new();
+            for (id, value) in pairs {
+                block.extend_from_slice(&(value.len() as u64 + 4).to_le_bytes());
+                block.extend_from_slice(&id.to_le_bytes());
+                block.extend_from_slice(value);
+            }
+            let size = (block.len() + 24) as u64;
+            data.extend_from_slice(&size.to_le_bytes());
+            data.extend_from_slice(&block);
+            data.extend_from_slice(&size.to_le_bytes());
+            data.extend_from_slice(SIGNING_BLOCK_MAGIC);
+        }
+
+        let cd_offset = data.len() as u32;
+        data.extend_from_slice(&EOCD_SIGNATURE.to_le_bytes());
+        data.extend_from_slice(&[0; 12]);
+        data.extend_from_slice(&cd_offset.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn signers_are_read_from_the_signing_block() {
+        let data = apk_tail(&[
+            (0x4272_7a2a, vec![0; 8]),
+            (SIGNATURE_SCHEME_V2, v2_signers(b"cert")),
+        ]);
+        let cd_offset = central_directory_offset(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(cd_offset, data.len() as u64 - 22);
+
+        let signers = read_signing_block(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(signers, [(2, vec![b"cert".to_vec()])]);
+
+        // v1-only: nothing in front of the central directory
+        let data = apk_tail(&[]);
+        assert!(
+            read_signing_block(&mut Cursor::new(&data))
+                .unwrap()
+                .is_empty()
+        );
+
+        assert!(matches!(
+            central_directory_offset(&mut Cursor::new(vec![0u8; 64])),
+            Err(PkgError::ExtractionError(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_signing_blocks_are_parse_errors() {
+        // A pair claiming more than the block holds
+        let mut pair = Vec::new();
+        pair.extend_from_slice(&1000u64.to_le_bytes());
+        pair.extend_from_slice(&SIGNATURE_SCHEME_V2.to_le_bytes());
+        let mut data = apk_tail(&[(SIGNATURE_SCHEME_V2, v2_signers(b"cert"))]);
+        let start = 64 + 8;
+        data[start..start + 12].copy_from_slice(&pair);
+        assert!(matches!(
+            read_signing_block(&mut Cursor::new(&data)),
+            Err(PkgError::ParseError(_))
+        ));
+
+        // A signer list cut short inside the scheme value
+        let mut value = v2_signers(b"cert");
+        value.truncate(value.len() - 3);
+        let data = apk_tail(&[(SIGNATURE_SCHEME_V2, value)]);
+        assert!(matches!(
+            read_signing_block(&mut Cursor::new(&data)),
+            Err(PkgError::ParseError(_))
+        ));
+
+        // A block size reaching past the start of the file
+        let mut data = apk_tail(&[(SIGNATURE_SCHEME_V2, v2_signers(b"cert"))]);
+        let footer = data.len() - 22 - 24;
+        data[footer..footer + 8].copy_from_slice(&10_000u64.to_le_bytes());
+        assert!(matches!(
+            read_signing_block(&mut Cursor::new(&data)),
+            Err(PkgError::ParseError(_))
+        ));
+    }
+}
@@ -0,0 +1,509 @@
+//! Alpine APK Adapter
+//!
+//! Handles Alpine Linux package format (.apk v2): concatenated gzip
+//! streams holding an optional signature tarball, a control tarball with
+//! `.PKGINFO`, and the data tarball.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use base64::Engine;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::archive;
+use crate::extract::{self, Extractor};
+use crate::reader::PackageReader;
+use crate::{ConstraintOp, Dependency, PackageFormat, PackageInfo, PkgError, VersionConstraint};
+
+/// Reader for Alpine packages
+pub struct ApkReader;
+
+impl PackageReader for ApkReader {
+    fn read(&self, path: &Path) -> Result<PackageInfo, PkgError> {
+        parse_apk(path)
+    }
+
+    fn extract(&self, path: &Path, extractor: &mut Extractor) -> Result<(), PkgError> {
+        extract_apk(path, extractor)
+    }
+}
+
+/// Parse an Alpine .apk package
+pub fn parse_apk(path: &Path) -> Result<PackageInfo, PkgError> {
+    let size = std::fs::metadata(path).map_err(PkgError::IoError)?.len();
+
+    let mut control = None;
+    let mut entries = Vec::new();
+
+    let digests = read_segments(path, |segment| {
+        if control.is_none() {
+            control = read_control(segment)?;
+        } else {
+            entries = archive::tar_entries(segment)?;
+        }
+        Ok(control.is_some())
+    })?;
+
+    let control =
+        control.ok_or_else(|| PkgError::ExtractionError("Missing .PKGINFO".to_string()))?;
+    let pkginfo = control
+        .get(".PKGINFO")
+        .map(|c| String::from_utf8_lossy(c).into_owned())
+        .unwrap_or_default();
+    let (mut info, expected_hash) = parse_pkginfo(&pkginfo)?;
+
+    if let Some(expected) = expected_hash
+        && digests.data.as_deref() != Some(expected.as_str())
+    {
+        return Err(PkgError::ExtractionError(format!(
+            "Data hash mismatch for {}",
+            info.name
+        )));
+    }
+
+    info.size = size;
+    info.files = entries
+        .iter()
+        .filter(|e| !e.is_dir())
+        .map(|e| e.path.clone())
+        .collect();
+    info.file_entries = entries;
+    info.checksum = archive::sha256_file(path)?;
+
+    Ok(info)
+}
+
+/// Extract the data tarball of an Alpine .apk
+pub fn extract_apk(path: &Path, extractor: &mut Extractor) -> Result<(), PkgError> {
+    let mut control_seen = false;
+    read_segments(path, |segment| {
+        if !control_seen {
+            control_seen = read_control(segment)?.is_some();
+            return Ok(control_seen);
+        }
+
+        extract::unpack_tar(extractor, segment, |_| false)?;
+        Ok(true)
+    })?;
+
+    if !control_seen {
+        return Err(PkgError::ExtractionError("Missing .PKGINFO".to_string()));
+    }
+    Ok(())
+}
+
+/// Repository checksum of a package (`Q1` + base64 SHA-1 of the control
+/// segment), as listed in the `C:` field of APKINDEX
+pub fn control_checksum(path: &Path) -> Result<String, PkgError> {
+    let mut seen = false;
+    let digests = read_segments(path, |segment| {
+        seen = seen || read_control(segment)?.is_some();
+        Ok(seen)
+    })?;
+
+    digests
+        .control
+        .ok_or_else(|| PkgError::ExtractionError("Missing .PKGINFO".to_string()))
+}
+
+/// Digests of the compressed segments of an APK
+struct SegmentDigests {
+    /// `Q1`-prefixed base64 SHA-1 of the control segment
+    control: Option<String>,
+    /// Hex SHA-256 of the data segment (`datahash` in .PKGINFO)
+    data: Option<String>,
+}
+
+/// Feed each gzip segment of the file to `f` until the data segment
+///
+/// `f` returns whether the control segment has been seen; the segment after
+/// it is the data tarball and ends the walk.
+fn read_segments(
+    path: &Path,
+    mut f: impl FnMut(&mut dyn Read) -> Result<bool, PkgError>,
+) -> Result<SegmentDigests, PkgError> {
+    let file = File::open(path).map_err(PkgError::IoError)?;
+    let mut reader = BufReader::new(file);
+    let mut digests = SegmentDigests {
+        control: None,
+        data: None,
+    };
+
+    while !reader.fill_buf().map_err(PkgError::IoError)?.is_empty() {
+        let mut hashing = HashingReader::new(&mut reader);
+        let mut segment = flate2::bufread::GzDecoder::new(&mut hashing);
+
+        let control_seen = f(&mut segment)?;
+        // Finish the gzip member so the next one starts at the right offset
+        std::io::copy(&mut segment, &mut std::io::sink()).map_err(PkgError::IoError)?;
+        drop(segment);
+
+        if digests.control.is_some() {
+            digests.data = Some(format!("{:x}", hashing.sha256.finalize()));
+            break;
+        }
+        if control_seen {
+            let sha1 = hashing.sha1.finalize();
+            digests.control = Some(format!(
+                "Q1{}",
+                base64::engine::general_purpose::STANDARD.encode(sha1)
+            ));
+        }
+    }
+
+    Ok(digests)
+}
+
+/// Read a signature or control segment
+///
+/// Returns the control files when the segment holds `.PKGINFO`, `None` for
+/// a signature segment.
+fn read_control(segment: &mut dyn Read) -> Result<Option<BTreeMap<String, Vec<u8>>>, PkgError> {
+    let mut archive = tar::Archive::new(segment);
+    let mut files = BTreeMap::new();
+
+    for entry in archive.entries().map_err(PkgError::IoError)? {
+        let mut entry = entry.map_err(PkgError::IoError)?;
+        let name = entry
+            .path()
+            .map_err(PkgError::IoError)?
+            .to_string_lossy()
+            .into_owned();
+
+        if name.starts_with(".SIGN.") {
+            continue;
+        }
+        if !name.starts_with('.') {
+            return Err(PkgError::ExtractionError(format!(
+                "Unexpected {} before .PKGINFO",
+                name
+            )));
+        }
+
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).map_err(PkgError::IoError)?;
+        files.insert(name, content);
+    }
+
+    Ok(files.contains_key(".PKGINFO").then_some(files))
+}
+
+/// Parse an APK .PKGINFO, returning the package and its `datahash`
+pub fn parse_pkginfo(content: &str) -> Result<(PackageInfo, Option<String>), PkgError> {
+    let mut info = PackageInfo {
+        name: String::new(),
+        version: String::new(),
+        release: 1,
+        arch: String::new(),
+        format: PackageFormat::Apk,
+        description: String::new(),
+        maintainer: String::new(),
+        license: String::new(),
+        homepage: String::new(),
+        size: 0,
+        installed_size: 0,
+        dependencies: Vec::new(),
+        conflicts: Vec::new(),
+        provides: Vec::new(),
+        replaces: Vec::new(),
+        files: Vec::new(),
+        file_entries: Vec::new(),
+        checksum: String::new(),
+        scripts: Vec::new(),
+        triggers: Vec::new(),
+    };
+    let mut data_hash = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+
+        match key.trim() {
+            "pkgname" => info.name = value.to_string(),
+            "pkgver" => info.version = value.to_string(),
+            "pkgdesc" => info.description = value.to_string(),
+            "url" => info.homepage = value.to_string(),
+            "size" => info.installed_size = value.parse().unwrap_or(0),
+            "arch" => info.arch = value.to_string(),
+            "license" => info.license = value.to_string(),
+            "maintainer" => info.maintainer = value.to_string(),
+            "packager" if info.maintainer.is_empty() => info.maintainer = value.to_string(),
+            "depend" => {
+                let (depends, conflicts) = parse_depends(value);
+                info.dependencies.extend(depends);
+                info.conflicts.extend(conflicts);
+            }
+            "provides" => info.provides.push(value.to_string()),
+            "replaces" => info.replaces.push(value.to_string()),
+            "datahash" => data_hash = Some(value.to_lowercase()),
+            _ => {}
+        }
+    }
+
+    if info.name.is_empty() {
+        return Err(PkgError::ParseError(
+            "Missing pkgname in .PKGINFO".to_string(),
+        ));
+    }
+
+    Ok((info, data_hash))
+}
+
+/// Parse a whitespace-separated apk dependency list
+///
+/// `!name` entries are conflicts and returned separately.
+pub fn parse_depends(list: &str) -> (Vec<Dependency>, Vec<String>) {
+    let mut depends = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for word in list.split_whitespace() {
+        match word.strip_prefix('!') {
+            Some(conflict) => conflicts.push(parse_dep(conflict).name),
+            None => depends.push(parse_dep(word)),
+        }
+    }
+
+    (depends, conflicts)
+}
+
+/// Parse one apk dependency like `so:libc.musl-x86_64.so.1` or `musl>=1.2`
+fn parse_dep(s: &str) -> Dependency {
+    let Some(idx) = s.find(['<', '>', '=', '~']) else {
+        return Dependency::new(strip_tag(s), None);
+    };
+
+    let (name, rest) = s.split_at(idx);
+    let op_len = rest
+        .find(|c: char| !matches!(c, '<' | '>' | '=' | '~'))
+        .unwrap_or(rest.len());
+    let (op, version) = rest.split_at(op_len);
+
+    let operator = match op {
+        ">=" => Some(ConstraintOp::Ge),
+        "<=" => Some(ConstraintOp::Le),
+        ">" => Some(ConstraintOp::Gt),
+        "<" => Some(ConstraintOp::Lt),
+        "=" => Some(ConstraintOp::Eq),
+        // Fuzzy match (`~1.2` is any 1.2.x); the lower bound is the closest fit
+        "~" | "~=" | "=~" => Some(ConstraintOp::Ge),
+        _ => None,
+    };
+
+    let constraint = operator.map(|operator| VersionConstraint {
+        operator,
+        version: strip_tag(version).to_string(),
+    });
+    Dependency::new(strip_tag(name), constraint)
+}
+
+/// Compare two apk versions (`1.2.3a_rc1-r4`): numbers, then an optional
+/// letter, then `_` suffixes, then the `-r` release
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a, b) = (ApkVersion::parse(a), ApkVersion::parse(b));
+
+    // A version with more numbers is newer (1.2.1 > 1.2)
+    let numbers = a
+        .numbers
+        .iter()
+        .zip(&b.numbers)
+        .map(|(x, y)| x.cmp(y))
+        .find(|o| o.is_ne())
+        .unwrap_or_else(|| a.numbers.len().cmp(&b.numbers.len()));
+
+    // A missing suffix sits between pre-release and post-release ones
+    let len = a.suffixes.len().max(b.suffixes.len());
+    let suffix = |v: &ApkVersion, i: usize| v.suffixes.get(i).copied().unwrap_or((0, 0));
+    let suffixes = (0..len)
+        .map(|i| suffix(&a, i).cmp(&suffix(&b, i)))
+        .find(|o| o.is_ne())
+        .unwrap_or(Ordering::Equal);
+
+    numbers
+        .then(a.letter.cmp(&b.letter))
+        .then(suffixes)
+        .then(a.release.cmp(&b.release))
+}
+
+/// Parts of an apk version string
+struct ApkVersion {
+    numbers: Vec<u64>,
+    letter: Option<char>,
+    /// Suffix rank and number (`_rc1` -> (-1, 1))
+    suffixes: Vec<(i32, u64)>,
+    release: u64,
+}
+
+impl ApkVersion {
+    fn parse(version: &str) -> Self {
+        let (version, release) = match version.rsplit_once("-r") {
+            Some((v, r)) if r.bytes().all(|c| c.is_ascii_digit()) => (v, r.parse().unwrap_or(0)),
+            _ => (version, 0),
+        };
+        let mut parts = version.split('_');
+        let mut core = parts.next().unwrap_or_default();
+
+        let letter = core.chars().last().filter(char::is_ascii_alphabetic);
+        if letter.is_some() {
+            core = &core[..core.len() - 1];
+        }
+        let numbers = core.split('.').map(|n| n.parse().unwrap_or(0)).collect();
+
+        let suffixes = parts
+            .map(|suffix| {
+                let split = suffix
+                    .find(|c: char| c.is_ascii_digit())
+                    .unwrap_or(suffix.len());
+                let (name, number) = suffix.split_at(split);
+                let rank = match name {
+                    "alpha" => -4,
+                    "beta" => -3,
+                    "pre" => -2,
+                    "rc" => -1,
+                    "cvs" => 1,
+                    "svn" => 2,
+                    "git" => 3,
+                    "hg" => 4,
+                    "p" => 5,
+                    _ => 0,
+                };
+                (rank, number.parse().unwrap_or(0))
+            })
+            .collect();
+
+        Self {
+            numbers,
+            letter,
+            suffixes,
+            release,
+        }
+    }
+}
+
+/// Drop a repository pin (`name@testing`)
+fn strip_tag(s: &str) -> &str {
+    s.split('@').next().unwrap_or(s)
+}
+
+/// BufRead adapter hashing every byte its consumer takes
+struct HashingReader<R: BufRead> {
+    inner: R,
+    sha1: Sha1,
+    sha256: Sha256,
+}
+
+impl<R: BufRead> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            sha1: Sha1::new(),
+            sha256: Sha256::new(),
+        }
+    }
+}
+
+impl<R: BufRead> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for HashingReader<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Ok(buf) = self.inner.fill_buf() {
+            let consumed = &buf[..amt.min(buf.len())];
+            self.sha1.update(consumed);
+            self.sha256.update(consumed);
+        }
+        self.inner.consume(amt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_compare_like_apk() {
+        for (lower, higher) in [
+            ("1.2", "1.10"),
+            ("1.2", "1.2.1"),
+            ("1.2", "1.2a"),
+            ("1.2_rc1", "1.2"),
+            ("1.2_alpha2", "1.2_beta1"),
+            ("1.2", "1.2_p1"),
+            ("1.2-r9", "1.2-r10"),
+            ("1.2_rc1-r30", "1.2-r1"),
+        ] {
+            assert_eq!(
+                compare_versions(lower, higher),
+                Ordering::Less,
+                "{lower} < {higher}"
+            );
+            assert_eq!(compare_versions(higher, lower), Ordering::Greater);
+        }
+        assert_eq!(compare_versions("1.2-r0", "1.2"), Ordering::Equal);
+    }
+
+    /// A gzip segment holding a tar of `files`
+    fn tar_gz(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(gz);
+        for (path, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_uid(0);
+            header.set_gid(0);
+            header.set_mtime(0);
+            builder.append_data(&mut header, path, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    /// Parse an APK whose .PKGINFO records `datahash` for `data`
+    fn parse_with_datahash(datahash: &str, data: &[u8]) -> Result<PackageInfo, PkgError> {
+        let pkginfo = format!("pkgname = foo\npkgver = 1.0-r0\ndatahash = {}\n", datahash);
+        let mut apk = tar_gz(&[(".PKGINFO", pkginfo.as_bytes())]);
+        apk.extend_from_slice(data);
+
+        let path = std::env::temp_dir().join(format!("rpm-next-apk-{}.apk", std::process::id()));
+        std::fs::write(&path, apk).unwrap();
+        let result = parse_apk(&path);
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn data_segment_must_match_its_datahash() {
+        let data = tar_gz(&[("usr/bin/foo", b"foo")]);
+        let datahash = format!("{:x}", Sha256::digest(&data));
+
+        let info = parse_with_datahash(&datahash.to_uppercase(), &data).unwrap();
+        assert_eq!(info.name, "foo");
+        assert_eq!(info.files, ["/usr/bin/foo"]);
+
+        let other = tar_gz(&[("usr/bin/foo", b"bar")]);
+        assert!(matches!(
+            parse_with_datahash(&datahash, &other),
+            Err(PkgError::ExtractionError(e)) if e == "Data hash mismatch for foo"
+        ));
+    }
+}
@@ -2,87 +2,484 @@
 //!
 //! Connects to Debian/Ubuntu APT repositories.
 //! Supports both legacy (dists/) and modern repository layouts.
+//!
+//! Indexes are checked against the SHA256 table of the Release file, but
+//! the Release file itself is not authenticated: the OpenPGP signature of
+//! InRelease is stripped unchecked, Release.gpg is never fetched and
+//! `signed_by` is recorded only. The hashes guard against corrupt or
+//! mixed-up downloads, not against a malicious mirror.
 
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
 
-use crate::{
-    ConstraintOp, Dependency, PackageFormat, PackageInfo, PkgError, Repository, VersionConstraint,
-};
+use crate::archive::Compression;
+use crate::deb::{self, parse_depends};
+use crate::repository;
+use crate::{Dependency, PackageFormat, PackageInfo, PkgError, Repository};
 
 /// Common Debian/Ubuntu mirrors
 pub const DEBIAN_MIRROR: &str = "http://deb.debian.org/debian";
 pub const UBUNTU_MIRROR: &str = "http://archive.ubuntu.com/ubuntu";
 
+/// Compressed variants of an index, smallest download first
+const INDEX_SUFFIXES: [&str; 4] = [".xz", ".gz", ".zst", ""];
+
 /// Pop!_OS mirrors (System76)
 pub const POP_OS_MIRROR: &str = "http://apt.pop-os.org/release";
 pub const POP_OS_PROPRIETARY: &str = "http://apt.pop-os.org/proprietary";
 pub const POP_OS_CUDA: &str = "http://apt.pop-os.org/proprietary-cuda";
 
 /// APT repository source
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct AptSource {
     pub source_type: String, // deb or deb-src
     pub uri: String,
     pub distribution: String,
     pub components: Vec<String>,
     pub architectures: Vec<String>,
+    /// Keyring path, or an inline armored key from a `.sources` stanza;
+    /// not used to verify signatures yet
+    pub signed_by: Option<String>,
+    /// `trusted=yes`: signature checks are skipped for this source
+    pub trusted: bool,
+    /// `allow-insecure=yes`: the source may have no Release file, so its
+    /// indexes are used unverified
+    pub allow_insecure: bool,
+    /// Translation languages (`lang=`)
+    pub languages: Vec<String>,
+    /// Index targets to fetch (`target=`)
+    pub targets: Vec<String>,
 }
 
 impl AptSource {
     /// Parse a sources.list line
+    ///
+    /// Options go in brackets after the type, e.g.
+    /// `deb [arch=amd64,arm64 signed-by=/usr/share/keyrings/x.gpg] uri suite main`.
     pub fn parse(line: &str) -> Option<Self> {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with('#') {
+        let line = line.split('#').next()?.trim();
+        let (source_type, rest) = line.split_once(char::is_whitespace)?;
+        if source_type != "deb" && source_type != "deb-src" {
             return None;
         }
 
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() < 4 {
-            return None;
+        let mut source = Self {
+            source_type: source_type.to_string(),
+            architectures: vec!["amd64".to_string()],
+            ..Self::default()
+        };
+
+        let mut rest = rest.trim_start();
+        if let Some(options) = rest.strip_prefix('[') {
+            let (options, after) = options.split_once(']')?;
+            // Like apt, a malformed option is skipped, not the whole line
+            for option in options.split_whitespace() {
+                if let Some((key, value)) = option.split_once('=') {
+                    source.set_option(key, value);
+                }
+            }
+            rest = after;
         }
 
-        let source_type = parts[0].to_string();
-        if source_type != "deb" && source_type != "deb-src" {
+        // Flat repositories (`./`) have no components; others need some
+        let parts: Vec<&str> = rest.split_whitespace().collect();
+        if parts.len() < 2 || (parts.len() > 2) == parts[1].ends_with('/') {
             return None;
         }
+        source.uri = parts[0].to_string();
+        source.distribution = parts[1].to_string();
+        source.components = parts[2..].iter().map(|s| s.to_string()).collect();
+        Some(source)
+    }
 
-        // Handle [arch=...] options
-        let (uri_idx, archs) = if parts[1].starts_with('[') {
-            let opts = parts[1].trim_start_matches('[').trim_end_matches(']');
-            let archs = if let Some(arch_str) = opts.strip_prefix("arch=") {
-                arch_str.split(',').map(|s| s.to_string()).collect()
-            } else {
-                vec!["amd64".to_string()]
-            };
-            (2, archs)
-        } else {
-            (1, vec!["amd64".to_string()])
+    /// Apply a one-line option; `key+=` adds to and `key-=` removes from
+    /// list values
+    fn set_option(&mut self, key: &str, value: &str) {
+        let (key, op) = match key.strip_suffix(['+', '-']) {
+            Some(name) => (name, &key[name.len()..]),
+            None => (key, ""),
+        };
+        let list = match key {
+            "arch" => &mut self.architectures,
+            "lang" => &mut self.languages,
+            "target" => &mut self.targets,
+            "signed-by" => {
+                self.signed_by = Some(value.to_string());
+                return;
+            }
+            "trusted" => {
+                self.trusted = value == "yes";
+                return;
+            }
+            "allow-insecure" => {
+                self.allow_insecure = value == "yes";
+                return;
+            }
+            _ => return,
         };
 
-        Some(Self {
-            source_type,
-            uri: parts[uri_idx].to_string(),
-            distribution: parts[uri_idx + 1].to_string(),
-            components: parts[uri_idx + 2..].iter().map(|s| s.to_string()).collect(),
-            architectures: archs,
-        })
+        let values = value.split(',').filter(|v| !v.is_empty()).map(String::from);
+        match op {
+            "+" => list.extend(values),
+            "-" => {
+                let removed: Vec<String> = values.collect();
+                list.retain(|v| !removed.contains(v));
+            }
+            _ => *list = values.collect(),
+        }
     }
 
-    /// Get the Packages file URL
-    pub fn packages_url(&self, component: &str, arch: &str) -> String {
-        format!(
-            "{}/dists/{}/{}/binary-{}/Packages",
-            self.uri, self.distribution, component, arch
-        )
+    /// Whether this is a flat repository (`deb uri ./`), with its
+    /// indexes in one directory rather than under `dists/`
+    pub fn is_flat(&self) -> bool {
+        self.distribution.ends_with('/')
+    }
+
+    /// Get the URL of a file under `dists/<distribution>`, or in the
+    /// directory of a flat repository
+    pub fn dist_url(&self, path: &str) -> String {
+        let uri = self.uri.trim_end_matches('/');
+        if !self.is_flat() {
+            return format!("{}/dists/{}/{}", uri, self.distribution, path);
+        }
+        match self.distribution.trim_start_matches("./") {
+            "" => format!("{}/{}", uri, path),
+            dir => format!("{}/{}{}", uri, dir, path),
+        }
     }
 
-    /// Get the compressed Packages file URL
-    pub fn packages_gz_url(&self, component: &str, arch: &str) -> String {
-        format!("{}.gz", self.packages_url(component, arch))
+    /// Get the Packages file paths, as listed in the Release file
+    pub fn packages_paths(&self) -> Vec<String> {
+        if self.is_flat() {
+            return vec!["Packages".to_string()];
+        }
+        self.components
+            .iter()
+            .flat_map(|component| {
+                self.architectures
+                    .iter()
+                    .map(move |arch| format!("{}/binary-{}/Packages", component, arch))
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for AptSource {
+    /// One-line sources.list form
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut options = Vec::new();
+        let mut list = |key: &str, values: &[String]| {
+            if !values.is_empty() {
+                options.push(format!("{}={}", key, values.join(",")));
+            }
+        };
+        list("arch", &self.architectures);
+        list("lang", &self.languages);
+        list("target", &self.targets);
+        match &self.signed_by {
+            // An inline key does not fit on one line
+            Some(key) if key.contains('\n') => options.push("signed-by=<inline key>".to_string()),
+            Some(key) => options.push(format!("signed-by={}", key)),
+            None => {}
+        }
+        if self.trusted {
+            options.push("trusted=yes".to_string());
+        }
+        if self.allow_insecure {
+            options.push("allow-insecure=yes".to_string());
+        }
+
+        write!(f, "{} ", self.source_type)?;
+        if !options.is_empty() {
+            write!(f, "[{}] ", options.join(" "))?;
+        }
+        write!(f, "{} {}", self.uri, self.distribution)?;
+        for component in &self.components {
+            write!(f, " {}", component)?;
+        }
+        Ok(())
     }
 }
 
+/// Parse a deb822 `.sources` file
+///
+/// Each stanza yields a source per type, URI and suite; stanzas with
+/// `Enabled: no` are skipped.
+pub fn parse_deb822(content: &str) -> Vec<AptSource> {
+    let mut sources = Vec::new();
+    for stanza in deb822_stanzas(content) {
+        let get = |key: &str| {
+            stanza
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v.as_str())
+        };
+        let words = |key: &str| -> Vec<String> {
+            get(key)
+                .map(|v| v.split_whitespace().map(String::from).collect())
+                .unwrap_or_default()
+        };
+        if get("Enabled").is_some_and(|v| v.eq_ignore_ascii_case("no")) {
+            continue;
+        }
+
+        let yes = |key: &str| get(key).is_some_and(|v| v.eq_ignore_ascii_case("yes"));
+
+        let mut template = AptSource {
+            components: words("Components"),
+            architectures: vec!["amd64".to_string()],
+            signed_by: get("Signed-By").map(String::from),
+            trusted: yes("Trusted"),
+            allow_insecure: yes("Allow-Insecure"),
+            languages: words("Languages"),
+            targets: words("Targets"),
+            ..AptSource::default()
+        };
+        if get("Architectures").is_some() {
+            template.architectures = words("Architectures");
+        }
+
+        for source_type in words("Types") {
+            if source_type != "deb" && source_type != "deb-src" {
+                continue;
+            }
+            for uri in words("URIs") {
+                for suite in words("Suites") {
+                    sources.push(AptSource {
+                        source_type: source_type.clone(),
+                        uri: uri.clone(),
+                        distribution: suite,
+                        ..template.clone()
+                    });
+                }
+            }
+        }
+    }
+    sources
+}
+
+/// Split deb822 content into stanzas of `(field, value)`
+///
+/// Continuation lines are joined with newlines, and a lone `.` stands for
+/// an empty line, as in an inline `Signed-By` key.
+fn deb822_stanzas(content: &str) -> Vec<Vec<(String, String)>> {
+    let mut stanzas = Vec::new();
+    let mut stanza: Vec<(String, String)> = Vec::new();
+
+    for line in content.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.trim().is_empty() {
+            if !stanza.is_empty() {
+                stanzas.push(std::mem::take(&mut stanza));
+            }
+            continue;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = stanza.last_mut() {
+                let line = line.trim();
+                if !value.is_empty() {
+                    value.push('\n');
+                }
+                value.push_str(if line == "." { "" } else { line });
+            }
+            continue;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            stanza.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    if !stanza.is_empty() {
+        stanzas.push(stanza);
+    }
+    stanzas
+}
+
+/// Parsed Release or InRelease file
+#[derive(Debug, Clone, Default)]
+pub struct Release {
+    pub origin: String,
+    pub suite: String,
+    pub codename: String,
+    pub date: String,
+    /// Empty when the file does not expire
+    pub valid_until: String,
+    pub architectures: Vec<String>,
+    pub components: Vec<String>,
+    /// Indexes can be fetched from `by-hash/SHA256/<digest>`
+    pub acquire_by_hash: bool,
+    /// Index files by path relative to `dists/<distribution>`
+    pub sha256: HashMap<String, ReleaseEntry>,
+}
+
+/// An index file listed in a Release file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseEntry {
+    pub sha256: String,
+    pub size: u64,
+}
+
+impl Release {
+    /// Fail when `Valid-Until` has passed
+    pub fn check_valid(&self) -> Result<(), PkgError> {
+        if self.valid_until.is_empty() {
+            return Ok(());
+        }
+        let until = parse_date(&self.valid_until).ok_or_else(|| {
+            PkgError::ParseError(format!("Invalid Valid-Until: {}", self.valid_until))
+        })?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        if now > until {
+            return Err(PkgError::DownloadError(format!(
+                "Release file of {} expired on {}",
+                self.suite, self.valid_until
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check downloaded index data against the SHA256 table
+    pub fn verify(&self, path: &str, data: &[u8]) -> Result<(), PkgError> {
+        let entry = self.sha256.get(path).ok_or_else(|| {
+            PkgError::DownloadError(format!("{} is not listed in the Release file", path))
+        })?;
+        let digest = format!("{:x}", Sha256::digest(data));
+        if data.len() as u64 != entry.size || digest != entry.sha256 {
+            return Err(PkgError::DownloadError(format!(
+                "{}: hash mismatch (expected {}, got {})",
+                path, entry.sha256, digest
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Parse Release content; the OpenPGP armor of an InRelease file is
+/// stripped, not checked, so the result is unauthenticated
+pub fn parse_release(content: &str) -> Result<Release, PkgError> {
+    let mut release = Release::default();
+    let mut in_sha256 = false;
+
+    for line in strip_clearsign(content).lines() {
+        if line.starts_with(' ') {
+            if in_sha256 {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let [digest, size, path] = fields[..] else {
+                    return Err(PkgError::ParseError(format!(
+                        "Invalid Release SHA256 line: {}",
+                        line.trim()
+                    )));
+                };
+                let size = size.parse().map_err(|_| {
+                    PkgError::ParseError(format!("Invalid Release size for {}: {}", path, size))
+                })?;
+                release.sha256.insert(
+                    path.to_string(),
+                    ReleaseEntry {
+                        sha256: digest.to_lowercase(),
+                        size,
+                    },
+                );
+            }
+            continue;
+        }
+
+        in_sha256 = false;
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        let words = || value.split_whitespace().map(String::from).collect();
+
+        match key.trim() {
+            "Origin" => release.origin = value.to_string(),
+            "Suite" => release.suite = value.to_string(),
+            "Codename" => release.codename = value.to_string(),
+            "Date" => release.date = value.to_string(),
+            "Valid-Until" => release.valid_until = value.to_string(),
+            "Architectures" => release.architectures = words(),
+            "Components" => release.components = words(),
+            "Acquire-By-Hash" => release.acquire_by_hash = value.eq_ignore_ascii_case("yes"),
+            "SHA256" => in_sha256 = true,
+            _ => {}
+        }
+    }
+
+    Ok(release)
+}
+
+/// Body of a clearsigned message, or the content itself when unsigned
+fn strip_clearsign(content: &str) -> String {
+    if !content.starts_with("-----BEGIN PGP SIGNED MESSAGE-----") {
+        return content.to_string();
+    }
+
+    // Armor headers (`Hash: SHA512`) end at the first blank line
+    let mut body = String::new();
+    for line in content
+        .lines()
+        .skip(1)
+        .skip_while(|line| !line.trim().is_empty())
+        .skip(1)
+        .take_while(|line| !line.starts_with("-----BEGIN PGP SIGNATURE-----"))
+    {
+        body.push_str(line.strip_prefix("- ").unwrap_or(line));
+        body.push('\n');
+    }
+    body
+}
+
+/// Parse an RFC 2822 date (`Sat, 10 Jun 2023 08:55:32 UTC`) to Unix time
+fn parse_date(date: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let date = date.split_once(',').map_or(date, |(_, rest)| rest);
+    let mut parts = date.split_whitespace();
+    let day: i64 = parts.next()?.parse().ok()?;
+    let month_name = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month_name)? as i64 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':').map(|t| t.parse::<i64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    // `UTC`, `GMT`, `Z` or a numeric offset
+    let offset = match parts.next() {
+        Some(zone) if zone.starts_with(['+', '-']) && zone.len() == 5 => {
+            let minutes = zone[1..3].parse::<i64>().ok()? * 60 + zone[3..].parse::<i64>().ok()?;
+            if zone.starts_with('-') {
+                -minutes
+            } else {
+                minutes
+            }
+        }
+        _ => 0,
+    };
+
+    // Days since the epoch of a proleptic Gregorian date
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second - offset * 60;
+    u64::try_from(seconds).ok()
+}
+
 /// APT package entry from Packages file
 #[derive(Debug, Clone, Default)]
 pub struct AptPackage {
@@ -106,6 +503,8 @@ pub struct AptPackage {
     pub priority: String,
     pub description: String,
     pub homepage: String,
+    /// Source the entry was synced from
+    pub source: Option<Arc<AptSource>>,
 }
 
 /// Parse APT Packages file content
@@ -182,61 +581,6 @@ pub fn parse_packages(content: &str) -> Vec<AptPackage> {
     packages
 }
 
-/// Parse dependency string with version constraints
-fn parse_depends(deps: &str) -> Vec<Dependency> {
-    deps.split(',')
-        .filter_map(|dep| {
-            let dep = dep.trim();
-            // Handle alternatives (|) by taking first option
-            let dep = dep.split('|').next()?.trim();
-
-            // Remove :any suffix
-            let dep = dep.split(':').next()?.trim();
-
-            // Parse version constraint
-            if let Some(paren_start) = dep.find('(') {
-                let name = dep[..paren_start].trim().to_string();
-                let constraint_str = dep[paren_start..].trim_matches(|c| c == '(' || c == ')');
-
-                let constraint = parse_version_constraint(constraint_str);
-                Some(Dependency {
-                    name,
-                    version_constraint: constraint,
-                })
-            } else {
-                Some(Dependency {
-                    name: dep.to_string(),
-                    version_constraint: None,
-                })
-            }
-        })
-        .collect()
-}
-
-/// Parse version constraint like ">= 1.0"
-fn parse_version_constraint(s: &str) -> Option<VersionConstraint> {
-    let s = s.trim();
-
-    let (op, version) = if let Some(v) = s.strip_prefix(">=") {
-        (ConstraintOp::Ge, v)
-    } else if let Some(v) = s.strip_prefix("<=") {
-        (ConstraintOp::Le, v)
-    } else if let Some(v) = s.strip_prefix(">>") {
-        (ConstraintOp::Gt, v)
-    } else if let Some(v) = s.strip_prefix("<<") {
-        (ConstraintOp::Lt, v)
-    } else if let Some(v) = s.strip_prefix('=') {
-        (ConstraintOp::Eq, v)
-    } else {
-        return None;
-    };
-
-    Some(VersionConstraint {
-        operator: op,
-        version: version.trim().to_string(),
-    })
-}
-
 impl From<AptPackage> for PackageInfo {
     fn from(apt: AptPackage) -> Self {
         let mut deps = apt.depends;
@@ -259,7 +603,10 @@ impl From<AptPackage> for PackageInfo {
             provides: apt.provides,
             replaces: apt.replaces,
             files: Vec::new(),
+            file_entries: Vec::new(),
             checksum: apt.sha256,
+            scripts: Vec::new(),
+            triggers: Vec::new(),
         }
     }
 }
@@ -280,9 +627,57 @@ impl AptRepository {
         }
     }
 
-    /// Add a repository source
+    /// Add a repository source, unless it is already configured
     pub fn add_source(&mut self, source: AptSource) {
-        self.sources.push(source);
+        if !self.sources.contains(&source) {
+            self.sources.push(source);
+        }
+    }
+
+    /// Configured sources
+    pub fn sources(&self) -> &[AptSource] {
+        &self.sources
+    }
+
+    /// Import the sources of an existing Debian/Ubuntu system from its APT
+    /// directory (`/etc/apt`): `sources.list` and the `.list` and `.sources`
+    /// files of `sources.list.d`, returning how many were added
+    pub fn import_sources(&mut self, apt_dir: &Path) -> Result<usize, PkgError> {
+        let mut files = vec![apt_dir.join("sources.list")];
+        match std::fs::read_dir(apt_dir.join("sources.list.d")) {
+            Ok(entries) => {
+                let mut listed = Vec::new();
+                for entry in entries {
+                    listed.push(entry.map_err(PkgError::IoError)?.path());
+                }
+                listed.sort();
+                files.extend(listed);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(PkgError::IoError(e)),
+        }
+
+        let before = self.sources.len();
+        for file in files {
+            let deb822 = match file.extension().and_then(|e| e.to_str()) {
+                Some("sources") => true,
+                Some("list") => false,
+                _ => continue,
+            };
+            let content = match std::fs::read_to_string(&file) {
+                Ok(content) => content,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(PkgError::IoError(e)),
+            };
+            let sources = match deb822 {
+                true => parse_deb822(&content),
+                false => content.lines().filter_map(AptSource::parse).collect(),
+            };
+            for source in sources {
+                self.add_source(source);
+            }
+        }
+        Ok(self.sources.len() - before)
     }
 
     /// Add default Debian sources
@@ -297,6 +692,7 @@ impl AptRepository {
                 "non-free".to_string(),
             ],
             architectures: vec!["amd64".to_string()],
+            ..AptSource::default()
         });
     }
 
@@ -312,6 +708,7 @@ impl AptRepository {
                 "multiverse".to_string(),
             ],
             architectures: vec!["amd64".to_string()],
+            ..AptSource::default()
         });
     }
 
@@ -329,6 +726,7 @@ impl AptRepository {
             distribution: release.to_string(),
             components: vec!["main".to_string()],
             architectures: vec!["amd64".to_string()],
+            ..AptSource::default()
         });
 
         // Pop!_OS proprietary repository (NVIDIA drivers, Steam, etc.)
@@ -338,6 +736,7 @@ impl AptRepository {
             distribution: release.to_string(),
             components: vec!["main".to_string()],
             architectures: vec!["amd64".to_string()],
+            ..AptSource::default()
         });
 
         // Pop!_OS CUDA repository (for machine learning/AI)
@@ -347,6 +746,7 @@ impl AptRepository {
             distribution: release.to_string(),
             components: vec!["main".to_string()],
             architectures: vec!["amd64".to_string()],
+            ..AptSource::default()
         });
 
         // Also add Ubuntu base (Pop!_OS is based on Ubuntu)
@@ -362,18 +762,28 @@ impl AptRepository {
     }
 
     /// Sync all sources
+    ///
+    /// Every index is checked against the source's Release file; a mismatch
+    /// fails the whole sync and keeps the previous package lists. Only
+    /// `allow-insecure=yes` sources may lack a Release file. Release files
+    /// are not authenticated, see the module documentation.
     pub fn sync(&mut self) -> Result<(), PkgError> {
-        for source in &self.sources {
-            for component in &source.components {
-                for arch in &source.architectures {
-                    let url = source.packages_gz_url(component, arch);
-                    // TODO: Download and decompress Packages.gz
-                    // let content = download(&url)?;
-                    // let packages = parse_packages(&content);
-                    // self.packages.extend(...);
+        let mut packages: HashMap<String, Vec<AptPackage>> = HashMap::new();
+
+        // deb-src sources index source packages, not binaries
+        for source in self.sources.iter().filter(|s| s.source_type == "deb") {
+            let release = fetch_release(source)?;
+            let shared = Arc::new(source.clone());
+            for path in source.packages_paths() {
+                let content = fetch_packages(source, release.as_ref(), &path)?;
+                for mut pkg in parse_packages(&content) {
+                    pkg.source = Some(shared.clone());
+                    packages.entry(pkg.package.clone()).or_default().push(pkg);
                 }
             }
         }
+
+        self.packages = packages;
         Ok(())
     }
 
@@ -395,15 +805,123 @@ impl AptRepository {
         results
     }
 
-    /// Get a specific package
+    /// Get the highest version of a package across all sources
     pub fn get(&self, name: &str) -> Option<&AptPackage> {
-        self.packages.get(name)?.last()
+        self.packages
+            .get(name)?
+            .iter()
+            .max_by(|a, b| deb::compare_versions(&a.version, &b.version))
+    }
+
+    /// Every synced package version
+    pub fn packages(&self) -> impl Iterator<Item = &AptPackage> {
+        self.packages.values().flatten()
+    }
+
+    /// Get download URL for a package, from the source it was synced from
+    pub fn get_download_url(&self, pkg: &AptPackage) -> Option<String> {
+        let source = pkg.source.as_ref()?;
+        // Relative to the repository root, also for flat repositories
+        Some(format!(
+            "{}/{}",
+            source.uri.trim_end_matches('/'),
+            pkg.filename.trim_start_matches("./")
+        ))
+    }
+}
+
+/// Fetch and check the InRelease file of a source, or its Release file
+///
+/// An `allow-insecure=yes` source without either is used unverified.
+fn fetch_release(source: &AptSource) -> Result<Option<Release>, PkgError> {
+    let data = match repository::fetch(&source.dist_url("InRelease"))
+        .or_else(|_| repository::fetch(&source.dist_url("Release")))
+    {
+        Ok(data) => data,
+        Err(_) if source.allow_insecure => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let release = parse_release(&String::from_utf8_lossy(&data))?;
+
+    // Flat repositories often leave Suite and Codename out
+    if !source.is_flat()
+        && release.suite != source.distribution
+        && release.codename != source.distribution
+    {
+        eprintln!(
+            "warning: {} is {} ({}), expected {}",
+            source.dist_url("Release"),
+            release.suite,
+            release.codename,
+            source.distribution
+        );
     }
+    release.check_valid()?;
+    Ok(Some(release))
+}
+
+/// Fetch, verify and decompress the smallest Packages variant the Release
+/// file lists, or without one the smallest variant available
+fn fetch_packages(
+    source: &AptSource,
+    release: Option<&Release>,
+    plain: &str,
+) -> Result<String, PkgError> {
+    let mut last_error = None;
 
-    /// Get download URL for a package
-    pub fn get_download_url(&self, source: &AptSource, pkg: &AptPackage) -> String {
-        format!("{}/{}", source.uri, pkg.filename)
+    for suffix in INDEX_SUFFIXES {
+        let path = format!("{}{}", plain, suffix);
+        let Some(release) = release else {
+            match repository::fetch(&source.dist_url(&path)) {
+                Ok(data) => return decompress(&path, suffix, &data),
+                Err(e) => {
+                    last_error = Some(e);
+                    continue;
+                }
+            }
+        };
+        let Some(entry) = release.sha256.get(&path) else {
+            continue;
+        };
+
+        // Mirrors may lag behind by-hash; the plain name is still checked
+        let by_hash = match path.rsplit_once('/') {
+            Some((dir, _)) => format!("{}/by-hash/SHA256/{}", dir, entry.sha256),
+            None => format!("by-hash/SHA256/{}", entry.sha256),
+        };
+        let fetched = match release.acquire_by_hash {
+            true => repository::fetch(&source.dist_url(&by_hash))
+                .or_else(|_| repository::fetch(&source.dist_url(&path))),
+            false => repository::fetch(&source.dist_url(&path)),
+        };
+        let data = match fetched {
+            Ok(data) => data,
+            Err(e) => {
+                last_error = Some(e);
+                continue;
+            }
+        };
+        release.verify(&path, &data)?;
+        return decompress(&path, suffix, &data);
     }
+
+    Err(last_error.unwrap_or_else(|| {
+        PkgError::DownloadError(format!(
+            "{}: not listed in the Release file",
+            source.dist_url(plain)
+        ))
+    }))
+}
+
+/// Decompress an index by the suffix it was fetched with
+fn decompress(path: &str, suffix: &str, data: &[u8]) -> Result<String, PkgError> {
+    let compression = Compression::from_extension(suffix).unwrap_or(Compression::None);
+    let mut content = Vec::new();
+    compression
+        .decoder(data)?
+        .read_to_end(&mut content)
+        .map_err(|e| PkgError::ParseError(format!("{}: {}", path, e)))?;
+    Ok(String::from_utf8_lossy(&content).into_owned())
 }
 
 impl Default for AptRepository {
@@ -419,12 +937,248 @@ pub fn create_apt_repo(
     distribution: &str,
     components: &[&str],
 ) -> Repository {
+    // Flat repositories keep their indexes next to the packages
+    let url = match distribution.ends_with('/') {
+        true => format!("{}/{}", uri, distribution.trim_start_matches("./")),
+        false => format!("{}/dists/{}", uri, distribution),
+    };
     Repository {
         name: name.to_string(),
-        url: format!("{}/dists/{}", uri, distribution),
+        url,
         format: PackageFormat::Deb,
         enabled: true,
         gpg_key: None,
         priority: 100,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write `dists/<suite>` with a gzipped Packages index and a Release
+    /// file listing it
+    fn write_suite(repo: &Path, suite: &str, packages: &str) {
+        use std::io::Write;
+
+        let dir = repo.join("dists").join(suite);
+        std::fs::create_dir_all(dir.join("main/binary-amd64")).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(packages.as_bytes()).unwrap();
+        let gz = encoder.finish().unwrap();
+        std::fs::write(dir.join("main/binary-amd64/Packages.gz"), &gz).unwrap();
+
+        let release = format!(
+            "Suite: {}\nCodename: {}\nSHA256:\n {:x} {} main/binary-amd64/Packages.gz\n",
+            suite,
+            suite,
+            Sha256::digest(&gz),
+            gz.len()
+        );
+        std::fs::write(dir.join("Release"), release).unwrap();
+    }
+
+    #[test]
+    fn sync_reads_file_repositories_and_get_picks_the_highest_version() {
+        let repo = std::env::temp_dir().join(format!("rpm-next-apt-{}", std::process::id()));
+        write_suite(
+            &repo,
+            "bookworm",
+            "Package: libc6\nVersion: 2.36-9+deb12u4\nFilename: pool/libc6_new.deb\n",
+        );
+        write_suite(
+            &repo,
+            "bookworm-updates",
+            "Package: libc6\nVersion: 2.36-9\nFilename: pool/libc6_old.deb\n\n\
+             Package: tzdata\nVersion: 2024a-0+deb12u1\n",
+        );
+
+        let mut apt = AptRepository::new();
+        for suite in ["bookworm", "bookworm-updates"] {
+            let line = format!("deb file://{} {} main", repo.display(), suite);
+            apt.add_source(AptSource::parse(&line).unwrap());
+        }
+        let synced = apt.sync();
+        std::fs::remove_dir_all(&repo).unwrap();
+        synced.unwrap();
+
+        let libc = apt.get("libc6").unwrap();
+        assert_eq!(libc.version, "2.36-9+deb12u4");
+        assert_eq!(libc.source.as_ref().unwrap().distribution, "bookworm");
+        assert_eq!(
+            apt.get_download_url(libc).unwrap(),
+            format!("file://{}/pool/libc6_new.deb", repo.display())
+        );
+        assert!(apt.get("tzdata").is_some());
+    }
+
+    const IN_RELEASE: &str = "-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA512
+
+Origin: Debian
+Suite: stable
+Codename: bookworm
+Date: Sat, 10 Jun 2023 08:55:32 UTC
+Architectures: amd64 arm64
+Components: main contrib
+Acquire-By-Hash: yes
+MD5Sum:
+ d41d8cd98f00b204e9800998ecf8427e 0 main/binary-amd64/Packages
+SHA256:
+ E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855 0 main/binary-amd64/Packages
+ 2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae 3 main/binary-amd64/Packages.gz
+- -----not armor
+-----BEGIN PGP SIGNATURE-----
+
+iQIzBAEBCgAdFiEE
+-----END PGP SIGNATURE-----
+";
+
+    #[test]
+    fn clearsigned_release_is_parsed_with_its_sha256_table() {
+        let release = parse_release(IN_RELEASE).unwrap();
+        assert_eq!(release.origin, "Debian");
+        assert_eq!(release.suite, "stable");
+        assert_eq!(release.codename, "bookworm");
+        assert_eq!(release.architectures, ["amd64", "arm64"]);
+        assert_eq!(release.components, ["main", "contrib"]);
+        assert!(release.acquire_by_hash);
+        assert_eq!(release.sha256.len(), 2);
+        assert_eq!(
+            release.sha256["main/binary-amd64/Packages"],
+            ReleaseEntry {
+                sha256: "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+                    .to_string(),
+                size: 0,
+            }
+        );
+        assert_eq!(release.sha256["main/binary-amd64/Packages.gz"].size, 3);
+    }
+
+    #[test]
+    fn release_verify_rejects_a_mismatched_index() {
+        let release = parse_release(IN_RELEASE).unwrap();
+        // sha256("foo")
+        assert!(
+            release
+                .verify("main/binary-amd64/Packages.gz", b"foo")
+                .is_ok()
+        );
+        assert!(
+            release
+                .verify("main/binary-amd64/Packages.gz", b"bar")
+                .is_err()
+        );
+        assert!(release.verify("main/binary-amd64/Packages", b"x").is_err());
+        assert!(release.verify("main/binary-arm64/Packages", b"").is_err());
+    }
+
+    #[test]
+    fn release_dates_parse_to_unix_time() {
+        assert_eq!(parse_date("Thu, 01 Jan 1970 00:00:00 UTC"), Some(0));
+        assert_eq!(
+            parse_date("Sat, 10 Jun 2023 08:55:32 UTC"),
+            Some(1_686_387_332)
+        );
+        assert_eq!(
+            parse_date("Sat, 10 Jun 2023 10:55:32 +0200"),
+            Some(1_686_387_332)
+        );
+        assert_eq!(parse_date("29 Feb 2024 00:00:00 GMT"), Some(1_709_164_800));
+        assert_eq!(parse_date("Sat, 10 Foo 2023 08:55:32 UTC"), None);
+        assert_eq!(parse_date("Sat, 10 Jun 2023"), None);
+    }
+
+    #[test]
+    fn one_line_options_are_parsed() {
+        let source = AptSource::parse(
+            "deb [arch=amd64,arm64 signed-by=/usr/share/keyrings/x.gpg lang=de] \
+             http://deb.example.org/debian bookworm main contrib # comment",
+        )
+        .unwrap();
+        assert_eq!(source.source_type, "deb");
+        assert_eq!(source.architectures, ["amd64", "arm64"]);
+        assert_eq!(
+            source.signed_by.as_deref(),
+            Some("/usr/share/keyrings/x.gpg")
+        );
+        assert_eq!(source.languages, ["de"]);
+        assert_eq!(source.uri, "http://deb.example.org/debian");
+        assert_eq!(source.distribution, "bookworm");
+        assert_eq!(source.components, ["main", "contrib"]);
+    }
+
+    #[test]
+    fn list_options_can_add_and_remove_values() {
+        let source =
+            AptSource::parse("deb [arch+=i386,arm64 arch-=amd64] http://x bookworm main").unwrap();
+        assert_eq!(source.architectures, ["i386", "arm64"]);
+    }
+
+    #[test]
+    fn malformed_option_skips_only_itself() {
+        let source = AptSource::parse("deb [trusted arch=arm64] http://x bookworm main").unwrap();
+        assert!(!source.trusted);
+        assert_eq!(source.architectures, ["arm64"]);
+        assert_eq!(source.components, ["main"]);
+    }
+
+    #[test]
+    fn deb822_stanzas_expand_types_uris_and_suites() {
+        let sources = parse_deb822(
+            "Types: deb deb-src
+URIs: http://a.example/debian http://b.example/debian
+Suites: bookworm bookworm-updates
+Components: main
+Architectures: arm64
+
+# Disabled stanza
+Types: deb
+URIs: http://c.example/debian
+Suites: bookworm
+Components: main
+Enabled: no
+",
+        );
+        assert_eq!(sources.len(), 8);
+        assert!(sources.iter().all(|s| s.architectures == ["arm64"]));
+        assert!(sources.iter().all(|s| !s.uri.starts_with("http://c.")));
+        let lines: Vec<String> = sources.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            lines[..4],
+            [
+                "deb [arch=arm64] http://a.example/debian bookworm main",
+                "deb [arch=arm64] http://a.example/debian bookworm-updates main",
+                "deb [arch=arm64] http://b.example/debian bookworm main",
+                "deb [arch=arm64] http://b.example/debian bookworm-updates main",
+            ]
+        );
+        assert_eq!(sources[4].source_type, "deb-src");
+    }
+
+    #[test]
+    fn deb822_inline_key_keeps_its_empty_lines() {
+        let sources = parse_deb822(
+            "Types: deb
+URIs: http://deb.example.org/debian
+Suites: bookworm
+Components: main
+Signed-By:
+ -----BEGIN PGP PUBLIC KEY BLOCK-----
+ .
+ mDMEZAbc
+ =abcd
+ -----END PGP PUBLIC KEY BLOCK-----
+",
+        );
+        assert_eq!(sources.len(), 1);
+        assert_eq!(
+            sources[0].signed_by.as_deref(),
+            Some(
+                "-----BEGIN PGP PUBLIC KEY BLOCK-----\n\nmDMEZAbc\n=abcd\n\
+                 -----END PGP PUBLIC KEY BLOCK-----"
+            )
+        );
+        assert_eq!(sources[0].components, ["main"]);
+    }
+}
@@ -0,0 +1,203 @@
+//! Archive Helpers
+//!
+//! Compression handling shared by the format adapters.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+use tar::EntryType;
+
+use crate::{FileEntry, PkgError};
+
+/// Stream compression used by archive members and payloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Xz,
+    Lzma,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// Detect compression from a file or member name (e.g. `data.tar.xz`)
+    pub fn from_extension(name: &str) -> Option<Self> {
+        let ext = match name.rsplit_once('.') {
+            Some((_, ext)) => ext,
+            None => return Some(Self::None),
+        };
+
+        match ext {
+            "gz" => Some(Self::Gzip),
+            "xz" => Some(Self::Xz),
+            "lzma" => Some(Self::Lzma),
+            "zst" => Some(Self::Zstd),
+            "bz2" => Some(Self::Bzip2),
+            "tar" => Some(Self::None),
+            _ => None,
+        }
+    }
+
+    /// Detect compression from the leading bytes of a stream
+    ///
+    /// Uncompressed data is only recognised when it is a tar archive.
+    pub fn from_magic(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::Gzip)
+        } else if data.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Self::Xz)
+        } else if data.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Self::Zstd)
+        } else if data.starts_with(b"BZh") {
+            Some(Self::Bzip2)
+        } else if data.get(257..262) == Some(b"ustar") {
+            Some(Self::None)
+        } else {
+            None
+        }
+    }
+
+    /// Map an RPM PAYLOADCOMPRESSOR value (gzip when the tag is absent)
+    pub fn from_rpm_compressor(name: Option<&str>) -> Option<Self> {
+        match name.unwrap_or("gzip") {
+            "gzip" => Some(Self::Gzip),
+            "bzip2" => Some(Self::Bzip2),
+            "xz" => Some(Self::Xz),
+            "lzma" => Some(Self::Lzma),
+            "zstd" => Some(Self::Zstd),
+            "identity" => Some(Self::None),
+            _ => None,
+        }
+    }
+
+    /// Wrap a reader with the matching decoder
+    pub fn decoder<'a, R: Read + 'a>(self, reader: R) -> Result<Box<dyn Read + 'a>, PkgError> {
+        Ok(match self {
+            Self::None => Box::new(reader),
+            Self::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+            Self::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+            Self::Lzma => {
+                let stream = xz2::stream::Stream::new_lzma_decoder(u64::MAX)
+                    .map_err(|e| PkgError::ExtractionError(format!("lzma: {}", e)))?;
+                Box::new(xz2::read::XzDecoder::new_stream(reader, stream))
+            }
+            Self::Zstd => {
+                Box::new(zstd::stream::read::Decoder::new(reader).map_err(PkgError::IoError)?)
+            }
+            Self::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+        })
+    }
+}
+
+/// SHA-256 of a file as lowercase hex
+pub fn sha256_file(path: &Path) -> Result<String, PkgError> {
+    let mut file = std::fs::File::open(path).map_err(PkgError::IoError)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).map_err(PkgError::IoError)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Describe every member of a tar stream, hashing regular file contents
+///
+/// Hard links take the size and digest of the file they point to.
+pub fn tar_entries<R: Read>(reader: R) -> Result<Vec<FileEntry>, PkgError> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries: Vec<FileEntry> = Vec::new();
+    let mut by_path: HashMap<String, usize> = HashMap::new();
+
+    for entry in archive.entries().map_err(PkgError::IoError)? {
+        let mut entry = entry.map_err(PkgError::IoError)?;
+        let mut file = tar_file_entry(&entry)?;
+        if file.path.is_empty() {
+            continue;
+        }
+
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Continuous => {
+                let mut hasher = Sha256::new();
+                std::io::copy(&mut entry, &mut hasher).map_err(PkgError::IoError)?;
+                file.digest = format!("{:x}", hasher.finalize());
+            }
+            EntryType::Link => {
+                let target = entry
+                    .link_name()
+                    .map_err(PkgError::IoError)?
+                    .map(|p| normalize_path(&p.to_string_lossy()))
+                    .unwrap_or_default();
+                if let Some(&index) = by_path.get(&target) {
+                    file.size = entries[index].size;
+                    file.digest = entries[index].digest.clone();
+                }
+            }
+            _ => {}
+        }
+
+        by_path.insert(file.path.clone(), entries.len());
+        entries.push(file);
+    }
+
+    Ok(entries)
+}
+
+/// Describe a tar member as a [`FileEntry`] (digest left empty)
+pub fn tar_file_entry<R: Read>(entry: &tar::Entry<R>) -> Result<FileEntry, PkgError> {
+    let header = entry.header();
+    let file_type = match header.entry_type() {
+        EntryType::Directory => 0o040000,
+        EntryType::Symlink => 0o120000,
+        EntryType::Char => 0o020000,
+        EntryType::Block => 0o060000,
+        EntryType::Fifo => 0o010000,
+        _ => 0o100000,
+    };
+
+    let path = normalize_path(&entry.path().map_err(PkgError::IoError)?.to_string_lossy());
+    let link_target = if header.entry_type() == EntryType::Symlink {
+        entry
+            .link_name()
+            .map_err(PkgError::IoError)?
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    } else {
+        String::new()
+    };
+    let owner = |name: Option<&str>, id: u64| match name {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => id.to_string(),
+    };
+
+    Ok(FileEntry {
+        path: path.trim_end_matches('/').to_string(),
+        mode: file_type | (header.mode().map_err(PkgError::IoError)? & 0o7777),
+        size: entry.size(),
+        mtime: header.mtime().map_err(PkgError::IoError)?,
+        digest: String::new(),
+        user: owner(
+            header.username().ok().flatten(),
+            header.uid().map_err(PkgError::IoError)?,
+        ),
+        group: owner(
+            header.groupname().ok().flatten(),
+            header.gid().map_err(PkgError::IoError)?,
+        ),
+        link_target,
+        flags: 0,
+        xattrs: Vec::new(),
+    })
+}
+
+/// Turn an archive path like `./usr/bin/foo` into `/usr/bin/foo`
+pub fn normalize_path(path: &str) -> String {
+    format!("/{}", path.trim_start_matches("./").trim_start_matches('/'))
+}
+
+/// Remove a file or symlink that is about to be replaced
+pub fn remove_existing(path: &Path) -> Result<(), PkgError> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if !meta.is_dir() => std::fs::remove_file(path).map_err(PkgError::IoError),
+        _ => Ok(()),
+    }
+}
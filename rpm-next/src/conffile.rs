@@ -0,0 +1,100 @@
+//! Configuration Files
+//!
+//! Files flagged `CONFIG` (RPM `%config`, Debian conffiles, pacman `backup=`)
+//! may have been edited by the administrator. Their digest at install time
+//! is recorded, and on upgrade an edited file is kept: the packaged copy is
+//! written beside it (`.rpmnew`, `.pacnew`, `.dpkg-dist`), or for plain RPM
+//! `%config` the edited copy is saved aside (`.rpmsave`) before replacing it.
+
+use std::path::Path;
+
+use crate::archive;
+use crate::extract::{self, Extractor};
+use crate::{FileEntry, PackageDatabase, PackageFormat, PackageInfo, PkgError, file_flags};
+
+/// Suffix of the packaged copy of a config file that was kept
+pub fn new_suffix(format: PackageFormat) -> &'static str {
+    match format {
+        PackageFormat::Rpm => ".rpmnew",
+        PackageFormat::Deb => ".dpkg-dist",
+        PackageFormat::Apk => ".apk-new",
+        _ => ".pacnew",
+    }
+}
+
+/// Suffix of an edited config file moved aside for the packaged copy
+pub fn save_suffix(format: PackageFormat) -> &'static str {
+    match format {
+        PackageFormat::Rpm => ".rpmsave",
+        PackageFormat::Deb => ".dpkg-old",
+        PackageFormat::Apk => ".apk-old",
+        _ => ".pacsave",
+    }
+}
+
+/// Decide how each config file of `pkg` is installed over what is on disk
+///
+/// `database` still describes the installed version, if any.
+pub fn plan(
+    root: &Path,
+    pkg: &PackageInfo,
+    database: &PackageDatabase,
+    extractor: &mut Extractor,
+) -> Result<(), PkgError> {
+    for new in pkg.file_entries.iter().filter(|e| is_config(e)) {
+        let path = extract::confined_path(root, &new.path)?;
+        let Some(current) = file_digest(&path)? else {
+            continue;
+        };
+
+        let old = database
+            .file_entry(&new.path)
+            .filter(|old| !old.digest.is_empty());
+        if old.is_some_and(|old| old.digest == current) || new.digest == current {
+            // Unedited, or already the packaged copy
+            continue;
+        }
+        if old.is_some_and(|old| old.digest == new.digest) {
+            // The package did not change it; keep the edits silently
+            extractor.preserve(&new.path, None);
+            continue;
+        }
+
+        if new.flags & file_flags::NOREPLACE != 0 {
+            let sidecar = format!("{}{}", new.path, new_suffix(pkg.format));
+            eprintln!("warning: {} created as {}", new.path, sidecar);
+            extractor.preserve(&new.path, Some(sidecar));
+        } else {
+            let saved = format!("{}{}", new.path, save_suffix(pkg.format));
+            eprintln!("warning: {} saved as {}", new.path, saved);
+            std::fs::rename(&path, extract::confined_path(root, &saved)?)
+                .map_err(PkgError::IoError)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the installed copy of a config file differs from its recorded
+/// digest; a missing file is not modified
+pub fn is_modified(root: &Path, entry: &FileEntry) -> Result<bool, PkgError> {
+    if entry.digest.is_empty() {
+        return Ok(false);
+    }
+    let path = extract::confined_path(root, &entry.path)?;
+    Ok(file_digest(&path)?.is_some_and(|digest| digest != entry.digest))
+}
+
+pub fn is_config(entry: &FileEntry) -> bool {
+    entry.flags & file_flags::CONFIG != 0 && entry.mode & 0o170000 == 0o100000
+}
+
+/// SHA-256 of a regular file, `None` when there is none at `path`
+fn file_digest(path: &Path) -> Result<Option<String>, PkgError> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_file() => archive::sha256_file(path).map(Some),
+        Ok(_) => Ok(None),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(PkgError::IoError(e)),
+    }
+}
@@ -0,0 +1,272 @@
+//! CPIO Archive Reader
+//!
+//! Streaming reader for the SVR4 "newc" (070701) and "crc" (070702)
+//! formats used by RPM payloads.
+
+use std::io::Read;
+
+use crate::PkgError;
+
+/// Name of the end-of-archive marker entry
+const TRAILER: &str = "TRAILER!!!";
+
+/// Longest entry name we accept
+const MAX_NAME_SIZE: usize = 4096;
+
+/// File type bits of `mode`
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+
+/// CPIO entry header
+#[derive(Debug, Clone)]
+pub struct CpioHeader {
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u32,
+    pub mtime: u32,
+    pub file_size: u32,
+    pub dev_major: u32,
+    pub dev_minor: u32,
+    pub name: String,
+}
+
+impl CpioHeader {
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & S_IFMT == S_IFLNK
+    }
+}
+
+/// Streaming cpio reader
+///
+/// Call [`CpioReader::next_entry`] to advance; the reader itself yields the
+/// data of the current entry.
+pub struct CpioReader<R: Read> {
+    inner: R,
+    /// Bytes consumed so far, for 4-byte alignment
+    pos: u64,
+    /// Unread data of the current entry
+    remaining: u64,
+    /// Padding after the current entry's data
+    padding: u64,
+}
+
+impl<R: Read> CpioReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            pos: 0,
+            remaining: 0,
+            padding: 0,
+        }
+    }
+
+    /// Advance to the next entry, skipping unread data of the current one
+    ///
+    /// Returns `None` at the trailer.
+    pub fn next_entry(&mut self) -> Result<Option<CpioHeader>, PkgError> {
+        let skip = self.remaining + self.padding;
+        self.skip(skip)?;
+        self.remaining = 0;
+        self.padding = 0;
+
+        let mut raw = [0u8; 110];
+        self.read_exact_counted(&mut raw)?;
+
+        if &raw[0..6] != b"070701" && &raw[0..6] != b"070702" {
+            return Err(PkgError::ExtractionError(
+                "Invalid cpio header magic".to_string(),
+            ));
+        }
+
+        let mut fields = [0u32; 13];
+        for (i, field) in fields.iter_mut().enumerate() {
+            let hex = &raw[6 + i * 8..14 + i * 8];
+            *field = std::str::from_utf8(hex)
+                .ok()
+                .and_then(|s| u32::from_str_radix(s, 16).ok())
+                .ok_or_else(|| {
+                    PkgError::ExtractionError("Invalid cpio header field".to_string())
+                })?;
+        }
+
+        let name_size = fields[11] as usize;
+        if name_size == 0 || name_size > MAX_NAME_SIZE {
+            return Err(PkgError::ExtractionError(format!(
+                "Invalid cpio name size {}",
+                name_size
+            )));
+        }
+
+        let mut name = vec![0u8; name_size];
+        self.read_exact_counted(&mut name)?;
+        let name_pad = pad4(self.pos);
+        self.skip(name_pad)?;
+
+        // Drop the trailing NUL
+        name.pop();
+        let name = String::from_utf8_lossy(&name).into_owned();
+
+        if name == TRAILER {
+            return Ok(None);
+        }
+
+        let header = CpioHeader {
+            ino: fields[0],
+            mode: fields[1],
+            uid: fields[2],
+            gid: fields[3],
+            nlink: fields[4],
+            mtime: fields[5],
+            file_size: fields[6],
+            dev_major: fields[7],
+            dev_minor: fields[8],
+            name,
+        };
+
+        self.remaining = header.file_size as u64;
+        self.padding = pad4(self.pos + self.remaining);
+
+        Ok(Some(header))
+    }
+
+    fn read_exact_counted(&mut self, buf: &mut [u8]) -> Result<(), PkgError> {
+        self.inner
+            .read_exact(buf)
+            .map_err(|_| PkgError::ExtractionError("Truncated cpio archive".to_string()))?;
+        self.pos += buf.len() as u64;
+        Ok(())
+    }
+
+    fn skip(&mut self, n: u64) -> Result<(), PkgError> {
+        let skipped = std::io::copy(&mut (&mut self.inner).take(n), &mut std::io::sink())
+            .map_err(PkgError::IoError)?;
+        if skipped != n {
+            return Err(PkgError::ExtractionError(
+                "Truncated cpio archive".to_string(),
+            ));
+        }
+        self.pos += n;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for CpioReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let max = (buf.len() as u64).min(self.remaining) as usize;
+        if max == 0 {
+            return Ok(0);
+        }
+        let n = self.inner.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "truncated cpio entry",
+            ));
+        }
+        self.remaining -= n as u64;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+/// Bytes needed to reach the next 4-byte boundary
+fn pad4(pos: u64) -> u64 {
+    (4 - pos % 4) % 4
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// One newc member; `ino` ties hard links together
+    pub(crate) fn newc(
+        name: &str,
+        ino: u32,
+        mode: u32,
+        nlink: u32,
+        mtime: u32,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let fields = [
+            ino,
+            mode,
+            1000,
+            1000,
+            nlink,
+            mtime,
+            data.len() as u32,
+            0,
+            0,
+            0,
+            0,
+            name.len() as u32 + 1,
+            0,
+        ];
+        let mut out = b"070701".to_vec();
+        for field in fields {
+            out.extend_from_slice(format!("{:08x}", field).as_bytes());
+        }
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+        out.resize(out.len().next_multiple_of(4), 0);
+        out.extend_from_slice(data);
+        out.resize(out.len().next_multiple_of(4), 0);
+        out
+    }
+
+    pub(crate) fn trailer() -> Vec<u8> {
+        newc("TRAILER!!!", 0, 0, 1, 0, b"")
+    }
+
+    #[test]
+    fn entries_are_aligned_and_unread_data_is_skipped() {
+        let archive = [
+            newc("odd", 1, S_IFREG | 0o644, 1, 0, b"xyz"),
+            newc("partly-read", 2, S_IFREG | 0o644, 1, 0, b"12345"),
+            newc("dir", 3, S_IFDIR | 0o755, 2, 0, b""),
+            trailer(),
+        ]
+        .concat();
+        let mut cpio = CpioReader::new(&archive[..]);
+
+        let odd = cpio.next_entry().unwrap().unwrap();
+        assert_eq!((odd.name.as_str(), odd.file_size), ("odd", 3));
+        let mut data = String::new();
+        cpio.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "xyz");
+
+        let partly_read = cpio.next_entry().unwrap().unwrap();
+        assert_eq!(partly_read.name, "partly-read");
+        let mut start = [0u8; 2];
+        cpio.read_exact(&mut start).unwrap();
+        assert_eq!(&start, b"12");
+
+        let dir = cpio.next_entry().unwrap().unwrap();
+        assert!(dir.is_dir() && !dir.is_file());
+        assert!(cpio.next_entry().unwrap().is_none());
+    }
+
+    #[test]
+    fn bad_magic_and_truncation_are_errors() {
+        let archive = newc("file", 1, S_IFREG | 0o644, 1, 0, b"data");
+        let mut cpio = CpioReader::new(&archive[..archive.len() - 2]);
+        cpio.next_entry().unwrap();
+        assert!(cpio.next_entry().is_err());
+
+        let mut bad = archive.clone();
+        bad[5] = b'7';
+        assert!(CpioReader::new(&bad[..]).next_entry().is_err());
+    }
+}
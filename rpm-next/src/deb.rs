@@ -2,10 +2,43 @@
 //!
 //! Handles Debian package format (.deb)
 
-use std::io::{Read, Seek};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::io::{BufReader, Read};
 use std::path::Path;
 
-use crate::{Dependency, PackageFormat, PackageInfo, PkgError};
+use crate::archive::{self, Compression};
+use crate::extract::{self, Extractor};
+use crate::hooks::Trigger;
+use crate::reader::PackageReader;
+use crate::scriptlet::{ScriptPhase, Scriptlet};
+use crate::{
+    ConstraintOp, Dependency, PackageFormat, PackageInfo, PkgError, VersionConstraint, file_flags,
+};
+
+/// ar global header
+pub const AR_MAGIC: &[u8; 8] = b"!<arch>\n";
+
+/// Maintainer scripts in control.tar and when they run
+const MAINTAINER_SCRIPTS: [(&str, ScriptPhase); 4] = [
+    ("preinst", ScriptPhase::PreInstall),
+    ("postinst", ScriptPhase::PostInstall),
+    ("prerm", ScriptPhase::PreRemove),
+    ("postrm", ScriptPhase::PostRemove),
+];
+
+/// Reader for .deb packages
+pub struct DebReader;
+
+impl PackageReader for DebReader {
+    fn read(&self, path: &Path) -> Result<PackageInfo, PkgError> {
+        parse_deb(path)
+    }
+
+    fn extract(&self, path: &Path, extractor: &mut Extractor) -> Result<(), PkgError> {
+        extract_deb(path, extractor)
+    }
+}
 
 /// Parse a .deb package
 pub fn parse_deb(path: &Path) -> Result<PackageInfo, PkgError> {
@@ -14,34 +47,266 @@ pub fn parse_deb(path: &Path) -> Result<PackageInfo, PkgError> {
     // - control.tar.gz (metadata)
     // - data.tar.* (files)
 
-    let file = std::fs::File::open(path).map_err(|e| PkgError::IoError(e))?;
+    let file = std::fs::File::open(path).map_err(PkgError::IoError)?;
+    let size = file.metadata().map_err(PkgError::IoError)?.len();
 
-    // For now, return a stub - real implementation would parse ar archive
-    let name = path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("unknown")
-        .to_string();
+    let mut format_version = None;
+    let mut control_files = BTreeMap::new();
+    let mut entries = Vec::new();
 
-    Ok(PackageInfo {
-        name,
-        version: "1.0.0".to_string(),
-        release: 1,
-        arch: "amd64".to_string(),
-        format: PackageFormat::Deb,
-        description: String::new(),
-        maintainer: String::new(),
-        license: String::new(),
-        homepage: String::new(),
-        size: 0,
-        installed_size: 0,
-        dependencies: Vec::new(),
-        conflicts: Vec::new(),
-        provides: Vec::new(),
-        replaces: Vec::new(),
-        files: Vec::new(),
-        checksum: String::new(),
-    })
+    read_ar(BufReader::new(file), |name, member| {
+        if name == "debian-binary" {
+            let mut version = String::new();
+            member
+                .read_to_string(&mut version)
+                .map_err(PkgError::IoError)?;
+            format_version = Some(version.trim().to_string());
+        } else if format_version.is_none() {
+            return Err(PkgError::ExtractionError(
+                "debian-binary must be the first member".to_string(),
+            ));
+        } else if name.starts_with("control.tar") {
+            control_files = read_control_tar(name, member)?;
+        } else if name.starts_with("data.tar") {
+            entries = archive::tar_entries(decoder_for(name, member)?)?;
+        }
+        Ok(())
+    })?;
+
+    match format_version.as_deref() {
+        Some(v) if v.starts_with("2.") => {}
+        Some(v) => {
+            return Err(PkgError::ExtractionError(format!(
+                "Unsupported deb format version {}",
+                v
+            )));
+        }
+        None => {
+            return Err(PkgError::ExtractionError(
+                "Missing debian-binary member".to_string(),
+            ));
+        }
+    }
+
+    let control = control_files
+        .get("control")
+        .ok_or_else(|| PkgError::ExtractionError("Missing control file".to_string()))?;
+    let control = String::from_utf8_lossy(control);
+
+    let mut info = parse_control(&control)?;
+    info.size = size;
+    info.files = entries
+        .iter()
+        .filter(|entry| !entry.is_dir())
+        .map(|entry| entry.path.clone())
+        .collect();
+    if let Some(conffiles) = control_files.get("conffiles") {
+        let conffiles = String::from_utf8_lossy(conffiles);
+        let conffiles = parse_conffiles(&conffiles);
+        for entry in &mut entries {
+            if conffiles.contains(&entry.path.as_str()) {
+                entry.flags |= file_flags::CONFIG | file_flags::NOREPLACE;
+            }
+        }
+    }
+    info.file_entries = entries;
+    info.scripts = MAINTAINER_SCRIPTS
+        .iter()
+        .filter_map(|&(name, phase)| {
+            let body = control_files.get(name)?;
+            Some(Scriptlet::from_script(
+                phase,
+                String::from_utf8_lossy(body).into_owned(),
+            ))
+        })
+        .collect();
+    if let Some(triggers) = control_files.get("triggers") {
+        info.triggers = parse_triggers(&String::from_utf8_lossy(triggers));
+    }
+    info.checksum = archive::sha256_file(path)?;
+
+    Ok(info)
+}
+
+/// Extract the data.tar.* member of a .deb package
+pub fn extract_deb(path: &Path, extractor: &mut Extractor) -> Result<(), PkgError> {
+    let file = std::fs::File::open(path).map_err(PkgError::IoError)?;
+
+    let mut found = false;
+    read_ar(BufReader::new(file), |name, member| {
+        if !name.starts_with("data.tar") {
+            return Ok(());
+        }
+        found = true;
+
+        extract::unpack_tar(extractor, decoder_for(name, member)?, |_| false)
+    })?;
+
+    if !found {
+        return Err(PkgError::ExtractionError(
+            "Missing data.tar member".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Iterate over the members of an ar archive
+///
+/// The callback receives each member's name and a reader limited to its
+/// contents; unread data is skipped before moving on.
+pub fn read_ar<R: Read>(
+    mut reader: R,
+    mut f: impl FnMut(&str, &mut dyn Read) -> Result<(), PkgError>,
+) -> Result<(), PkgError> {
+    let mut magic = [0u8; 8];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|_| PkgError::ExtractionError("Not an ar archive".to_string()))?;
+    if &magic != AR_MAGIC {
+        return Err(PkgError::ExtractionError("Not an ar archive".to_string()));
+    }
+
+    loop {
+        let mut header = [0u8; 60];
+        match read_full(&mut reader, &mut header).map_err(PkgError::IoError)? {
+            0 => break,
+            60 => {}
+            _ => {
+                return Err(PkgError::ExtractionError(
+                    "Truncated ar member header".to_string(),
+                ));
+            }
+        }
+
+        if &header[58..60] != b"`\n" {
+            return Err(PkgError::ExtractionError(
+                "Invalid ar member header".to_string(),
+            ));
+        }
+
+        let field = |range: std::ops::Range<usize>| {
+            String::from_utf8_lossy(&header[range]).trim().to_string()
+        };
+        let size: u64 = field(48..58)
+            .parse()
+            .map_err(|_| PkgError::ExtractionError("Invalid ar member size".to_string()))?;
+        let raw_name = field(0..16);
+
+        let mut member = (&mut reader).take(size);
+
+        // BSD ar stores long names ("#1/<len>") in front of the data
+        let name = if let Some(len) = raw_name.strip_prefix("#1/") {
+            let len: u64 = len
+                .parse()
+                .map_err(|_| PkgError::ExtractionError("Invalid ar member name".to_string()))?;
+            let mut buf = Vec::new();
+            (&mut member)
+                .take(len)
+                .read_to_end(&mut buf)
+                .map_err(PkgError::IoError)?;
+            String::from_utf8_lossy(&buf)
+                .trim_end_matches('\0')
+                .to_string()
+        } else {
+            raw_name.trim_end_matches('/').to_string()
+        };
+
+        f(&name, &mut member)?;
+
+        std::io::copy(&mut member, &mut std::io::sink()).map_err(PkgError::IoError)?;
+        if member.limit() > 0 {
+            return Err(PkgError::ExtractionError(format!(
+                "Truncated ar member {}",
+                name
+            )));
+        }
+
+        // Members are padded to an even offset
+        if size % 2 == 1 {
+            let mut pad = [0u8; 1];
+            read_full(&mut reader, &mut pad).map_err(PkgError::IoError)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the regular files of control.tar.* into memory, keyed by name
+fn read_control_tar(
+    member_name: &str,
+    member: &mut dyn Read,
+) -> Result<BTreeMap<String, Vec<u8>>, PkgError> {
+    let mut archive = tar::Archive::new(decoder_for(member_name, member)?);
+    let mut files = BTreeMap::new();
+
+    for entry in archive.entries().map_err(PkgError::IoError)? {
+        let mut entry = entry.map_err(PkgError::IoError)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let name =
+            archive::normalize_path(&entry.path().map_err(PkgError::IoError)?.to_string_lossy());
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).map_err(PkgError::IoError)?;
+        files.insert(name.trim_start_matches('/').to_string(), content);
+    }
+
+    Ok(files)
+}
+
+/// Paths listed in a conffiles control file
+///
+/// Lines may carry a flag before the path (`remove-on-upgrade /etc/foo`).
+fn parse_conffiles(content: &str) -> Vec<&str> {
+    content
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .collect()
+}
+
+/// Directives of a triggers control file
+///
+/// The `-await`/`-noawait` variants are treated alike, since triggers run
+/// once the whole transaction is done.
+fn parse_triggers(content: &str) -> Vec<Trigger> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let directive = words.next()?;
+            let name = words.next()?.to_string();
+            let interest = match directive.split('-').next()? {
+                "interest" => true,
+                "activate" => false,
+                _ => return None,
+            };
+            Some(Trigger { interest, name })
+        })
+        .collect()
+}
+
+fn decoder_for<'a>(
+    member_name: &str,
+    member: &'a mut dyn Read,
+) -> Result<Box<dyn Read + 'a>, PkgError> {
+    Compression::from_extension(member_name)
+        .ok_or_else(|| {
+            PkgError::ExtractionError(format!("Unsupported compression for {}", member_name))
+        })?
+        .decoder(member)
+}
+
+/// Read until the buffer is full or EOF, returning the bytes read
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
 }
 
 /// Parse control file content
@@ -63,10 +328,30 @@ pub fn parse_control(content: &str) -> Result<PackageInfo, PkgError> {
         provides: Vec::new(),
         replaces: Vec::new(),
         files: Vec::new(),
+        file_entries: Vec::new(),
         checksum: String::new(),
+        scripts: Vec::new(),
+        triggers: Vec::new(),
     };
 
+    let mut pre_depends = Vec::new();
+    let mut in_description = false;
+
     for line in content.lines() {
+        // Continuation lines of the extended description
+        if line.starts_with([' ', '\t']) {
+            if in_description {
+                let line = line.trim();
+                info.description.push('\n');
+                if line != "." {
+                    info.description.push_str(line);
+                }
+            }
+            continue;
+        }
+
+        in_description = false;
+
         if let Some((key, value)) = line.split_once(':') {
             let key = key.trim();
             let value = value.trim();
@@ -75,7 +360,10 @@ pub fn parse_control(content: &str) -> Result<PackageInfo, PkgError> {
                 "Package" => info.name = value.to_string(),
                 "Version" => info.version = value.to_string(),
                 "Architecture" => info.arch = value.to_string(),
-                "Description" => info.description = value.to_string(),
+                "Description" => {
+                    info.description = value.to_string();
+                    in_description = true;
+                }
                 "Maintainer" => info.maintainer = value.to_string(),
                 "Homepage" => info.homepage = value.to_string(),
                 "Installed-Size" => {
@@ -84,6 +372,9 @@ pub fn parse_control(content: &str) -> Result<PackageInfo, PkgError> {
                 "Depends" => {
                     info.dependencies = parse_depends(value);
                 }
+                "Pre-Depends" => {
+                    pre_depends = parse_depends(value);
+                }
                 "Conflicts" => {
                     info.conflicts = value.split(',').map(|s| s.trim().to_string()).collect();
                 }
@@ -98,31 +389,305 @@ pub fn parse_control(content: &str) -> Result<PackageInfo, PkgError> {
         }
     }
 
+    // Same ordering as the APT index conversion
+    info.dependencies.extend(pre_depends);
+
     Ok(info)
 }
 
-/// Parse dependency string
-fn parse_depends(deps: &str) -> Vec<Dependency> {
+/// Parse a Debian relationship field (Depends, Pre-Depends, Build-Depends, ...)
+///
+/// Handles version constraints, `:any` qualifiers, architecture restrictions,
+/// build profiles and `|` alternatives.
+pub fn parse_depends(deps: &str) -> Vec<Dependency> {
     deps.split(',')
-        .filter_map(|dep| {
-            let dep = dep.trim();
-            // Handle alternatives (|) by taking first option
-            let dep = dep.split('|').next()?.trim();
-
-            // Parse version constraint
-            if let Some(idx) = dep.find('(') {
-                let name = dep[..idx].trim().to_string();
-                // TODO: Parse version constraint
-                Some(Dependency {
-                    name,
-                    version_constraint: None,
-                })
-            } else {
-                Some(Dependency {
-                    name: dep.to_string(),
-                    version_constraint: None,
-                })
-            }
+        .filter_map(|group| {
+            let mut options = group.split('|').filter_map(parse_relation);
+            let mut dep = options.next()?;
+            dep.alternatives = options.collect();
+            Some(dep)
         })
         .collect()
 }
+
+/// Parse a single relation like `libc6:any (>= 2.34) [amd64] <!nocheck>`
+fn parse_relation(relation: &str) -> Option<Dependency> {
+    let relation = relation.trim();
+    let name_end = relation
+        .find(|c: char| c.is_whitespace() || c == '(' || c == '[' || c == '<')
+        .unwrap_or(relation.len());
+    let (name, mut rest) = relation.split_at(name_end);
+
+    // Remove :any / :native / :<arch> qualifier
+    let name = name.split(':').next()?;
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut dep = Dependency::new(name, None);
+
+    loop {
+        rest = rest.trim_start();
+        if let Some(r) = rest.strip_prefix('(') {
+            let (constraint, r) = r.split_once(')')?;
+            dep.version_constraint = parse_version_constraint(constraint);
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let (archs, r) = r.split_once(']')?;
+            dep.arch_restrictions = archs.split_whitespace().map(String::from).collect();
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('<') {
+            let (profiles, r) = r.split_once('>')?;
+            dep.build_profiles
+                .push(profiles.split_whitespace().map(String::from).collect());
+            rest = r;
+        } else {
+            break;
+        }
+    }
+
+    Some(dep)
+}
+
+/// Parse version constraint like ">= 1.0"
+pub fn parse_version_constraint(s: &str) -> Option<VersionConstraint> {
+    let s = s.trim();
+
+    // `<` and `>` are deprecated spellings of `<=` and `>=`
+    let (op, version) = if let Some(v) = s.strip_prefix(">=") {
+        (ConstraintOp::Ge, v)
+    } else if let Some(v) = s.strip_prefix("<=") {
+        (ConstraintOp::Le, v)
+    } else if let Some(v) = s.strip_prefix(">>") {
+        (ConstraintOp::Gt, v)
+    } else if let Some(v) = s.strip_prefix("<<") {
+        (ConstraintOp::Lt, v)
+    } else if let Some(v) = s.strip_prefix('=') {
+        (ConstraintOp::Eq, v)
+    } else if let Some(v) = s.strip_prefix('>') {
+        (ConstraintOp::Ge, v)
+    } else if let Some(v) = s.strip_prefix('<') {
+        (ConstraintOp::Le, v)
+    } else {
+        return None;
+    };
+
+    let version = version.trim();
+    if version.is_empty() {
+        return None;
+    }
+
+    Some(VersionConstraint {
+        operator: op,
+        version: version.to_string(),
+    })
+}
+
+/// Compare two Debian versions (`[epoch:]upstream[-revision]`) the way
+/// dpkg does, with `~` sorting before everything, even the end
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn split(v: &str) -> (u64, &str, &str) {
+        let (epoch, rest) = match v.split_once(':') {
+            Some((epoch, rest)) => (epoch.parse().unwrap_or(0), rest),
+            None => (0, v),
+        };
+        let (upstream, revision) = rest.rsplit_once('-').unwrap_or((rest, ""));
+        (epoch, upstream, revision)
+    }
+
+    let (epoch_a, upstream_a, revision_a) = split(a);
+    let (epoch_b, upstream_b, revision_b) = split(b);
+    epoch_a
+        .cmp(&epoch_b)
+        .then_with(|| compare_fragment(upstream_a, upstream_b))
+        .then_with(|| compare_fragment(revision_a, revision_b))
+}
+
+/// dpkg's `verrevcmp`: alternating non-digit and digit runs, letters before
+/// other characters and numeric runs compared by value
+fn compare_fragment(a: &str, b: &str) -> Ordering {
+    let order = |c: Option<u8>| -> i32 {
+        match c {
+            None => 0,
+            Some(b'~') => -1,
+            Some(c) if c.is_ascii_digit() => 0,
+            Some(c) if c.is_ascii_alphabetic() => c as i32,
+            Some(c) => c as i32 + 256,
+        }
+    };
+    let digit = |s: &[u8], i: usize| s.get(i).is_some_and(u8::is_ascii_digit);
+
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        while (i < a.len() && !digit(a, i)) || (j < b.len() && !digit(b, j)) {
+            let (ac, bc) = (order(a.get(i).copied()), order(b.get(j).copied()));
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+            i += 1;
+            j += 1;
+        }
+
+        while a.get(i) == Some(&b'0') {
+            i += 1;
+        }
+        while b.get(j) == Some(&b'0') {
+            j += 1;
+        }
+        let mut first_diff = Ordering::Equal;
+        while digit(a, i) && digit(b, j) {
+            if first_diff == Ordering::Equal {
+                first_diff = a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+        if digit(a, i) {
+            return Ordering::Greater;
+        }
+        if digit(b, j) {
+            return Ordering::Less;
+        }
+        if first_diff != Ordering::Equal {
+            return first_diff;
+        }
+    }
+    Ordering::Equal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relationships_print_in_debian_syntax() {
+        let deps = parse_depends("foo (<< 2.0), bar (>> 1) | baz (<= 3), qux (= 4) [amd64]");
+        let printed: Vec<String> = deps.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            printed,
+            [
+                "foo (<< 2.0)",
+                "bar (>> 1) | baz (<= 3)",
+                "qux (= 4) [amd64]"
+            ]
+        );
+    }
+
+    #[test]
+    fn versions_compare_like_dpkg() {
+        for (lower, higher) in [
+            ("1.0", "1.1"),
+            ("1.9", "1.10"),
+            ("1.0~rc1", "1.0"),
+            ("1.0", "1.0a"),
+            ("1.0-1", "1.0-2"),
+            ("1.0-9", "1.0-10"),
+            ("2.0", "1:0.1"),
+            ("1.0+b1", "1.0.1"),
+            ("2.36-9", "2.36-9+deb12u4"),
+        ] {
+            assert_eq!(
+                compare_versions(lower, higher),
+                Ordering::Less,
+                "{lower} < {higher}"
+            );
+            assert_eq!(compare_versions(higher, lower), Ordering::Greater);
+        }
+        assert_eq!(compare_versions("1.01", "1.1"), Ordering::Equal);
+    }
+
+    /// An ar member with a raw 16-byte name field, padded to an even size
+    fn ar_member(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut member = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            name,
+            0,
+            0,
+            0,
+            644,
+            data.len()
+        )
+        .into_bytes();
+        member.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            member.push(b'\n');
+        }
+        member
+    }
+
+    fn ar_members(archive: &[u8]) -> Result<Vec<(String, Vec<u8>)>, PkgError> {
+        let mut members = Vec::new();
+        read_ar(archive, |name, member| {
+            let mut data = Vec::new();
+            member.read_to_end(&mut data).map_err(PkgError::IoError)?;
+            members.push((name.to_string(), data));
+            Ok(())
+        })?;
+        Ok(members)
+    }
+
+    #[test]
+    fn ar_members_after_an_odd_sized_one_skip_its_padding() {
+        let mut archive = AR_MAGIC.to_vec();
+        archive.extend(ar_member("debian-binary/", b"2.0"));
+        archive.extend(ar_member("data.tar/", b"data"));
+
+        let members = ar_members(&archive).unwrap();
+        assert_eq!(
+            members,
+            [
+                ("debian-binary".to_string(), b"2.0".to_vec()),
+                ("data.tar".to_string(), b"data".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn bsd_ar_names_are_read_from_the_member_data() {
+        // The name is NUL-padded and counts towards the member size
+        let mut archive = AR_MAGIC.to_vec();
+        archive.extend(ar_member("#1/20", b"control.tar.gz\0\0\0\0\0\0ctl"));
+        let members = ar_members(&archive).unwrap();
+        assert_eq!(members, [("control.tar.gz".to_string(), b"ctl".to_vec())]);
+
+        let mut archive = AR_MAGIC.to_vec();
+        archive.extend(ar_member("#1/x", b""));
+        assert!(matches!(
+            ar_members(&archive),
+            Err(PkgError::ExtractionError(e)) if e == "Invalid ar member name"
+        ));
+    }
+
+    #[test]
+    fn truncated_ar_members_are_errors() {
+        let mut archive = AR_MAGIC.to_vec();
+        archive.extend(ar_member("data.tar/", b"data"));
+        archive.truncate(archive.len() - 2);
+        assert!(ar_members(&archive).is_err());
+        assert!(ar_members(b"!<arch>").is_err());
+    }
+
+    #[test]
+    fn control_fields_fill_package_info() {
+        let control = "Package: foo\n\
+                       Version: 1.0-1\n\
+                       Architecture: amd64\n\
+                       Installed-Size: 2\n\
+                       Pre-Depends: dpkg (>= 1.19)\n\
+                       Depends: libc6, bar | baz\n\
+                       Provides: foo-api, foo-bin\n\
+                       Description: short\n \
+                       first line\n \
+                       .\n \
+                       second line\n";
+        let info = parse_control(control).unwrap();
+        assert_eq!(info.name, "foo");
+        assert_eq!(info.version, "1.0-1");
+        assert_eq!(info.installed_size, 2048);
+        assert_eq!(info.provides, ["foo-api", "foo-bin"]);
+        assert_eq!(info.description, "short\nfirst line\n\nsecond line");
+        let deps: Vec<String> = info.dependencies.iter().map(|d| d.to_string()).collect();
+        assert_eq!(deps, ["libc6", "bar | baz", "dpkg (>= 1.19)"]);
+    }
+}
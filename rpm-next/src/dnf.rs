@@ -6,6 +6,7 @@
 use std::collections::HashMap;
 use std::io::Read;
 
+use crate::rpm;
 use crate::{
     ConstraintOp, Dependency, PackageFormat, PackageInfo, PkgError, Repository, VersionConstraint,
 };
@@ -87,12 +88,7 @@ impl RpmRequire {
                 "LE" => ConstraintOp::Le,
                 "GT" => ConstraintOp::Gt,
                 "GE" => ConstraintOp::Ge,
-                _ => {
-                    return Dependency {
-                        name: self.name.clone(),
-                        version_constraint: None,
-                    }
-                }
+                _ => return rpm::dependency(self.name.clone(), None),
             };
             Some(VersionConstraint {
                 operator: op,
@@ -102,10 +98,7 @@ impl RpmRequire {
             None
         };
 
-        Dependency {
-            name: self.name.clone(),
-            version_constraint: constraint,
-        }
+        rpm::dependency(self.name.clone(), constraint)
     }
 }
 
@@ -116,6 +109,8 @@ pub fn parse_primary_xml(content: &str) -> Vec<DnfPackage> {
     let mut current = DnfPackage::default();
     let mut in_package = false;
     let mut current_tag = String::new();
+    // The relation list (`provides`, `requires`, ...) of `<rpm:entry>` lines
+    let mut relations: Option<&str> = None;
 
     // Very simplified XML parsing - production would use proper parser
     // This handles the basic structure only
@@ -131,8 +126,26 @@ pub fn parse_primary_xml(content: &str) -> Vec<DnfPackage> {
             }
             in_package = false;
         } else if in_package {
-            // Extract simple tags
-            if let Some(name) = extract_tag_value(line, "name") {
+            // Relation entries and files, then simple tags
+            if let Some(list) = line
+                .strip_prefix("<rpm:")
+                .and_then(|rest| rest.strip_suffix('>'))
+                .filter(|list| matches!(*list, "provides" | "requires" | "conflicts" | "obsoletes"))
+            {
+                relations = Some(list);
+            } else if line.starts_with("</rpm:") {
+                relations = None;
+            } else if line.starts_with("<rpm:entry ") {
+                if let Some(list) = relations {
+                    add_relation(&mut current, list, line);
+                }
+            } else if line.starts_with("<file") {
+                if extract_attribute(line, "type").as_deref() != Some("dir")
+                    && let Some(file) = extract_tag_content(line)
+                {
+                    current.files.push(file);
+                }
+            } else if let Some(name) = extract_tag_value(line, "name") {
                 current.name = name;
             } else if let Some(arch) = extract_tag_value(line, "arch") {
                 current.arch = arch;
@@ -179,6 +192,45 @@ pub fn parse_primary_xml(content: &str) -> Vec<DnfPackage> {
     packages
 }
 
+/// Add an `<rpm:entry>` line to one of the package's relation lists
+fn add_relation(pkg: &mut DnfPackage, list: &str, line: &str) {
+    let Some(name) = extract_attribute(line, "name") else {
+        return;
+    };
+    let epoch = extract_attribute(line, "epoch").and_then(|e| e.parse().ok());
+    let ver = extract_attribute(line, "ver");
+    let rel = extract_attribute(line, "rel");
+
+    match list {
+        // rpmlib() features are provided by rpm itself
+        "requires" if name.starts_with("rpmlib(") => {}
+        "requires" => pkg.requires.push(RpmRequire {
+            name,
+            flags: extract_attribute(line, "flags"),
+            epoch,
+            ver,
+            rel,
+            pre: extract_attribute(line, "pre").as_deref() == Some("1"),
+        }),
+        "provides" => pkg.provides.push(match ver {
+            Some(ver) => {
+                let epoch = epoch.filter(|e| *e > 0).map(|e| format!("{}:", e));
+                let rel = rel.map(|r| format!("-{}", r));
+                format!(
+                    "{} = {}{}{}",
+                    name,
+                    epoch.unwrap_or_default(),
+                    ver,
+                    rel.unwrap_or_default()
+                )
+            }
+            None => name,
+        }),
+        "conflicts" => pkg.conflicts.push(name),
+        _ => pkg.obsoletes.push(name),
+    }
+}
+
 fn extract_tag_value(line: &str, tag: &str) -> Option<String> {
     let open = format!("<{}>", tag);
     let close = format!("</{}>", tag);
@@ -245,7 +297,10 @@ impl From<DnfPackage> for PackageInfo {
             provides: dnf.provides,
             replaces: dnf.obsoletes,
             files: dnf.files,
+            file_entries: Vec::new(),
             checksum: dnf.checksum,
+            scripts: Vec::new(),
+            triggers: Vec::new(),
         }
     }
 }
@@ -296,6 +351,11 @@ impl DnfRepository {
         self.packages.get(name)?.last()
     }
 
+    /// Every synced package version
+    pub fn packages(&self) -> impl Iterator<Item = &DnfPackage> {
+        self.packages.values().flatten()
+    }
+
     /// Get download URL for a package
     pub fn get_download_url(&self, pkg: &DnfPackage) -> String {
         format!("{}/{}", self.base_url, pkg.location_href)
@@ -319,3 +379,53 @@ pub fn create_dnf_repo(name: &str, base_url: &str) -> Repository {
         priority: 90,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primary_xml_lists_relations_and_files() {
+        let xml = r#"<package type="rpm">
+  <name>bash</name>
+  <arch>x86_64</arch>
+  <version epoch="0" ver="5.2.26" rel="1.fc40"/>
+  <format>
+    <rpm:provides>
+      <rpm:entry name="bash" flags="EQ" epoch="0" ver="5.2.26" rel="1.fc40"/>
+      <rpm:entry name="config(bash)" flags="EQ" epoch="1" ver="5.2.26" rel="1.fc40"/>
+      <rpm:entry name="/bin/sh"/>
+    </rpm:provides>
+    <rpm:requires>
+      <rpm:entry name="libc.so.6()(64bit)"/>
+      <rpm:entry name="glibc" flags="GE" epoch="0" ver="2.34" pre="1"/>
+      <rpm:entry name="rpmlib(CompressedFileNames)" flags="LE" epoch="0" ver="3.0.4" rel="1"/>
+    </rpm:requires>
+    <rpm:obsoletes>
+      <rpm:entry name="bash-old"/>
+    </rpm:obsoletes>
+    <file>/usr/bin/bash</file>
+    <file type="dir">/usr/share/bash</file>
+  </format>
+</package>"#;
+        let packages = parse_primary_xml(xml);
+        let pkg = &packages[0];
+        assert_eq!(
+            pkg.provides,
+            [
+                "bash = 5.2.26-1.fc40",
+                "config(bash) = 1:5.2.26-1.fc40",
+                "/bin/sh"
+            ]
+        );
+        let requires: Vec<String> = pkg
+            .requires
+            .iter()
+            .map(|r| r.to_dependency().to_string())
+            .collect();
+        assert_eq!(requires, ["libc.so.6()(64bit)", "glibc (>= 2.34)"]);
+        assert!(pkg.requires[1].pre);
+        assert_eq!(pkg.obsoletes, ["bash-old"]);
+        assert_eq!(pkg.files, ["/usr/bin/bash"]);
+    }
+}
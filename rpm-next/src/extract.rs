@@ -0,0 +1,753 @@
+//! Safe Extraction
+//!
+//! Shared engine the format adapters use to write archive members under an
+//! install root. Member names are confined to the root: `..` components and
+//! absolute names are refused, and symlinks met on the way are resolved as
+//! if the root were `/`, so a link can never carry a write outside of it.
+//! Total size and entry count are capped to stop archive bombs.
+//!
+//! Members get the mode, owner, mtime and extended attributes recorded in the
+//! archive. Owner names are looked up in the root's own `/etc/passwd` and
+//! `/etc/group`, falling back to the archive's numeric ids.
+
+use std::collections::{HashMap, VecDeque};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use filetime::FileTime;
+use sha2::{Digest, Sha256};
+use tar::EntryType;
+
+use crate::archive;
+use crate::{FileEntry, PkgError};
+
+/// Most bytes a single package may write
+pub const MAX_EXTRACT_SIZE: u64 = 16 * 1024 * 1024 * 1024;
+
+/// Most members a single package may contain
+pub const MAX_EXTRACT_ENTRIES: u64 = 1_000_000;
+
+/// Symlinks followed while resolving one path, as in Linux's `MAXSYMLINKS`
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Pax header prefix for extended attributes, as written by GNU tar and bsdtar
+const PAX_XATTR_PREFIX: &str = "SCHILY.xattr.";
+
+/// Ownership, permissions and timestamps of an archive member
+#[derive(Debug, Clone, Default)]
+pub struct Attributes {
+    /// Permission bits, including setuid, setgid and sticky
+    pub mode: u32,
+    /// Owner name, empty when the archive only has `uid`
+    pub user: String,
+    pub uid: u32,
+    /// Group name, empty when the archive only has `gid`
+    pub group: String,
+    pub gid: u32,
+    pub mtime: u64,
+    /// Extended attributes, e.g. `security.capability`
+    pub xattrs: Vec<(String, Vec<u8>)>,
+}
+
+/// Writes archive members under a root directory
+pub struct Extractor {
+    root: PathBuf,
+    max_size: u64,
+    max_entries: u64,
+    written: u64,
+    count: u64,
+    /// User and group ids of the root's `/etc/passwd` and `/etc/group`
+    users: HashMap<String, u32>,
+    groups: HashMap<String, u32>,
+    /// Extracted members, in archive order
+    installed: Vec<FileEntry>,
+    index: HashMap<String, usize>,
+    /// Directory attributes, applied last so read-only directories can be
+    /// filled and their mtimes stick
+    dirs: Vec<(PathBuf, Attributes)>,
+    /// Files whose copy on disk is kept (path -> sidecar for the new copy)
+    preserved: HashMap<String, Option<String>>,
+}
+
+impl Extractor {
+    pub fn new(root: &Path) -> Result<Self, PkgError> {
+        std::fs::create_dir_all(root).map_err(PkgError::IoError)?;
+        let root = root.canonicalize().map_err(PkgError::IoError)?;
+        Ok(Self {
+            users: read_ids(&root.join("etc/passwd"))?,
+            groups: read_ids(&root.join("etc/group"))?,
+            root,
+            max_size: MAX_EXTRACT_SIZE,
+            max_entries: MAX_EXTRACT_ENTRIES,
+            written: 0,
+            count: 0,
+            installed: Vec::new(),
+            index: HashMap::new(),
+            dirs: Vec::new(),
+            preserved: HashMap::new(),
+        })
+    }
+
+    /// Leave the file at `path` (`/etc/foo`) untouched, writing the packaged
+    /// copy to `sidecar` instead, or nowhere
+    ///
+    /// The packaged copy is still recorded under `path`.
+    pub fn preserve(&mut self, path: &str, sidecar: Option<String>) {
+        self.preserved.insert(path.to_string(), sidecar);
+    }
+
+    /// Create a directory, with `attrs` applied when extraction finishes
+    pub fn create_dir(&mut self, name: &str, attrs: &Attributes) -> Result<PathBuf, PkgError> {
+        let parts = self.begin(name)?;
+        if parts.is_empty() {
+            // The root itself keeps its attributes
+            return Ok(self.root.clone());
+        }
+        let path = self.resolve(name, parts, true)?;
+        self.dirs.push((path.clone(), attrs.clone()));
+        self.record(name, 0o040000, 0, attrs, "");
+        Ok(path)
+    }
+
+    /// Write a regular file from `data`, replacing whatever is there unless
+    /// it is [preserved](Self::preserve)
+    pub fn write_file(
+        &mut self,
+        name: &str,
+        data: &mut dyn Read,
+        attrs: &Attributes,
+    ) -> Result<PathBuf, PkgError> {
+        let preserved = self.preserved.get(&entry_path(name)).cloned();
+        let target = match &preserved {
+            Some(Some(sidecar)) => self.target(sidecar.trim_start_matches('/'))?,
+            _ => self.target(name)?,
+        };
+
+        let out: Box<dyn Write> = if let Some(None) = preserved {
+            Box::new(std::io::sink())
+        } else {
+            archive::remove_existing(&target)?;
+            Box::new(File::create(&target).map_err(PkgError::IoError)?)
+        };
+        let mut out = HashingWriter {
+            inner: out,
+            hasher: Sha256::new(),
+        };
+
+        let remaining = self.max_size - self.written;
+        let copied =
+            std::io::copy(&mut data.take(remaining + 1), &mut out).map_err(PkgError::IoError)?;
+        if copied > remaining {
+            return Err(PkgError::ExtractionError(format!(
+                "{}: package exceeds the {} byte extraction limit",
+                name, self.max_size
+            )));
+        }
+        self.written += copied;
+        let digest = format!("{:x}", out.hasher.finalize());
+        drop(out.inner);
+
+        if !matches!(preserved, Some(None)) {
+            self.apply(&target, attrs, false)?;
+        }
+        self.record(name, 0o100000, copied, attrs, "").digest = digest;
+        Ok(target)
+    }
+
+    /// Create a symlink; its target is stored as-is and only matters when a
+    /// later member is written through it
+    pub fn symlink(
+        &mut self,
+        name: &str,
+        link: &str,
+        attrs: &Attributes,
+    ) -> Result<PathBuf, PkgError> {
+        let target = self.target(name)?;
+        archive::remove_existing(&target)?;
+        std::os::unix::fs::symlink(link, &target).map_err(PkgError::IoError)?;
+
+        self.apply(&target, attrs, true)?;
+        self.record(name, 0o120000, link.len() as u64, attrs, link);
+        Ok(target)
+    }
+
+    /// Hard link `name` to the already extracted member `source`
+    ///
+    /// The link shares the source's inode, and so its attributes.
+    pub fn hard_link(&mut self, name: &str, source: &str) -> Result<PathBuf, PkgError> {
+        let source_parts = validate(source)?;
+        let source_path = self.resolve_parent(source, source_parts, false)?;
+        let target = self.target(name)?;
+        archive::remove_existing(&target)?;
+        std::fs::hard_link(&source_path, &target).map_err(|e| {
+            PkgError::ExtractionError(format!("{}: cannot link to {}: {}", name, source, e))
+        })?;
+
+        let path = entry_path(name);
+        if let Some(&i) = self.index.get(&entry_path(source)) {
+            let entry = FileEntry {
+                path: path.clone(),
+                ..self.installed[i].clone()
+            };
+            self.insert(path, entry);
+        }
+        Ok(target)
+    }
+
+    /// Apply deferred directory attributes and return what was installed
+    pub fn finish(self) -> Result<Vec<FileEntry>, PkgError> {
+        // Deepest first, so a read-only parent is locked after its children
+        for (path, attrs) in self.dirs.iter().rev() {
+            self.apply(path, attrs, false)?;
+        }
+        Ok(self.installed)
+    }
+
+    /// Set owner, mode, extended attributes and mtime, in that order since
+    /// `chown` clears setuid bits and file capabilities
+    fn apply(&self, path: &Path, attrs: &Attributes, symlink: bool) -> Result<(), PkgError> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let uid = self.users.get(&attrs.user).copied().unwrap_or(attrs.uid);
+        let gid = self.groups.get(&attrs.group).copied().unwrap_or(attrs.gid);
+        permitted(std::os::unix::fs::lchown(path, Some(uid), Some(gid)))?;
+
+        // Symlink permissions are not meaningful
+        if !symlink {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(attrs.mode & 0o7777))
+                .map_err(PkgError::IoError)?;
+        }
+
+        for (name, value) in &attrs.xattrs {
+            permitted(xattr::set(path, name, value))?;
+        }
+
+        let mtime = FileTime::from_unix_time(attrs.mtime as i64, 0);
+        filetime::set_symlink_file_times(path, mtime, mtime).map_err(PkgError::IoError)
+    }
+
+    fn record(
+        &mut self,
+        name: &str,
+        file_type: u32,
+        size: u64,
+        attrs: &Attributes,
+        link: &str,
+    ) -> &mut FileEntry {
+        let owner = |name: &str, id: u32| {
+            if name.is_empty() {
+                id.to_string()
+            } else {
+                name.to_string()
+            }
+        };
+        let path = entry_path(name);
+        let entry = FileEntry {
+            path: path.clone(),
+            mode: file_type | (attrs.mode & 0o7777),
+            size,
+            mtime: attrs.mtime,
+            user: owner(&attrs.user, attrs.uid),
+            group: owner(&attrs.group, attrs.gid),
+            link_target: link.to_string(),
+            xattrs: attrs.xattrs.clone(),
+            ..Default::default()
+        };
+        let i = self.insert(path, entry);
+        &mut self.installed[i]
+    }
+
+    /// Add an entry; a member repeated later in the archive replaces it
+    fn insert(&mut self, path: String, entry: FileEntry) -> usize {
+        match self.index.get(&path) {
+            Some(&i) => {
+                self.installed[i] = entry;
+                i
+            }
+            None => {
+                self.index.insert(path, self.installed.len());
+                self.installed.push(entry);
+                self.installed.len() - 1
+            }
+        }
+    }
+
+    /// Host path for a new non-directory member; the last component is not
+    /// followed, so it is replaced rather than written through
+    fn target(&mut self, name: &str) -> Result<PathBuf, PkgError> {
+        let parts = self.begin(name)?;
+        self.resolve_parent(name, parts, true)
+    }
+
+    /// Count a member and split its name into components
+    fn begin(&mut self, name: &str) -> Result<Vec<OsString>, PkgError> {
+        self.count += 1;
+        if self.count > self.max_entries {
+            return Err(PkgError::ExtractionError(format!(
+                "{}: package has more than {} entries",
+                name, self.max_entries
+            )));
+        }
+        validate(name)
+    }
+
+    fn resolve_parent(
+        &self,
+        name: &str,
+        mut parts: Vec<OsString>,
+        create: bool,
+    ) -> Result<PathBuf, PkgError> {
+        let file_name = parts.pop().ok_or_else(|| {
+            PkgError::ExtractionError(format!("{}: refusing to replace the root", name))
+        })?;
+        Ok(self.resolve(name, parts, create)?.join(file_name))
+    }
+
+    fn resolve(&self, name: &str, parts: Vec<OsString>, create: bool) -> Result<PathBuf, PkgError> {
+        resolve(&self.root, name, parts, create)
+    }
+}
+
+/// Host path of an installed file (`/etc/foo`) under `root`, resolved the
+/// way [`Extractor`] writes it: symlinks in its parent directories are
+/// followed with the root as `/`, the last component is not
+pub fn confined_path(root: &Path, name: &str) -> Result<PathBuf, PkgError> {
+    let mut parts = validate(name.trim_start_matches('/'))?;
+    let Some(file_name) = parts.pop() else {
+        return Ok(root.to_path_buf());
+    };
+    Ok(resolve(root, name, parts, false)?.join(file_name))
+}
+
+/// Walk `parts` from `root`, following symlinks with the root as `/`
+///
+/// Missing directories are created when `create` is set; otherwise the rest
+/// of the path is kept as-is, since nothing can exist below it.
+fn resolve(
+    root: &Path,
+    name: &str,
+    parts: Vec<OsString>,
+    create: bool,
+) -> Result<PathBuf, PkgError> {
+    let mut resolved: Vec<OsString> = Vec::new();
+    let mut pending: VecDeque<OsString> = parts.into();
+    let mut hops = 0;
+
+    while let Some(part) = pending.pop_front() {
+        if part == "." {
+            continue;
+        }
+        if part == ".." {
+            if resolved.pop().is_none() {
+                return Err(PkgError::ExtractionError(format!(
+                    "{}: symlink escapes the root",
+                    name
+                )));
+            }
+            continue;
+        }
+
+        let mut path = root.to_path_buf();
+        path.extend(&resolved);
+        path.push(&part);
+
+        match std::fs::symlink_metadata(&path) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                hops += 1;
+                if hops > MAX_SYMLINK_HOPS {
+                    return Err(PkgError::ExtractionError(format!(
+                        "{}: too many levels of symlinks",
+                        name
+                    )));
+                }
+                let link = std::fs::read_link(&path).map_err(PkgError::IoError)?;
+                if link.is_absolute() {
+                    resolved.clear();
+                }
+                for component in link.components().rev() {
+                    match component {
+                        Component::Normal(p) => pending.push_front(p.to_os_string()),
+                        Component::ParentDir => pending.push_front("..".into()),
+                        Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+                    }
+                }
+            }
+            Ok(meta) if meta.is_dir() => resolved.push(part),
+            Ok(_) if create => {
+                return Err(PkgError::ExtractionError(format!(
+                    "{}: {} is not a directory",
+                    name,
+                    path.display()
+                )));
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound && create => {
+                std::fs::create_dir(&path).map_err(PkgError::IoError)?;
+                resolved.push(part);
+            }
+            // Nothing can exist below a file or a missing directory
+            Ok(_) => {
+                resolved.push(part);
+                resolved.extend(pending);
+                break;
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                resolved.push(part);
+                resolved.extend(pending);
+                break;
+            }
+            Err(e) => return Err(PkgError::IoError(e)),
+        }
+    }
+
+    let mut path = root.to_path_buf();
+    path.extend(&resolved);
+    Ok(path)
+}
+
+/// Writer that hashes what passes through it
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Database path of a member name (`./usr/bin/foo` -> `/usr/bin/foo`)
+fn entry_path(name: &str) -> String {
+    archive::normalize_path(name)
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Ownership and some attribute namespaces need privileges; like tar run as
+/// a regular user, carry on without them
+fn permitted(result: std::io::Result<()>) -> Result<(), PkgError> {
+    match result {
+        Err(e)
+            if matches!(
+                e.kind(),
+                ErrorKind::PermissionDenied | ErrorKind::Unsupported
+            ) =>
+        {
+            Ok(())
+        }
+        result => result.map_err(PkgError::IoError),
+    }
+}
+
+/// Read `name -> id` from a passwd or group file, empty when it is missing
+fn read_ids(path: &Path) -> Result<HashMap<String, u32>, PkgError> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(PkgError::IoError(e)),
+    };
+
+    Ok(content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((name.to_string(), id))
+        })
+        .collect())
+}
+
+/// Split a member name into components, refusing absolute and `..` names
+fn validate(name: &str) -> Result<Vec<OsString>, PkgError> {
+    let mut parts = Vec::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_os_string()),
+            Component::CurDir => {}
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(PkgError::ExtractionError(format!(
+                    "{}: absolute paths are not allowed",
+                    name
+                )));
+            }
+            Component::ParentDir => {
+                return Err(PkgError::ExtractionError(format!(
+                    "{}: `..` components are not allowed",
+                    name
+                )));
+            }
+        }
+    }
+    Ok(parts)
+}
+
+/// Extract a tar stream, skipping members for which `skip` returns true
+///
+/// `skip` receives the normalized name (`/usr/bin/foo`). Device nodes and
+/// FIFOs are not created.
+pub fn unpack_tar<R: Read>(
+    extractor: &mut Extractor,
+    reader: R,
+    skip: impl Fn(&str) -> bool,
+) -> Result<(), PkgError> {
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries().map_err(PkgError::IoError)? {
+        let mut entry = entry.map_err(PkgError::IoError)?;
+        let name = entry
+            .path()
+            .map_err(PkgError::IoError)?
+            .to_string_lossy()
+            .into_owned();
+        if skip(&archive::normalize_path(&name)) {
+            continue;
+        }
+
+        let attrs = tar_attributes(&mut entry)?;
+        let link_name = || -> Result<String, PkgError> {
+            Ok(entry
+                .link_name()
+                .map_err(PkgError::IoError)?
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_default())
+        };
+
+        match entry.header().entry_type() {
+            EntryType::Directory => {
+                extractor.create_dir(&name, &attrs)?;
+            }
+            EntryType::Symlink => {
+                let link = link_name()?;
+                extractor.symlink(&name, &link, &attrs)?;
+            }
+            EntryType::Link => {
+                let source = link_name()?;
+                extractor.hard_link(&name, &source)?;
+            }
+            EntryType::Regular | EntryType::Continuous => {
+                extractor.write_file(&name, &mut entry, &attrs)?;
+            }
+            _ => {
+                // Still counted against the entry limit
+                extractor.begin(&name)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn tar_attributes<R: Read>(entry: &mut tar::Entry<R>) -> Result<Attributes, PkgError> {
+    let mut xattrs = Vec::new();
+    if let Some(extensions) = entry.pax_extensions().map_err(PkgError::IoError)? {
+        for extension in extensions {
+            let extension = extension.map_err(PkgError::IoError)?;
+            if let Ok(key) = extension.key()
+                && let Some(name) = key.strip_prefix(PAX_XATTR_PREFIX)
+            {
+                xattrs.push((name.to_string(), extension.value_bytes().to_vec()));
+            }
+        }
+    }
+
+    let header = entry.header();
+    let name = |name: std::io::Result<Option<&str>>| name.ok().flatten().unwrap_or("").to_string();
+    Ok(Attributes {
+        mode: header.mode().map_err(PkgError::IoError)? & 0o7777,
+        user: name(header.username().map_err(std::io::Error::other)),
+        uid: header.uid().map_err(PkgError::IoError)? as u32,
+        group: name(header.groupname().map_err(std::io::Error::other)),
+        gid: header.gid().map_err(PkgError::IoError)? as u32,
+        mtime: header.mtime().map_err(PkgError::IoError)?,
+        xattrs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scratch directory holding a `root` to extract into
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rpm-next-extract-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("root")).unwrap();
+        dir
+    }
+
+    fn file(mode: u32) -> Attributes {
+        Attributes {
+            mode,
+            ..Attributes::default()
+        }
+    }
+
+    fn write(extractor: &mut Extractor, name: &str, data: &[u8]) -> Result<PathBuf, PkgError> {
+        extractor.write_file(name, &mut &data[..], &file(0o644))
+    }
+
+    #[test]
+    fn parent_and_absolute_names_are_refused() {
+        let dir = scratch("names");
+        let mut extractor = Extractor::new(&dir.join("root")).unwrap();
+
+        let parent = write(&mut extractor, "../escaped", b"x");
+        let nested = write(&mut extractor, "usr/../../escaped", b"x");
+        let absolute = write(&mut extractor, "/escaped", b"x");
+        let escaped = dir.join("escaped").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(parent, Err(PkgError::ExtractionError(_))));
+        assert!(matches!(nested, Err(PkgError::ExtractionError(_))));
+        assert!(matches!(absolute, Err(PkgError::ExtractionError(_))));
+        assert!(!escaped);
+    }
+
+    #[test]
+    fn relative_symlink_cannot_carry_a_write_out_of_the_root() {
+        let dir = scratch("relative-link");
+        let mut extractor = Extractor::new(&dir.join("root")).unwrap();
+
+        extractor.symlink("lib", "../../..", &file(0o777)).unwrap();
+        let result = write(&mut extractor, "lib/x", b"x");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(result, Err(PkgError::ExtractionError(_))));
+    }
+
+    #[test]
+    fn absolute_symlink_is_resolved_under_the_root() {
+        let dir = scratch("absolute-link");
+        let root = dir.join("root");
+        let outside = dir.join("outside");
+        let mut extractor = Extractor::new(&root).unwrap();
+
+        extractor
+            .symlink("etc", &outside.to_string_lossy(), &file(0o777))
+            .unwrap();
+        let written = write(&mut extractor, "etc/passwd", b"x").unwrap();
+        let root = root.canonicalize().unwrap();
+        let escaped = outside.join("passwd").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(written.starts_with(&root));
+        assert!(written.ends_with("outside/passwd"));
+        assert!(!escaped);
+    }
+
+    #[test]
+    fn size_limit_stops_extraction() {
+        let dir = scratch("size");
+        let mut extractor = Extractor::new(&dir.join("root")).unwrap();
+        extractor.max_size = 8;
+
+        let first = write(&mut extractor, "a", b"12345");
+        let second = write(&mut extractor, "b", b"6789");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(first.is_ok());
+        assert!(matches!(second, Err(PkgError::ExtractionError(_))));
+    }
+
+    #[test]
+    fn entry_limit_stops_extraction() {
+        let dir = scratch("entries");
+        let mut extractor = Extractor::new(&dir.join("root")).unwrap();
+        extractor.max_entries = 2;
+
+        let dir_result = extractor.create_dir("usr", &file(0o755));
+        let first = write(&mut extractor, "usr/a", b"");
+        let second = write(&mut extractor, "usr/b", b"");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(dir_result.is_ok() && first.is_ok());
+        assert!(matches!(second, Err(PkgError::ExtractionError(_))));
+    }
+
+    #[test]
+    fn hard_link_source_must_be_inside_the_root() {
+        let dir = scratch("hard-link");
+        std::fs::write(dir.join("secret"), "x").unwrap();
+        let mut extractor = Extractor::new(&dir.join("root")).unwrap();
+
+        let parent = extractor.hard_link("a", "../secret");
+        let absolute = extractor.hard_link("b", &dir.join("secret").to_string_lossy());
+        extractor.symlink("up", "../..", &file(0o777)).unwrap();
+        let through_link = extractor.hard_link("c", "up/secret");
+        let linked = ["a", "b", "c"]
+            .iter()
+            .any(|name| dir.join("root").join(name).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(parent, Err(PkgError::ExtractionError(_))));
+        assert!(matches!(absolute, Err(PkgError::ExtractionError(_))));
+        assert!(matches!(through_link, Err(PkgError::ExtractionError(_))));
+        assert!(!linked);
+    }
+
+    #[test]
+    fn tar_members_keep_mode_owner_mtime_and_hard_links() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = scratch("tar-metadata");
+        let root = dir.join("root");
+        std::fs::create_dir_all(root.join("etc")).unwrap();
+        std::fs::write(root.join("etc/passwd"), "builder:x:4321:4321::/:/bin/sh\n").unwrap();
+        std::fs::write(root.join("etc/group"), "builder:x:4321:\n").unwrap();
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o4755);
+        header.set_uid(1000);
+        header.set_gid(1000);
+        header.set_username("builder").unwrap();
+        header.set_groupname("builder").unwrap();
+        header.set_mtime(1_000_000);
+        builder
+            .append_data(&mut header, "./usr/bin/tool", &b"tool"[..])
+            .unwrap();
+        header.set_entry_type(EntryType::Link);
+        header.set_size(0);
+        builder
+            .append_link(&mut header, "./usr/bin/alias", "./usr/bin/tool")
+            .unwrap();
+        let data = builder.into_inner().unwrap();
+
+        let mut extractor = Extractor::new(&root).unwrap();
+        unpack_tar(&mut extractor, &data[..], |_| false).unwrap();
+        let entries = extractor.finish().unwrap();
+        let tool = std::fs::metadata(root.join("usr/bin/tool")).unwrap();
+        let alias = std::fs::metadata(root.join("usr/bin/alias")).unwrap();
+        // Ownership only changes when running as root
+        let privileged = std::fs::metadata(&dir).unwrap().uid() == 0;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(tool.mode() & 0o7777, 0o4755);
+        assert_eq!(tool.mtime(), 1_000_000);
+        if privileged {
+            assert_eq!((tool.uid(), tool.gid()), (4321, 4321));
+        }
+        assert_eq!(alias.ino(), tool.ino());
+        assert_eq!(entries.len(), 2);
+        for entry in &entries {
+            assert_eq!(entry.mode, 0o104755);
+            assert_eq!(
+                (entry.user.as_str(), entry.group.as_str()),
+                ("builder", "builder")
+            );
+            assert_eq!(entry.mtime, 1_000_000);
+            assert_eq!(entry.size, 4);
+        }
+    }
+}
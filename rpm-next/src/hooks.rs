@@ -0,0 +1,503 @@
+//! Transaction Hooks
+//!
+//! Work done once per transaction rather than once per package: pacman
+//! `.hook` files (`usr/share/libalpm/hooks`, plus the administrator's own
+//! directory) and Debian triggers. A transaction records what it installs,
+//! upgrades and removes in [`Changes`]; hooks matching those changes run
+//! once before or after it, and every package interested in an activated
+//! trigger has its `postinst triggered` run once afterwards.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use serde::{Deserialize, Serialize};
+
+use crate::scriptlet::{self, Invocation, ScriptPhase, ScriptPolicy};
+use crate::{PackageFormat, PackageInfo, PkgError};
+
+/// What a transaction does to a package or path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Install,
+    Upgrade,
+    Remove,
+}
+
+/// When a hook runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum When {
+    PreTransaction,
+    PostTransaction,
+}
+
+/// A Debian trigger directive from a package's `triggers` control file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trigger {
+    /// `interest` rather than `activate`
+    pub interest: bool,
+    /// Trigger name, or an absolute path for file triggers
+    pub name: String,
+}
+
+/// Packages and paths a transaction touches
+#[derive(Debug, Default)]
+pub struct Changes {
+    packages: Vec<(Operation, String)>,
+    /// Paths without the leading `/`, as hook targets are written
+    paths: Vec<(Operation, String)>,
+    /// Triggers named by `activate` directives of changed packages
+    activated: BTreeSet<String>,
+}
+
+impl Changes {
+    /// Record installing `new`, over `old` when upgrading
+    pub fn install(&mut self, new: &PackageInfo, old: Option<&PackageInfo>) {
+        let op = match old {
+            Some(_) => Operation::Upgrade,
+            None => Operation::Install,
+        };
+        self.packages.push((op, new.name.clone()));
+
+        let old_paths: BTreeSet<&str> = old.map(paths).unwrap_or_default();
+        let new_paths = paths(new);
+        for path in &new_paths {
+            let op = match old_paths.contains(path) {
+                true => Operation::Upgrade,
+                false => Operation::Install,
+            };
+            self.paths.push((op, path.to_string()));
+        }
+        for path in old_paths.difference(&new_paths) {
+            self.paths.push((Operation::Remove, path.to_string()));
+        }
+        self.activate(new);
+    }
+
+    /// Record removing `pkg`
+    pub fn remove(&mut self, pkg: &PackageInfo) {
+        self.packages.push((Operation::Remove, pkg.name.clone()));
+        for path in paths(pkg) {
+            self.paths.push((Operation::Remove, path.to_string()));
+        }
+        self.activate(pkg);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    fn activate(&mut self, pkg: &PackageInfo) {
+        for trigger in pkg.triggers.iter().filter(|t| !t.interest) {
+            self.activated.insert(trigger.name.clone());
+        }
+    }
+}
+
+/// Paths of a package, without the leading `/`
+fn paths(pkg: &PackageInfo) -> BTreeSet<&str> {
+    let paths: Vec<&str> = match pkg.file_entries.is_empty() {
+        true => pkg.files.iter().map(String::as_str).collect(),
+        false => pkg.file_entries.iter().map(|e| e.path.as_str()).collect(),
+    };
+    paths
+        .into_iter()
+        .map(|p| p.trim_start_matches('/'))
+        .collect()
+}
+
+/// A parsed `.hook` file
+#[derive(Debug, Clone)]
+pub struct Hook {
+    /// File name without `.hook`
+    pub name: String,
+    /// Where the hook was loaded from, as an absolute path inside the root;
+    /// empty for a hook not read from a file
+    pub path: String,
+    pub triggers: Vec<HookTrigger>,
+    pub description: String,
+    pub when: When,
+    /// Command and arguments
+    pub exec: Vec<String>,
+    /// A failing `PreTransaction` hook aborts the transaction
+    pub abort_on_fail: bool,
+    /// Pass the matched targets on stdin, one per line
+    pub needs_targets: bool,
+}
+
+/// A `[Trigger]` section
+#[derive(Debug, Clone, Default)]
+pub struct HookTrigger {
+    pub operations: Vec<Operation>,
+    /// `Type = Package` rather than `Path`
+    pub package: bool,
+    /// Glob patterns; a leading `!` excludes
+    pub targets: Vec<String>,
+}
+
+impl HookTrigger {
+    /// Targets of `changes` this trigger matches
+    fn matches<'a>(&self, changes: &'a Changes) -> impl Iterator<Item = &'a str> {
+        let candidates = match self.package {
+            true => &changes.packages,
+            false => &changes.paths,
+        };
+        candidates
+            .iter()
+            .filter(|(op, target)| {
+                self.operations.contains(op) && matches_targets(&self.targets, target)
+            })
+            .map(|(_, target)| target.as_str())
+    }
+}
+
+/// Whether `target` matches a pacman target list: the last pattern that
+/// matches decides, and `!` patterns exclude
+fn matches_targets(patterns: &[String], target: &str) -> bool {
+    patterns.iter().rev().find_map(|pattern| {
+        let (negated, pattern) = match pattern.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern.as_str()),
+        };
+        glob_match(pattern.as_bytes(), target.as_bytes()).then_some(!negated)
+    }) == Some(true)
+}
+
+/// `fnmatch` without flags: `*` and `?` also match `/`
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        Some((b'?', rest)) => !text.is_empty() && glob_match(rest, &text[1..]),
+        Some((b'[', rest)) => {
+            let Some((&c, text_rest)) = text.split_first() else {
+                return false;
+            };
+            match bracket(rest, c) {
+                Some((true, rest)) => glob_match(rest, text_rest),
+                Some((false, _)) => false,
+                // No closing `]`: a literal `[`
+                None => c == b'[' && glob_match(rest, text_rest),
+            }
+        }
+        Some((b'\\', [escaped, rest @ ..])) => {
+            text.first() == Some(escaped) && glob_match(rest, &text[1..])
+        }
+        Some((&p, rest)) => text.first() == Some(&p) && glob_match(rest, &text[1..]),
+    }
+}
+
+/// Match `c` against a bracket expression after its `[`, returning whether
+/// it matched and the pattern after the `]`
+fn bracket(pattern: &[u8], c: u8) -> Option<(bool, &[u8])> {
+    let (negated, mut i) = match pattern.first() {
+        Some(b'!' | b'^') => (true, 1),
+        _ => (false, 0),
+    };
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() {
+        let p = pattern[i];
+        if p == b']' && !first {
+            return Some((matched != negated, &pattern[i + 1..]));
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&b'-') && pattern.get(i + 2).is_some_and(|&e| e != b']') {
+            matched |= (p..=pattern[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= p == c;
+            i += 1;
+        }
+    }
+    None
+}
+
+/// Parse a `.hook` file
+pub fn parse_hook(name: &str, content: &str) -> Result<Hook, PkgError> {
+    let error = |message: String| PkgError::ParseError(format!("hook {}: {}", name, message));
+
+    let mut triggers: Vec<HookTrigger> = Vec::new();
+    let mut in_action = false;
+    let mut description = String::new();
+    let mut when = None;
+    let mut exec = Vec::new();
+    let mut abort_on_fail = false;
+    let mut needs_targets = false;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line {
+            "[Trigger]" => {
+                triggers.push(HookTrigger::default());
+                in_action = false;
+                continue;
+            }
+            "[Action]" => {
+                in_action = true;
+                continue;
+            }
+            _ if line.starts_with('[') => return Err(error(format!("unknown section {}", line))),
+            _ => {}
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => (line, ""),
+        };
+        if in_action {
+            match key {
+                "Description" => description = value.to_string(),
+                "When" => {
+                    when = Some(match value {
+                        "PreTransaction" => When::PreTransaction,
+                        "PostTransaction" => When::PostTransaction,
+                        _ => return Err(error(format!("invalid When {}", value))),
+                    })
+                }
+                "Exec" => exec = split_words(value),
+                "AbortOnFail" => abort_on_fail = true,
+                "NeedsTargets" => needs_targets = true,
+                // `Depends` names packages the hook needs; not checked
+                _ => {}
+            }
+            continue;
+        }
+
+        let Some(trigger) = triggers.last_mut() else {
+            return Err(error(format!("{} outside a section", key)));
+        };
+        match key {
+            "Operation" => trigger.operations.push(match value {
+                "Install" => Operation::Install,
+                "Upgrade" => Operation::Upgrade,
+                "Remove" => Operation::Remove,
+                _ => return Err(error(format!("invalid Operation {}", value))),
+            }),
+            "Type" => {
+                trigger.package = match value {
+                    "Package" => true,
+                    // `File` is the deprecated spelling of `Path`
+                    "Path" | "File" => false,
+                    _ => return Err(error(format!("invalid Type {}", value))),
+                }
+            }
+            "Target" => trigger.targets.push(value.to_string()),
+            _ => {}
+        }
+    }
+
+    let when = when.ok_or_else(|| error("missing When".to_string()))?;
+    if exec.is_empty() {
+        return Err(error("missing Exec".to_string()));
+    }
+    if triggers.is_empty() {
+        return Err(error("missing [Trigger]".to_string()));
+    }
+    Ok(Hook {
+        name: name.to_string(),
+        path: String::new(),
+        triggers,
+        description,
+        when,
+        exec,
+        abort_on_fail,
+        needs_targets,
+    })
+}
+
+/// Split an `Exec` line into words, honouring quotes and backslashes
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', q) if q != Some('\'') => {
+                word.extend(chars.next());
+                in_word = true;
+            }
+            (c, Some(q)) if c == q => quote = None,
+            (_, Some(_)) => word.push(c),
+            ('\'' | '"', None) => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (c, None) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (c, None) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Load the hooks in `dirs` (under `root`), sorted by file name
+///
+/// A hook in a later directory replaces one of the same name in an earlier
+/// one; a replacement that is empty or links to `/dev/null` disables it.
+/// Invalid hooks are reported and skipped.
+pub fn load_hooks(root: &Path, dirs: &[PathBuf]) -> Result<Vec<Hook>, PkgError> {
+    let mut files: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for dir in dirs {
+        let dir = root.join(dir.strip_prefix("/").unwrap_or(dir));
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(PkgError::IoError(e)),
+        };
+        for entry in entries {
+            let path = entry.map_err(PkgError::IoError)?.path();
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            if let Some(name) = file_name.strip_suffix(".hook") {
+                files.insert(name.to_string(), path.clone());
+            }
+        }
+    }
+
+    let mut hooks = Vec::new();
+    for (name, path) in files {
+        if std::fs::read_link(&path).is_ok_and(|link| link == Path::new("/dev/null")) {
+            continue;
+        }
+        let content = std::fs::read_to_string(&path).map_err(PkgError::IoError)?;
+        if content.trim().is_empty() {
+            continue;
+        }
+        match parse_hook(&name, &content) {
+            Ok(mut hook) => {
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+                hook.path = format!("/{}", relative.display());
+                hooks.push(hook)
+            }
+            Err(e) => eprintln!("warning: skipping {}: {:?}", path.display(), e),
+        }
+    }
+    Ok(hooks)
+}
+
+/// Run each hook matching `changes` at `when` once, chrooted into `root`
+///
+/// `policy` gives what to do with each hook. A failing `PreTransaction`
+/// hook with `AbortOnFail` aborts the transaction; other failures are
+/// reported.
+pub fn run_hooks(
+    root: &Path,
+    hooks: &[Hook],
+    when: When,
+    changes: &Changes,
+    policy: impl Fn(&Hook) -> ScriptPolicy,
+) -> Result<(), PkgError> {
+    let matched: Vec<(&Hook, BTreeSet<&str>)> = hooks
+        .iter()
+        .filter(|hook| hook.when == when)
+        .filter_map(|hook| {
+            let targets: BTreeSet<&str> = hook
+                .triggers
+                .iter()
+                .flat_map(|trigger| trigger.matches(changes))
+                .collect();
+            (!targets.is_empty()).then_some((hook, targets))
+        })
+        .collect();
+
+    for (i, (hook, targets)) in matched.iter().enumerate() {
+        let description = match hook.description.is_empty() {
+            true => &hook.name,
+            false => &hook.description,
+        };
+        match policy(hook) {
+            ScriptPolicy::Run => {}
+            ScriptPolicy::Skip => continue,
+            ScriptPolicy::Log => {
+                eprintln!("Not running hook {}: {}", hook.name, hook.exec.join(" "));
+                continue;
+            }
+        }
+        println!("({}/{}) {}", i + 1, matched.len(), description);
+
+        let mut command = scriptlet::command(root, &hook.exec[0]);
+        command.args(&hook.exec[1..]);
+        if hook.needs_targets {
+            command.stdin(Stdio::piped());
+        }
+        let status = command.spawn().and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                for target in targets {
+                    writeln!(stdin, "{}", target)?;
+                }
+            }
+            child.wait()
+        });
+
+        let error = match status {
+            Ok(status) if status.success() => continue,
+            Ok(status) => format!("hook {} failed: {}", hook.name, status),
+            Err(e) => format!("hook {} could not run: {}", hook.name, e),
+        };
+        if when == When::PreTransaction && hook.abort_on_fail {
+            return Err(PkgError::ScriptError(error));
+        }
+        eprintln!("warning: {}", error);
+    }
+    Ok(())
+}
+
+/// Run `postinst triggered` once for each installed Debian package
+/// interested in a trigger `changes` activated
+///
+/// File triggers (`interest /usr/lib/mime`) are activated by any path at
+/// or below them. `policy` gives what to do with each package's script.
+pub fn run_triggers<'a>(
+    root: &Path,
+    installed: impl Iterator<Item = &'a PackageInfo>,
+    changes: &Changes,
+    policy: impl Fn(&PackageInfo) -> ScriptPolicy,
+) -> Result<(), PkgError> {
+    for pkg in installed.filter(|p| p.format == PackageFormat::Deb) {
+        let names: Vec<&str> = pkg
+            .triggers
+            .iter()
+            .filter(|t| t.interest && is_activated(&t.name, changes))
+            .map(|t| t.name.as_str())
+            .collect();
+        if names.is_empty() {
+            continue;
+        }
+
+        let call = Invocation {
+            pkg,
+            phase: ScriptPhase::PostInstall,
+            args: vec!["triggered".to_string(), names.join(" ")],
+        };
+        scriptlet::run(root, &call, policy(pkg))?;
+    }
+    Ok(())
+}
+
+fn is_activated(name: &str, changes: &Changes) -> bool {
+    let Some(dir) = name.strip_prefix('/') else {
+        return changes.activated.contains(name);
+    };
+    let dir = dir.trim_end_matches('/');
+    changes.paths.iter().any(|(_, path)| {
+        path.strip_prefix(dir)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
@@ -37,16 +37,35 @@
 //! └─────────────────────────────────────────────────────────────────┘
 //! ```
 
-use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
+use serde::{Deserialize, Serialize};
+
+use hooks::When;
+use resolver::Resolver;
+use scriptlet::{ScriptPolicy, Stage};
+
+mod android;
+mod apk;
+mod archive;
+mod conffile;
+mod cpio;
 mod deb;
+mod extract;
+mod hooks;
+mod msi;
+mod msix;
+mod mtree;
 mod pkg;
+mod reader;
 mod repository;
 mod resolver;
 mod rpm;
+mod scriptlet;
 
 // External repository adapters
+mod alpine;
 mod apt;
 mod dnf;
 mod pacman;
@@ -54,6 +73,7 @@ mod playstore;
 mod winget;
 
 // Re-export repository types
+pub use alpine::AlpineRepository;
 pub use apt::AptRepository;
 pub use dnf::DnfRepository;
 pub use pacman::PacmanRepository;
@@ -61,7 +81,7 @@ pub use playstore::PlayStoreRepository;
 pub use winget::WingetRepository;
 
 /// Package format
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PackageFormat {
     /// Redox native (tar + zstd)
     Native,
@@ -80,7 +100,7 @@ pub enum PackageFormat {
 }
 
 /// Package metadata
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PackageInfo {
     pub name: String,
     pub version: String,
@@ -98,24 +118,207 @@ pub struct PackageInfo {
     pub provides: Vec<String>,
     pub replaces: Vec<String>,
     pub files: Vec<String>,
+    /// Per-file metadata, when the format records it
+    pub file_entries: Vec<FileEntry>,
     pub checksum: String,
+    /// Maintainer scripts
+    pub scripts: Vec<scriptlet::Scriptlet>,
+    /// Debian trigger directives
+    pub triggers: Vec<hooks::Trigger>,
+}
+
+/// Per-file metadata recorded by a package
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub path: String,
+    /// Type and permission bits (`st_mode`)
+    pub mode: u32,
+    pub size: u64,
+    pub mtime: u64,
+    /// SHA-256 of the contents, empty when unknown
+    pub digest: String,
+    pub user: String,
+    pub group: String,
+    /// Symlink target, empty for other file types
+    pub link_target: String,
+    /// See [`file_flags`]
+    pub flags: u32,
+    /// Extended attributes set on install
+    pub xattrs: Vec<(String, Vec<u8>)>,
+}
+
+impl FileEntry {
+    pub fn is_dir(&self) -> bool {
+        self.mode & 0o170000 == 0o040000
+    }
+}
+
+/// File attribute flags (values match RPM's `RPMFILE_*`)
+pub mod file_flags {
+    pub const CONFIG: u32 = 1 << 0;
+    pub const DOC: u32 = 1 << 1;
+    pub const NOREPLACE: u32 = 1 << 4;
+    pub const GHOST: u32 = 1 << 6;
+    pub const LICENSE: u32 = 1 << 7;
+}
+
+/// Difference between an installed file and its recorded metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyIssue {
+    Missing(String),
+    FileType(String),
+    Mode {
+        path: String,
+        expected: u32,
+        actual: u32,
+    },
+    Size {
+        path: String,
+        expected: u64,
+        actual: u64,
+    },
+    Digest(String),
+    LinkTarget {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl std::fmt::Display for VerifyIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(path) => write!(f, "{}: missing", path),
+            Self::FileType(path) => write!(f, "{}: file type changed", path),
+            Self::Mode {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: mode {:04o}, expected {:04o}",
+                path, actual, expected
+            ),
+            Self::Size {
+                path,
+                expected,
+                actual,
+            } => write!(f, "{}: size {}, expected {}", path, actual, expected),
+            Self::Digest(path) => write!(f, "{}: checksum mismatch", path),
+            Self::LinkTarget {
+                path,
+                expected,
+                actual,
+            } => write!(f, "{}: links to {}, expected {}", path, actual, expected),
+        }
+    }
 }
 
 /// Package dependency
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Dependency {
     pub name: String,
     pub version_constraint: Option<VersionConstraint>,
+    /// Architecture restrictions (`[amd64 !i386]`)
+    pub arch_restrictions: Vec<String>,
+    /// Build profile restrictions, one list per `<...>` group (`<!nocheck>`)
+    pub build_profiles: Vec<Vec<String>>,
+    /// Alternatives that satisfy this dependency equally (`a | b`)
+    pub alternatives: Vec<Dependency>,
+    /// Boolean expression for RPM rich dependencies; `name` then holds the
+    /// original expression text
+    pub rich: Option<Box<RichDependency>>,
+}
+
+/// RPM boolean dependency expression, e.g. `(foo if bar)`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RichDependency {
+    Atom(Dependency),
+    And(Vec<RichDependency>),
+    Or(Vec<RichDependency>),
+    /// `then if condition [else otherwise]`
+    If {
+        then: Box<RichDependency>,
+        condition: Box<RichDependency>,
+        otherwise: Option<Box<RichDependency>>,
+    },
+    /// `then unless condition [else otherwise]`
+    Unless {
+        then: Box<RichDependency>,
+        condition: Box<RichDependency>,
+        otherwise: Option<Box<RichDependency>>,
+    },
+    /// All operands must be satisfied by the same package
+    With(Vec<RichDependency>),
+    /// Left side, provided the same package does not also match the right
+    Without(Box<RichDependency>, Box<RichDependency>),
+}
+
+impl Dependency {
+    pub fn new(name: impl Into<String>, version_constraint: Option<VersionConstraint>) -> Self {
+        Self {
+            name: name.into(),
+            version_constraint,
+            arch_restrictions: Vec::new(),
+            build_profiles: Vec::new(),
+            alternatives: Vec::new(),
+            rich: None,
+        }
+    }
+
+    /// This dependency followed by its alternatives
+    pub fn choices(&self) -> impl Iterator<Item = &Dependency> {
+        std::iter::once(self).chain(self.alternatives.iter())
+    }
+}
+
+impl std::fmt::Display for Dependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, dep) in self.choices().enumerate() {
+            if i > 0 {
+                write!(f, " | ")?;
+            }
+            write!(f, "{}", dep.name)?;
+            if let Some(constraint) = &dep.version_constraint {
+                write!(
+                    f,
+                    " ({} {})",
+                    constraint.operator.debian_symbol(),
+                    constraint.version
+                )?;
+            }
+            if !dep.arch_restrictions.is_empty() {
+                write!(f, " [{}]", dep.arch_restrictions.join(" "))?;
+            }
+            for profiles in &dep.build_profiles {
+                write!(f, " <{}>", profiles.join(" "))?;
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Version constraint
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VersionConstraint {
     pub operator: ConstraintOp,
     pub version: String,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl std::fmt::Display for VersionConstraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self.operator {
+            ConstraintOp::Eq => "=",
+            ConstraintOp::Lt => "<",
+            ConstraintOp::Le => "<=",
+            ConstraintOp::Gt => ">",
+            ConstraintOp::Ge => ">=",
+        };
+        write!(f, "{} {}", op, self.version)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ConstraintOp {
     Eq, // =
     Lt, // <
@@ -124,6 +327,20 @@ pub enum ConstraintOp {
     Ge, // >=
 }
 
+impl ConstraintOp {
+    /// Spelling in Debian relationship fields, where a bare `<` or `>`
+    /// means `<=` or `>=`
+    pub fn debian_symbol(self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::Lt => "<<",
+            Self::Le => "<=",
+            Self::Gt => ">>",
+            Self::Ge => ">=",
+        }
+    }
+}
+
 /// Repository configuration
 #[derive(Debug, Clone)]
 pub struct Repository {
@@ -148,6 +365,14 @@ pub struct PkgConfig {
     pub repos: Vec<Repository>,
     /// Enable parallel downloads
     pub parallel_downloads: usize,
+    /// Whether maintainer scripts and hooks run
+    pub script_policy: ScriptPolicy,
+    /// Policy for the maintainer scripts of packages from a source, in
+    /// place of `script_policy`; `Log` or `Skip` for untrusted sources
+    pub source_script_policies: HashMap<RepositorySource, ScriptPolicy>,
+    /// Directories of pacman `.hook` files under `root`; a hook in a later
+    /// directory replaces one of the same name
+    pub hook_dirs: Vec<PathBuf>,
 }
 
 impl Default for PkgConfig {
@@ -158,16 +383,25 @@ impl Default for PkgConfig {
             db_dir: PathBuf::from("/var/lib/rpm-next"),
             repos: Vec::new(),
             parallel_downloads: 4,
+            script_policy: ScriptPolicy::default(),
+            source_script_policies: HashMap::new(),
+            hook_dirs: vec![
+                PathBuf::from("/usr/share/libalpm/hooks"),
+                PathBuf::from("/etc/rpm-next/hooks"),
+            ],
         }
     }
 }
 
 /// Installed package database
+#[derive(Serialize, Deserialize)]
 pub struct PackageDatabase {
     /// Installed packages
     packages: BTreeMap<String, PackageInfo>,
     /// File ownership (file -> package)
     files: HashMap<String, String>,
+    /// Recorded metadata of installed files (file -> entry)
+    entries: HashMap<String, FileEntry>,
 }
 
 impl PackageDatabase {
@@ -175,19 +409,33 @@ impl PackageDatabase {
         Self {
             packages: BTreeMap::new(),
             files: HashMap::new(),
+            entries: HashMap::new(),
         }
     }
 
-    /// Load database from disk
+    /// Load database from disk, empty when nothing was saved yet
     pub fn load(path: &Path) -> Result<Self, PkgError> {
-        // TODO: Load from path/installed.json
-        Ok(Self::new())
+        let file = path.join("installed.json");
+        let content = match std::fs::read(&file) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(PkgError::IoError(e)),
+        };
+        serde_json::from_slice(&content)
+            .map_err(|e| PkgError::ParseError(format!("{}: {}", file.display(), e)))
     }
 
     /// Save database to disk
+    ///
+    /// Written to a temporary file first, so an interrupted save leaves the
+    /// previous database intact.
     pub fn save(&self, path: &Path) -> Result<(), PkgError> {
-        // TODO: Save to path/installed.json
-        Ok(())
+        std::fs::create_dir_all(path).map_err(PkgError::IoError)?;
+        let content = serde_json::to_vec(self)
+            .map_err(|e| PkgError::ParseError(format!("Cannot encode database: {}", e)))?;
+        let temp = path.join("installed.json.new");
+        std::fs::write(&temp, content).map_err(PkgError::IoError)?;
+        std::fs::rename(&temp, path.join("installed.json")).map_err(PkgError::IoError)
     }
 
     /// Check if package is installed
@@ -210,19 +458,40 @@ impl PackageDatabase {
         self.files.get(path).map(|s| s.as_str())
     }
 
+    /// Get the recorded metadata of an installed file
+    pub fn file_entry(&self, path: &str) -> Option<&FileEntry> {
+        self.entries.get(path)
+    }
+
     /// Register package installation
     pub fn register(&mut self, pkg: PackageInfo) {
         for file in &pkg.files {
             self.files.insert(file.clone(), pkg.name.clone());
         }
+        for entry in &pkg.file_entries {
+            self.entries.insert(entry.path.clone(), entry.clone());
+        }
         self.packages.insert(pkg.name.clone(), pkg);
     }
 
     /// Unregister package
+    ///
+    /// Files since taken over by another package stay registered to it.
     pub fn unregister(&mut self, name: &str) -> Option<PackageInfo> {
         if let Some(pkg) = self.packages.remove(name) {
+            for entry in &pkg.file_entries {
+                if self
+                    .files
+                    .get(&entry.path)
+                    .is_none_or(|owner| owner == name)
+                {
+                    self.entries.remove(&entry.path);
+                }
+            }
             for file in &pkg.files {
-                self.files.remove(file);
+                if self.files.get(file).is_some_and(|owner| owner == name) {
+                    self.files.remove(file);
+                }
             }
             Some(pkg)
         } else {
@@ -249,6 +518,8 @@ pub struct Transaction {
     pub download_size: u64,
     /// Total installed size change
     pub size_change: i64,
+    /// Also delete configuration files of removed packages
+    pub purge: bool,
 }
 
 impl Transaction {
@@ -259,6 +530,7 @@ impl Transaction {
             upgrade: Vec::new(),
             download_size: 0,
             size_change: 0,
+            purge: false,
         }
     }
 
@@ -277,26 +549,53 @@ impl Default for Transaction {
 pub struct RpmNext {
     config: PkgConfig,
     database: PackageDatabase,
+    /// Repository indexes packages and their dependencies are taken from
+    pub repos: UnifiedRepositoryManager,
 }
 
 impl RpmNext {
     pub fn new(config: PkgConfig) -> Result<Self, PkgError> {
         let db_path = config.db_dir.clone();
-        let database = PackageDatabase::load(&db_path).unwrap_or_default();
+        let database = PackageDatabase::load(&db_path)?;
 
-        Ok(Self { config, database })
+        Ok(Self {
+            config,
+            database,
+            repos: UnifiedRepositoryManager::default(),
+        })
     }
 
     /// Install packages
     pub fn install(&mut self, names: &[&str]) -> Result<Transaction, PkgError> {
         let mut transaction = Transaction::new();
 
+        // Requests resolve within the source `find_package` picks for them,
+        // so a Debian package never depends on an Arch or Alpine one
+        let mut by_source: Vec<(RepositorySource, Vec<&str>)> = Vec::new();
         for name in names {
-            // Resolve dependencies and add to transaction
-            if let Some(pkg) = self.find_package(name)? {
-                transaction.install.push(pkg);
-            } else {
+            let Some((source, _)) = self.repos.get(name) else {
                 return Err(PkgError::PackageNotFound(name.to_string()));
+            };
+            match by_source.iter_mut().find(|(s, _)| *s == source) {
+                Some((_, names)) => names.push(name),
+                None => by_source.push((source, vec![name])),
+            }
+        }
+
+        for (source, names) in by_source {
+            let mut resolver = Resolver::new();
+            resolver.add_available(self.repos.packages(source));
+            resolver.set_installed(
+                self.database
+                    .list()
+                    .filter(|pkg| self.repos.source_of(pkg) == source)
+                    .cloned()
+                    .collect(),
+            );
+            for pkg in resolver.resolve(&names)? {
+                if !transaction.install.iter().any(|p| p.name == pkg.name) {
+                    transaction.install.push(pkg);
+                }
             }
         }
 
@@ -306,9 +605,19 @@ impl RpmNext {
         Ok(transaction)
     }
 
-    /// Remove packages
+    /// Remove packages, keeping configuration files that were edited
     pub fn remove(&mut self, names: &[&str]) -> Result<Transaction, PkgError> {
+        self.remove_packages(names, false)
+    }
+
+    /// Remove packages along with all their configuration files
+    pub fn purge(&mut self, names: &[&str]) -> Result<Transaction, PkgError> {
+        self.remove_packages(names, true)
+    }
+
+    fn remove_packages(&mut self, names: &[&str], purge: bool) -> Result<Transaction, PkgError> {
         let mut transaction = Transaction::new();
+        transaction.purge = purge;
 
         for name in names {
             if self.database.is_installed(name) {
@@ -371,10 +680,122 @@ impl RpmNext {
         self.find_package(name)
     }
 
+    /// Compare an installed package's files against their recorded metadata
+    pub fn verify(&self, name: &str) -> Result<Vec<VerifyIssue>, PkgError> {
+        let pkg = self
+            .database
+            .get(name)
+            .ok_or_else(|| PkgError::NotInstalled(name.to_string()))?;
+
+        let mut issues = Vec::new();
+        for path in pkg.file_entries.iter().map(|e| &e.path) {
+            if let Some(entry) = self.database.file_entry(path)
+                && entry.flags & file_flags::GHOST == 0
+            {
+                self.verify_entry(entry, &mut issues)?;
+            }
+        }
+        Ok(issues)
+    }
+
+    fn verify_entry(
+        &self,
+        entry: &FileEntry,
+        issues: &mut Vec<VerifyIssue>,
+    ) -> Result<(), PkgError> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = extract::confined_path(&self.config.root, &entry.path)?;
+        let meta = match std::fs::symlink_metadata(&path) {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                issues.push(VerifyIssue::Missing(entry.path.clone()));
+                return Ok(());
+            }
+            Err(e) => return Err(PkgError::IoError(e)),
+        };
+
+        let mode = meta.permissions().mode();
+        if mode & 0o170000 != entry.mode & 0o170000 {
+            issues.push(VerifyIssue::FileType(entry.path.clone()));
+            return Ok(());
+        }
+
+        if meta.file_type().is_symlink() {
+            let target = std::fs::read_link(&path).map_err(PkgError::IoError)?;
+            let target = target.to_string_lossy();
+            if !entry.link_target.is_empty() && target != entry.link_target {
+                issues.push(VerifyIssue::LinkTarget {
+                    path: entry.path.clone(),
+                    expected: entry.link_target.clone(),
+                    actual: target.into_owned(),
+                });
+            }
+            return Ok(());
+        }
+
+        if mode & 0o7777 != entry.mode & 0o7777 {
+            issues.push(VerifyIssue::Mode {
+                path: entry.path.clone(),
+                expected: entry.mode & 0o7777,
+                actual: mode & 0o7777,
+            });
+        }
+
+        if meta.is_file() {
+            if meta.len() != entry.size {
+                issues.push(VerifyIssue::Size {
+                    path: entry.path.clone(),
+                    expected: entry.size,
+                    actual: meta.len(),
+                });
+            } else if !entry.digest.is_empty() && archive::sha256_file(&path)? != entry.digest {
+                issues.push(VerifyIssue::Digest(entry.path.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reset the permission bits of an installed package's files
+    ///
+    /// Returns the number of files whose mode was changed.
+    pub fn restore_permissions(&self, name: &str) -> Result<usize, PkgError> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let pkg = self
+            .database
+            .get(name)
+            .ok_or_else(|| PkgError::NotInstalled(name.to_string()))?;
+
+        let mut restored = 0;
+        for path in pkg.file_entries.iter().map(|e| &e.path) {
+            let Some(entry) = self.database.file_entry(path) else {
+                continue;
+            };
+            let target = extract::confined_path(&self.config.root, &entry.path)?;
+            let Ok(meta) = std::fs::symlink_metadata(&target) else {
+                continue;
+            };
+            // Symlink permissions are not meaningful
+            if meta.file_type().is_symlink() {
+                continue;
+            }
+
+            let mode = entry.mode & 0o7777;
+            if meta.permissions().mode() & 0o7777 != mode {
+                std::fs::set_permissions(&target, std::fs::Permissions::from_mode(mode))
+                    .map_err(PkgError::IoError)?;
+                restored += 1;
+            }
+        }
+
+        Ok(restored)
+    }
+
     /// Find package in repositories
     fn find_package(&self, name: &str) -> Result<Option<PackageInfo>, PkgError> {
-        // TODO: Search all enabled repositories
-        Ok(None)
+        Ok(self.repos.get(name).map(|(_, pkg)| pkg))
     }
 
     /// Check if removal would break dependencies
@@ -386,73 +807,283 @@ impl RpmNext {
     /// Execute a transaction
     fn execute_transaction(&mut self, tx: &Transaction) -> Result<(), PkgError> {
         // Download packages
-        for pkg in &tx.install {
+        for pkg in tx
+            .install
+            .iter()
+            .chain(tx.upgrade.iter().map(|(_, new)| new))
+        {
             self.download_package(pkg)?;
         }
 
-        // Remove packages
+        // New packages as their archives describe them, then upgrades
+        let mut archives = Vec::new();
+        for pkg in tx
+            .install
+            .iter()
+            .chain(tx.upgrade.iter().map(|(_, new)| new))
+        {
+            archives.push(self.read_archive(pkg)?);
+        }
+
+        // What the transaction does, for hooks and triggers
+        let mut changes = hooks::Changes::default();
         for name in &tx.remove {
-            self.remove_package(name)?;
+            if let Some(pkg) = self.database.get(name) {
+                changes.remove(pkg);
+            }
         }
+        for (pkg, _) in &archives {
+            changes.install(pkg, self.database.get(&pkg.name));
+        }
+        self.run_hooks(When::PreTransaction, &changes)?;
 
-        // Install packages
-        for pkg in &tx.install {
-            self.install_package(pkg)?;
+        // Remove packages
+        for name in &tx.remove {
+            self.remove_package(name, tx.purge)?;
         }
 
-        // Upgrade packages
-        for (old, new) in &tx.upgrade {
-            self.remove_package(&old.name)?;
-            self.install_package(new)?;
+        // Install packages; upgrades go over the old version so edited
+        // configuration files are seen and kept
+        for (pkg, archive) in &archives {
+            self.install_archive(pkg, archive)?;
         }
 
         // Save database
         self.database.save(&self.config.db_dir)?;
 
-        Ok(())
+        self.run_hooks(When::PostTransaction, &changes)
     }
 
-    fn download_package(&self, _pkg: &PackageInfo) -> Result<(), PkgError> {
-        // TODO: Download to cache
-        Ok(())
+    /// Run the hooks matching `changes` and, after the transaction, the
+    /// Debian triggers it activated
+    ///
+    /// Hooks shipped by a package follow the policy of its source, like its
+    /// scripts; other hooks follow the global policy.
+    fn run_hooks(&self, when: When, changes: &hooks::Changes) -> Result<(), PkgError> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let root = &self.config.root;
+
+        if when == When::PostTransaction {
+            hooks::run_triggers(root, self.database.list(), changes, |pkg| {
+                self.script_policy(pkg)
+            })?;
+        }
+        let hooks = hooks::load_hooks(root, &self.config.hook_dirs)?;
+        hooks::run_hooks(root, &hooks, when, changes, |hook| {
+            match self
+                .database
+                .file_owner(&hook.path)
+                .and_then(|name| self.database.get(name))
+            {
+                Some(pkg) => self.script_policy(pkg),
+                None => self.config.script_policy,
+            }
+        })
     }
 
-    fn install_package(&mut self, pkg: &PackageInfo) -> Result<(), PkgError> {
-        // Extract package based on format
+    /// Fetch a package into the cache, unless it is already there
+    fn download_package(&self, pkg: &PackageInfo) -> Result<(), PkgError> {
+        let archive = self.cached_archive(pkg);
+        if archive.is_file() {
+            return Ok(());
+        }
+        let url = self.repos.download_url(pkg).ok_or_else(|| {
+            PkgError::DownloadError(format!("No download URL for {} {}", pkg.name, pkg.version))
+        })?;
+        let data = repository::fetch(&url)?;
+
+        // Renamed into place so an interrupted download is not taken for a
+        // complete one
+        std::fs::create_dir_all(&self.config.cache_dir).map_err(PkgError::IoError)?;
+        let partial = archive.with_extension("part");
+        std::fs::write(&partial, data).map_err(PkgError::IoError)?;
+        std::fs::rename(&partial, &archive).map_err(PkgError::IoError)
+    }
+
+    /// Check the downloaded archive of an index entry and read its own
+    /// metadata; index entries carry no scripts, file list or triggers
+    fn read_archive(&self, pkg: &PackageInfo) -> Result<(PackageInfo, PathBuf), PkgError> {
+        let archive = self.cached_archive(pkg);
+        self.verify_download(pkg, &archive)?;
+        let local = reader::reader_for(pkg.format).read(&archive)?;
+        Ok((local, archive))
+    }
+
+    /// Check a downloaded archive against what its repository index recorded
+    fn verify_download(&self, pkg: &PackageInfo, archive: &Path) -> Result<(), PkgError> {
         match pkg.format {
-            PackageFormat::Native => self.install_native(pkg)?,
-            PackageFormat::Deb => self.install_deb(pkg)?,
-            PackageFormat::Rpm => self.install_rpm(pkg)?,
-            _ => return Err(PkgError::UnsupportedFormat),
+            PackageFormat::Apk => alpine::verify_download(pkg, archive),
+            PackageFormat::Android => match self.repos.playstore.get(&pkg.name) {
+                Some(app) => self.repos.playstore.verify_apk(app, archive).map(|_| ()),
+                None => Ok(()),
+            },
+            PackageFormat::Msi | PackageFormat::Msix => match self.repos.winget.get(&pkg.name) {
+                Some(manifest) => self
+                    .repos
+                    .winget
+                    .verify_installer(manifest, archive)
+                    .map(|_| ()),
+                None => Ok(()),
+            },
+            _ => Ok(()),
         }
+    }
 
-        self.database.register(pkg.clone());
-        Ok(())
+    /// Install a local package file
+    pub fn install_file(&mut self, path: &Path) -> Result<PackageInfo, PkgError> {
+        let pkg = inspect_file(path)?;
+        let mut changes = hooks::Changes::default();
+        changes.install(&pkg, self.database.get(&pkg.name));
+        self.run_hooks(When::PreTransaction, &changes)?;
+
+        self.install_archive(&pkg, path)?;
+        self.database.save(&self.config.db_dir)?;
+        self.run_hooks(When::PostTransaction, &changes)?;
+        Ok(pkg)
     }
 
-    fn remove_package(&mut self, name: &str) -> Result<(), PkgError> {
-        if let Some(pkg) = self.database.unregister(name) {
-            // Remove files in reverse order
-            for file in pkg.files.iter().rev() {
-                let path = self.config.root.join(file.trim_start_matches('/'));
-                let _ = std::fs::remove_file(&path);
-            }
+    fn install_archive(&mut self, pkg: &PackageInfo, archive: &Path) -> Result<(), PkgError> {
+        let old = self.database.get(&pkg.name).cloned();
+        self.run_scripts(&scriptlet::install_scripts(
+            Stage::BeforeUnpack,
+            pkg,
+            old.as_ref(),
+        ))?;
+
+        let mut extractor = extract::Extractor::new(&self.config.root)?;
+        conffile::plan(&self.config.root, pkg, &self.database, &mut extractor)?;
+
+        // Extract package based on format
+        reader::reader_for(pkg.format).extract(archive, &mut extractor)?;
+
+        let mut installed = pkg.clone();
+        installed.file_entries = merge_entries(&pkg.file_entries, extractor.finish()?);
+        self.database.unregister(&pkg.name);
+        self.database.register(installed);
+        self.run_scripts(&scriptlet::install_scripts(
+            Stage::AfterUnpack,
+            pkg,
+            old.as_ref(),
+        ))?;
+
+        // Over an installed version, drop the files it no longer ships
+        if let Some(old) = &old {
+            self.remove_files(old, false)?;
+            self.run_scripts(&scriptlet::install_scripts(
+                Stage::AfterCleanup,
+                pkg,
+                Some(old),
+            ))?;
         }
         Ok(())
     }
 
-    fn install_native(&self, _pkg: &PackageInfo) -> Result<(), PkgError> {
-        // Extract tar.zst to root
+    /// Run maintainer scripts in order, as the policy for each package's
+    /// source allows
+    fn run_scripts(&self, calls: &[scriptlet::Invocation]) -> Result<(), PkgError> {
+        for call in calls {
+            scriptlet::run(&self.config.root, call, self.script_policy(call.pkg))?;
+        }
         Ok(())
     }
 
-    fn install_deb(&self, _pkg: &PackageInfo) -> Result<(), PkgError> {
-        // Extract ar -> data.tar.* to root
-        Ok(())
+    fn script_policy(&self, pkg: &PackageInfo) -> ScriptPolicy {
+        let source = self.repos.source_of(pkg);
+        self.config
+            .source_script_policies
+            .get(&source)
+            .copied()
+            .unwrap_or(self.config.script_policy)
     }
 
-    fn install_rpm(&self, _pkg: &PackageInfo) -> Result<(), PkgError> {
-        // Extract cpio to root
+    /// Location of a downloaded package archive in the cache
+    fn cached_archive(&self, pkg: &PackageInfo) -> PathBuf {
+        let ext = match pkg.format {
+            PackageFormat::Native => "pkg.tar.zst",
+            PackageFormat::Deb => "deb",
+            PackageFormat::Rpm => "rpm",
+            PackageFormat::Apk | PackageFormat::Android => "apk",
+            PackageFormat::Msi => "msi",
+            PackageFormat::Msix => "msix",
+        };
+        self.config
+            .cache_dir
+            .join(format!("{}-{}-{}.{}", pkg.name, pkg.version, pkg.arch, ext))
+    }
+
+    fn remove_package(&mut self, name: &str, purge: bool) -> Result<(), PkgError> {
+        let Some(pkg) = self.database.get(name).cloned() else {
+            return Ok(());
+        };
+        self.run_scripts(&scriptlet::remove_scripts(&pkg, true, purge))?;
+
+        self.database.unregister(name);
+        self.remove_files(&pkg, purge)?;
+        self.run_scripts(&scriptlet::remove_scripts(&pkg, false, purge))
+    }
+
+    /// Delete the files of an unregistered package that no installed
+    /// package owns; edited configuration files stay unless purging
+    fn remove_files(&self, pkg: &PackageInfo, purge: bool) -> Result<(), PkgError> {
+        let root = &self.config.root;
+
+        // Directories other packages also ship are left in place
+        let shared: HashSet<&str> = self
+            .database
+            .list()
+            .flat_map(|p| p.file_entries.iter())
+            .filter(|e| e.is_dir())
+            .map(|e| e.path.as_str())
+            .collect();
+
+        let owned = |path: &str| {
+            self.database.file_owner(path).is_some()
+                || self.database.file_entry(path).is_some()
+                || shared.contains(path)
+        };
+
+        let mut dirs = Vec::new();
+        let mut files = Vec::new();
+        for entry in pkg.file_entries.iter().filter(|e| !owned(&e.path)) {
+            if entry.is_dir() {
+                dirs.push(extract::confined_path(root, &entry.path)?);
+                continue;
+            }
+            if conffile::is_config(entry) {
+                if purge {
+                    for suffix in [
+                        conffile::new_suffix(pkg.format),
+                        conffile::save_suffix(pkg.format),
+                    ] {
+                        files.push(format!("{}{}", entry.path, suffix));
+                    }
+                } else if conffile::is_modified(root, entry)? {
+                    eprintln!("warning: {} was modified, keeping it", entry.path);
+                    continue;
+                }
+            }
+            files.push(entry.path.clone());
+        }
+        let described: HashSet<&str> = pkg.file_entries.iter().map(|e| e.path.as_str()).collect();
+        files.extend(
+            pkg.files
+                .iter()
+                .filter(|f| !owned(f) && !described.contains(f.as_str()))
+                .cloned(),
+        );
+
+        for file in files {
+            // Symlinks are removed, not followed
+            let _ = std::fs::remove_file(extract::confined_path(root, &file)?);
+        }
+
+        // Deepest first; directories still holding other files stay
+        dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+        for dir in dirs {
+            let _ = std::fs::remove_dir(&dir);
+        }
         Ok(())
     }
 
@@ -475,6 +1106,35 @@ impl RpmNext {
     }
 }
 
+/// Entries of the files actually written, with flags (and digests the
+/// extraction did not compute) from the package's own file list
+///
+/// Listed files the payload does not carry (RPM `%ghost`) are kept as listed.
+fn merge_entries(listed: &[FileEntry], installed: Vec<FileEntry>) -> Vec<FileEntry> {
+    let mut pending: HashMap<&str, &FileEntry> =
+        listed.iter().map(|e| (e.path.as_str(), e)).collect();
+
+    let mut entries: Vec<FileEntry> = installed
+        .into_iter()
+        .map(|mut entry| {
+            if let Some(listed) = pending.remove(entry.path.as_str()) {
+                if entry.digest.is_empty() {
+                    entry.digest = listed.digest.clone();
+                }
+                entry.flags = listed.flags;
+            }
+            entry
+        })
+        .collect();
+    entries.extend(
+        listed
+            .iter()
+            .filter(|e| pending.contains_key(e.path.as_str()))
+            .cloned(),
+    );
+    entries
+}
+
 /// Package manager errors
 #[derive(Debug)]
 pub enum PkgError {
@@ -489,10 +1149,12 @@ pub enum PkgError {
     DatabaseError(String),
     NetworkError(String),
     ParseError(String),
+    /// A maintainer script failed
+    ScriptError(String),
 }
 
 /// Repository source type
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum RepositorySource {
     /// Native Redox packages
     Native,
@@ -506,6 +1168,23 @@ pub enum RepositorySource {
     Winget,
     /// Android F-Droid/Play Store
     Android,
+    /// Alpine apk
+    Alpine,
+}
+
+impl RepositorySource {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "native" => Some(Self::Native),
+            "apt" => Some(Self::Apt),
+            "dnf" => Some(Self::Dnf),
+            "pacman" => Some(Self::Pacman),
+            "winget" => Some(Self::Winget),
+            "android" => Some(Self::Android),
+            "alpine" => Some(Self::Alpine),
+            _ => None,
+        }
+    }
 }
 
 /// Unified Repository Manager
@@ -518,6 +1197,8 @@ pub struct UnifiedRepositoryManager {
     pub dnf: dnf::DnfRepository,
     /// Pacman repositories (Arch)
     pub pacman: pacman::PacmanRepository,
+    /// Alpine repositories
+    pub alpine: alpine::AlpineRepository,
     /// Winget repositories (Windows)
     pub winget: winget::WingetRepository,
     /// Play Store / F-Droid (Android)
@@ -535,6 +1216,7 @@ impl UnifiedRepositoryManager {
                 dnf::FEDORA_DL
             )),
             pacman: pacman::PacmanRepository::new(pacman::ARCH_MIRROR),
+            alpine: alpine::AlpineRepository::new(alpine::ALPINE_MIRROR, alpine::ALPINE_BRANCH),
             winget: winget::WingetRepository::new(),
             playstore: playstore::PlayStoreRepository::new_fdroid(),
             enabled_sources: vec![
@@ -544,6 +1226,7 @@ impl UnifiedRepositoryManager {
                 RepositorySource::Pacman,
                 RepositorySource::Winget,
                 RepositorySource::Android,
+                RepositorySource::Alpine,
             ],
         }
     }
@@ -558,6 +1241,8 @@ impl UnifiedRepositoryManager {
 
         // Arch repos are configured by default in PacmanRepository
 
+        // Alpine main/community are configured by default in AlpineRepository
+
         // DNF/Fedora is configured by default
 
         // F-Droid is configured by default in PlayStoreRepository
@@ -583,6 +1268,7 @@ impl UnifiedRepositoryManager {
                 RepositorySource::Apt => self.apt.sync(),
                 RepositorySource::Dnf => self.dnf.sync(),
                 RepositorySource::Pacman => self.pacman.sync(),
+                RepositorySource::Alpine => self.alpine.sync(),
                 RepositorySource::Winget => self.winget.sync(),
                 RepositorySource::Android => self.playstore.sync(),
                 RepositorySource::Native => Ok(()), // Native uses local repo
@@ -621,6 +1307,11 @@ impl UnifiedRepositoryManager {
                         results.push((RepositorySource::Pacman, pkg.clone().into()));
                     }
                 }
+                RepositorySource::Alpine => {
+                    for pkg in self.alpine.search(query) {
+                        results.push((RepositorySource::Alpine, pkg.clone().into()));
+                    }
+                }
                 RepositorySource::Winget => {
                     for manifest in self.winget.search(query) {
                         results.push((RepositorySource::Winget, manifest.clone().into()));
@@ -638,9 +1329,34 @@ impl UnifiedRepositoryManager {
         results
     }
 
+    /// Every package of one source, for dependency resolution
+    pub fn packages(&self, source: RepositorySource) -> Vec<PackageInfo> {
+        match source {
+            RepositorySource::Apt => self.apt.packages().map(|pkg| pkg.clone().into()).collect(),
+            RepositorySource::Dnf => self.dnf.packages().map(|pkg| pkg.clone().into()).collect(),
+            RepositorySource::Pacman => self
+                .pacman
+                .packages()
+                .map(|pkg| pkg.clone().into())
+                .collect(),
+            RepositorySource::Alpine => self
+                .alpine
+                .packages()
+                .map(|pkg| pkg.clone().into())
+                .collect(),
+            RepositorySource::Winget => self.winget.manifests().map(|m| m.clone().into()).collect(),
+            RepositorySource::Android => self
+                .playstore
+                .apps()
+                .map(|app| app.clone().into())
+                .collect(),
+            RepositorySource::Native => Vec::new(),
+        }
+    }
+
     /// Get package by name from best source
     pub fn get(&self, name: &str) -> Option<(RepositorySource, PackageInfo)> {
-        // Priority order: Native > Pacman > APT > DNF > Winget > Android
+        // Priority order: Native > Pacman > APT > DNF > Winget > Android > Alpine
 
         // Try Pacman first (good for Linux apps)
         if let Some(pkg) = self.pacman.get(name) {
@@ -667,8 +1383,63 @@ impl UnifiedRepositoryManager {
             return Some((RepositorySource::Android, app.clone().into()));
         }
 
+        // Try Alpine
+        if let Some(pkg) = self.alpine.get(name) {
+            return Some((RepositorySource::Alpine, pkg.clone().into()));
+        }
+
         None
     }
+
+    /// Where to download a package version the indexes list
+    pub fn download_url(&self, pkg: &PackageInfo) -> Option<String> {
+        let same = |name: &str, version: &str| name == pkg.name && version == pkg.version;
+        match self.source_of(pkg) {
+            RepositorySource::Apt => self
+                .apt
+                .packages()
+                .find(|p| same(&p.package, &p.version))
+                .and_then(|p| self.apt.get_download_url(p)),
+            RepositorySource::Dnf => self
+                .dnf
+                .packages()
+                .find(|p| same(&p.name, &p.version.ver))
+                .map(|p| self.dnf.get_download_url(p)),
+            RepositorySource::Alpine => self
+                .alpine
+                .packages()
+                .find(|p| same(&p.name, &p.version))
+                .map(|p| self.alpine.get_download_url(p)),
+            RepositorySource::Winget => self
+                .winget
+                .get(&pkg.name)
+                .filter(|m| m.version == pkg.version)
+                .map(|m| self.winget.get_download_url(m)),
+            RepositorySource::Android => self
+                .playstore
+                .get(&pkg.name)
+                .and_then(|app| self.playstore.get_download_url(app)),
+            // The pacman index does not record which repository a package
+            // is in, which its URL needs
+            RepositorySource::Pacman | RepositorySource::Native => None,
+        }
+    }
+
+    /// Source that ships packages of `pkg`'s format
+    pub fn source_of(&self, pkg: &PackageInfo) -> RepositorySource {
+        match pkg.format {
+            // Pacman packages share the native format
+            PackageFormat::Native if self.pacman.get(&pkg.name).is_some() => {
+                RepositorySource::Pacman
+            }
+            PackageFormat::Native => RepositorySource::Native,
+            PackageFormat::Deb => RepositorySource::Apt,
+            PackageFormat::Rpm => RepositorySource::Dnf,
+            PackageFormat::Apk => RepositorySource::Alpine,
+            PackageFormat::Android => RepositorySource::Android,
+            PackageFormat::Msi | PackageFormat::Msix => RepositorySource::Winget,
+        }
+    }
 }
 
 impl Default for UnifiedRepositoryManager {
@@ -687,17 +1458,73 @@ fn main() {
     println!("║  • APT (Debian/Ubuntu)    • DNF (Fedora/RHEL)                ║");
     println!("║  • Pacman (Arch Linux)    • Winget (Windows)                 ║");
     println!("║  • F-Droid (Android)      • Native (Redox)                   ║");
+    println!("║  • Alpine (musl)                                              ║");
     println!("╚═══════════════════════════════════════════════════════════════╝");
     println!();
 
-    let config = PkgConfig::default();
-    let pm = RpmNext::new(config).expect("Failed to initialize package manager");
+    // Example CLI handling
+    let mut args: Vec<String> = std::env::args().collect();
+
+    let mut config = PkgConfig::default();
+    if let Some(idx) = args.iter().position(|a| a == "--root") {
+        if idx + 1 < args.len() {
+            config.root = PathBuf::from(args.remove(idx + 1));
+            // The database belongs to the root it describes
+            config.db_dir = config.root.join("var/lib/rpm-next");
+        }
+        args.remove(idx);
+    }
+    // `--scripts <policy>` for every source, `--scripts <source>=<policy>`
+    // for one; may repeat
+    while let Some(idx) = args.iter().position(|a| a == "--scripts") {
+        if idx + 1 < args.len() {
+            let value = args.remove(idx + 1);
+            let (source, name) = match value.split_once('=') {
+                Some((source, name)) => match RepositorySource::from_name(source) {
+                    Some(source) => (Some(source), name),
+                    None => {
+                        eprintln!("Unknown source '{}'", source);
+                        return;
+                    }
+                },
+                None => (None, value.as_str()),
+            };
+            match (source, ScriptPolicy::from_name(name)) {
+                (Some(source), Some(policy)) => {
+                    config.source_script_policies.insert(source, policy);
+                }
+                (None, Some(policy)) => config.script_policy = policy,
+                (_, None) => {
+                    eprintln!(
+                        "Unknown script policy '{}': expected run, log or skip",
+                        name
+                    );
+                    return;
+                }
+            }
+        }
+        args.remove(idx);
+    }
 
-    // Initialize repository manager with all sources
-    let mut repos = UnifiedRepositoryManager::default();
+    let apt_dir = config.root.join("etc/apt");
+    let db_dir = config.db_dir.clone();
+    let mut pm = match RpmNext::new(config) {
+        Ok(pm) => pm,
+        Err(e) => {
+            eprintln!(
+                "✗ Cannot read the package database in {}: {:?}",
+                db_dir.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+    };
 
-    // Example CLI handling
-    let args: Vec<String> = std::env::args().collect();
+    // The repository manager has all sources, plus those of an existing
+    // Debian/Ubuntu system
+    if let Err(e) = pm.repos.apt.import_sources(&apt_dir) {
+        eprintln!("warning: could not import APT sources: {:?}", e);
+    }
 
     if args.len() < 2 {
         print_usage();
@@ -707,7 +1534,7 @@ fn main() {
     match args[1].as_str() {
         "sync" | "update" => {
             println!("Synchronizing all repositories...");
-            match repos.sync_all() {
+            match pm.repos.sync_all() {
                 Ok(_) => println!("✓ All repositories synchronized"),
                 Err(e) => eprintln!("✗ Sync failed: {:?}", e),
             }
@@ -720,7 +1547,7 @@ fn main() {
             let query = &args[2];
             println!("Searching for '{}'...\n", query);
 
-            let results = repos.search(query);
+            let results = pm.repos.search(query);
             if results.is_empty() {
                 println!("No packages found.");
             } else {
@@ -744,12 +1571,70 @@ fn main() {
                 return;
             }
             let name = &args[2];
-            match repos.get(name) {
-                Some((source, pkg)) => {
-                    println!("Found {} in {:?} repository", pkg.name, source);
-                    println!("Would install: {} v{}", pkg.name, pkg.version);
+            let path = Path::new(name);
+            if path.is_file() {
+                match pm.install_file(path) {
+                    Ok(pkg) => println!("✓ Installed {} {}", pkg.name, pkg.version),
+                    Err(e) => eprintln!("✗ Install failed: {:?}", e),
+                }
+                return;
+            }
+            match pm.install(&[name.as_str()]) {
+                Ok(tx) => {
+                    for pkg in &tx.install {
+                        println!("✓ Installed {} {}", pkg.name, pkg.version);
+                    }
+                }
+                Err(e) => eprintln!("✗ Install failed: {:?}", e),
+            }
+        }
+        "remove" => {
+            let purge = args.iter().any(|a| a == "--purge");
+            let names: Vec<&str> = args[2..]
+                .iter()
+                .map(String::as_str)
+                .filter(|a| *a != "--purge")
+                .collect();
+            if names.is_empty() {
+                eprintln!("Usage: rpm-next remove [--purge] <package>...");
+                return;
+            }
+            let result = match purge {
+                true => pm.purge(&names),
+                false => pm.remove(&names),
+            };
+            match result {
+                Ok(_) => println!("✓ Removed {}", names.join(", ")),
+                Err(e) => eprintln!("✗ Remove failed: {:?}", e),
+            }
+        }
+        "verify" => {
+            if args.len() < 3 {
+                eprintln!("Usage: rpm-next verify <package>...");
+                return;
+            }
+            for name in &args[2..] {
+                match pm.verify(name) {
+                    Ok(issues) if issues.is_empty() => println!("✓ {}: all files match", name),
+                    Ok(issues) => {
+                        for issue in issues {
+                            println!("✗ {}: {}", name, issue);
+                        }
+                    }
+                    Err(e) => eprintln!("✗ Verify of {} failed: {:?}", name, e),
+                }
+            }
+        }
+        "restore" => {
+            if args.len() < 3 {
+                eprintln!("Usage: rpm-next restore <package>...");
+                return;
+            }
+            for name in &args[2..] {
+                match pm.restore_permissions(name) {
+                    Ok(restored) => println!("✓ {}: restored {} file modes", name, restored),
+                    Err(e) => eprintln!("✗ Restore of {} failed: {:?}", name, e),
                 }
-                None => eprintln!("Package '{}' not found in any repository", name),
             }
         }
         "info" => {
@@ -758,7 +1643,38 @@ fn main() {
                 return;
             }
             let name = &args[2];
-            match repos.get(name) {
+            let path = Path::new(name);
+            if path.is_file() {
+                match inspect_file(path) {
+                    Ok(pkg) => print_package_file(&pkg),
+                    Err(e) => eprintln!("✗ Failed to read {}: {:?}", name, e),
+                }
+                match reader::detect_format(path) {
+                    Ok(PackageFormat::Native) => {
+                        if let Ok(native) = pkg::read_pkg(path) {
+                            print_pkginfo(&native.pkginfo);
+                        }
+                    }
+                    Ok(PackageFormat::Android) => {
+                        if let Ok(apk) = android::read_android(path) {
+                            print_android(&apk);
+                        }
+                    }
+                    Ok(PackageFormat::Msi) => {
+                        if let Ok(msi) = msi::read_msi(path) {
+                            print_msi(&msi);
+                        }
+                    }
+                    Ok(PackageFormat::Msix) => {
+                        if let Ok(msix) = msix::read_msix(path) {
+                            print_msix(&msix);
+                        }
+                    }
+                    _ => {}
+                }
+                return;
+            }
+            match pm.repos.get(name) {
                 Some((source, pkg)) => {
                     println!("Name:        {}", pkg.name);
                     println!("Version:     {}", pkg.version);
@@ -779,27 +1695,401 @@ fn main() {
             );
             println!("  • DNF (Fedora/RHEL)   - {}fedora 40", "✓ ");
             println!("  • Pacman (Arch)       - {}core, extra, multilib", "✓ ");
+            println!("  • Alpine (apk)        - ✓ main, community");
             println!("  • Winget (Windows)    - {}microsoft winget-pkgs", "✓ ");
             println!("  • F-Droid (Android)   - {}f-droid.org", "✓ ");
+            println!();
+            println!("APT sources:");
+            for source in pm.repos.apt.sources() {
+                println!("  {}", source);
+            }
         }
         _ => print_usage(),
     }
 }
 
+/// Read package metadata from a local package file
+fn inspect_file(path: &Path) -> Result<PackageInfo, PkgError> {
+    reader::read_package(path)
+}
+
+fn print_package_file(pkg: &PackageInfo) {
+    println!("Name:         {}", pkg.name);
+    println!("Version:      {}", pkg.version);
+    println!("Architecture: {}", pkg.arch);
+    println!("Format:       {:?}", pkg.format);
+    println!("Maintainer:   {}", pkg.maintainer);
+    println!("License:      {}", pkg.license);
+    println!("Homepage:     {}", pkg.homepage);
+    println!("Size:         {}", pkg.size);
+    println!("Installed:    {}", pkg.installed_size);
+    println!("Checksum:     {}", pkg.checksum);
+    let deps: Vec<String> = pkg.dependencies.iter().map(|d| d.to_string()).collect();
+    println!("Depends:      {}", deps.join(", "));
+    println!("Provides:     {}", pkg.provides.join(", "));
+    println!("Conflicts:    {}", pkg.conflicts.join(", "));
+    let scripts: Vec<&str> = pkg.scripts.iter().map(|s| s.phase.name()).collect();
+    println!("Scripts:      {}", scripts.join(", "));
+    println!("Description:  {}", pkg.description);
+    println!("Files:");
+    if pkg.file_entries.is_empty() {
+        for file in &pkg.files {
+            println!("  {}", file);
+        }
+    }
+    for entry in &pkg.file_entries {
+        let mut line = format!(
+            "  {:06o} {}/{} {:>8} {}",
+            entry.mode, entry.user, entry.group, entry.size, entry.path
+        );
+        if !entry.link_target.is_empty() {
+            line.push_str(&format!(" -> {}", entry.link_target));
+        }
+        for (flag, label) in [
+            (file_flags::CONFIG, "config"),
+            (file_flags::DOC, "doc"),
+            (file_flags::LICENSE, "license"),
+            (file_flags::GHOST, "ghost"),
+        ] {
+            if entry.flags & flag != 0 {
+                line.push_str(&format!(" [{}]", label));
+            }
+        }
+        println!("{}", line);
+    }
+}
+
+/// Native-only .PKGINFO fields not carried by `PackageInfo`
+fn print_pkginfo(info: &pkg::PkgInfo) {
+    let deps = |deps: &[Dependency]| {
+        deps.iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    println!("Base:         {}", info.pkgbase);
+    println!("Groups:       {}", info.groups.join(", "));
+    println!("Build Date:   {}", info.builddate);
+    println!("Packager:     {}", info.packager);
+    println!("Optional:     {}", info.optdepends.join(", "));
+    println!("Make Deps:    {}", deps(&info.makedepends));
+    println!("Check Deps:   {}", deps(&info.checkdepends));
+    println!("Backup:       {}", info.backup.join(", "));
+}
+
+/// Android manifest and signing details not carried by `PackageInfo`
+fn print_android(apk: &android::AndroidApk) {
+    println!("Min SDK:      {}", apk.min_sdk);
+    println!("Target SDK:   {}", apk.target_sdk);
+    println!("Native Code:  {}", apk.native_code.join(", "));
+    println!("Permissions:  {}", apk.permissions.join(", "));
+    let schemes: Vec<String> = apk
+        .signature_schemes
+        .iter()
+        .map(|v| format!("v{}", v))
+        .collect();
+    println!("Signature:    {}", schemes.join(", "));
+    for signer in &apk.signers {
+        println!("Signer:       {}", signer);
+    }
+}
+
+fn print_msi(msi: &msi::MsiPackage) {
+    println!("Product Code: {}", msi.product_code);
+    println!("Upgrade Code: {}", msi.upgrade_code);
+    println!("Languages:    {}", msi.languages.join(", "));
+}
+
+fn print_msix(msix: &msix::MsixPackage) {
+    println!("Display Name: {}", msix.display_name);
+    println!("Publisher:    {}", msix.publisher);
+    println!("Family Name:  {}", msix.family_name());
+    for family in &msix.device_families {
+        println!("Device:       {} >= {}", family.name, family.min_version);
+    }
+    for app in &msix.applications {
+        println!("Application:  {} ({})", app.id, app.executable);
+    }
+}
+
 fn print_usage() {
-    println!("Usage: rpm-next <command> [options]");
+    println!(
+        "Usage: rpm-next [--root <dir>] [--scripts [<source>=]<run|log|skip>] <command> [options]"
+    );
     println!();
     println!("Commands:");
     println!("  sync, update      Synchronize all repository indexes");
     println!("  search <query>    Search packages across all sources");
-    println!("  install <pkg>     Install a package");
-    println!("  remove <pkg>      Remove an installed package");
+    println!("  install <pkg|file> Install a package");
+    println!("  remove [--purge] <pkg> Remove a package; --purge also deletes its config files");
     println!("  upgrade [pkg]     Upgrade packages");
-    println!("  info <pkg>        Show package information");
+    println!("  verify <pkg>      Check installed files against their recorded metadata");
+    println!("  restore <pkg>     Reset installed file permissions to the recorded modes");
+    println!("  info <pkg|file>   Show package information");
     println!("  sources           List configured repository sources");
     println!();
     println!("Examples:");
     println!("  rpm-next search firefox");
     println!("  rpm-next install com.mozilla.firefox");
     println!("  rpm-next upgrade");
+    println!("  rpm-next --scripts android=skip --scripts winget=log upgrade");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn database_keeps_file_digests_across_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("rpm-next-db-{}", std::process::id()));
+        let mut pkg = deb::parse_control("Package: foo\nVersion: 1\n").unwrap();
+        pkg.file_entries.push(FileEntry {
+            path: "/etc/foo.conf".to_string(),
+            mode: 0o100644,
+            digest: "abc".to_string(),
+            flags: file_flags::CONFIG,
+            ..Default::default()
+        });
+        let mut database = PackageDatabase::new();
+        database.register(pkg);
+        database.save(&dir).unwrap();
+
+        let loaded = PackageDatabase::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(loaded.is_installed("foo"));
+        assert_eq!(loaded.file_entry("/etc/foo.conf").unwrap().digest, "abc");
+    }
+
+    #[test]
+    fn missing_database_loads_empty() {
+        let loaded = PackageDatabase::load(Path::new("/nonexistent/rpm-next")).unwrap();
+        assert_eq!(loaded.list().count(), 0);
+    }
+
+    /// gzip-compressed tar of regular files
+    fn tar_gz(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (path, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_uid(0);
+            header.set_gid(0);
+            header.set_mtime(0);
+            builder.append_data(&mut header, path, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn deb_package(control: &str, files: &[(&str, &[u8])]) -> Vec<u8> {
+        let members = [
+            ("debian-binary", b"2.0\n".to_vec()),
+            (
+                "control.tar.gz",
+                tar_gz(&[("./control", control.as_bytes())]),
+            ),
+            ("data.tar.gz", tar_gz(files)),
+        ];
+        let mut ar = deb::AR_MAGIC.to_vec();
+        for (name, data) in members {
+            let header = format!(
+                "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+                name,
+                0,
+                0,
+                0,
+                "100644",
+                data.len()
+            );
+            ar.extend_from_slice(header.as_bytes());
+            ar.extend_from_slice(&data);
+            if data.len() % 2 == 1 {
+                ar.push(b'\n');
+            }
+        }
+        ar
+    }
+
+    /// Archive members: path and content
+    type Members<'a> = &'a [(&'a str, &'a [u8])];
+
+    /// A package manager whose only source is a flat APT repository of
+    /// `debs`
+    fn apt_manager(dir: &Path, debs: &[(&str, Members)]) -> RpmNext {
+        use sha2::{Digest, Sha256};
+
+        let repo = dir.join("repo");
+        let config = PkgConfig {
+            root: dir.join("root"),
+            cache_dir: dir.join("cache"),
+            db_dir: dir.join("db"),
+            ..PkgConfig::default()
+        };
+        std::fs::create_dir_all(repo.join("pool")).unwrap();
+
+        let mut index = String::new();
+        for (control, files) in debs {
+            let pkg = deb::parse_control(control).unwrap();
+            let filename = format!("pool/{}_{}.deb", pkg.name, pkg.version);
+            std::fs::write(repo.join(&filename), deb_package(control, files)).unwrap();
+            index.push_str(&format!("{}Filename: {}\n\n", control, filename));
+        }
+        std::fs::write(repo.join("Packages"), &index).unwrap();
+        let release = format!(
+            "SHA256:\n {:x} {} Packages\n",
+            Sha256::digest(&index),
+            index.len()
+        );
+        std::fs::write(repo.join("Release"), release).unwrap();
+
+        let mut pm = RpmNext::new(config).unwrap();
+        pm.repos.apt = AptRepository::new();
+        let line = format!("deb file://{} ./", repo.display());
+        pm.repos
+            .apt
+            .add_source(apt::AptSource::parse(&line).unwrap());
+        pm.repos.apt.sync().unwrap();
+        pm
+    }
+
+    #[test]
+    fn install_takes_dependencies_from_the_repository_index() {
+        let dir = std::env::temp_dir().join(format!("rpm-next-install-{}", std::process::id()));
+        // Only `app` is requested; `lib` is found through the index
+        let mut pm = apt_manager(
+            &dir,
+            &[
+                (
+                    "Package: app\nVersion: 1\nArchitecture: all\nDepends: lib (>= 1)\n",
+                    &[("./usr/bin/app", b"data")],
+                ),
+                (
+                    "Package: lib\nVersion: 1\nArchitecture: all\n",
+                    &[("./usr/lib/libfoo.so", b"data")],
+                ),
+            ],
+        );
+
+        let tx = pm.install(&["app"]).unwrap();
+        let installed: Vec<&str> = tx.install.iter().map(|p| p.name.as_str()).collect();
+        let lib_exists = pm.config.root.join("usr/lib/libfoo.so").exists();
+        let app_exists = pm.config.root.join("usr/bin/app").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(installed, ["lib", "app"]);
+        assert!(lib_exists && app_exists);
+        assert!(pm.database.is_installed("lib"));
+    }
+
+    #[test]
+    fn dependencies_resolve_within_the_source_of_the_request() {
+        let dir = std::env::temp_dir().join(format!("rpm-next-sources-{}", std::process::id()));
+        let mut pm = apt_manager(
+            &dir,
+            &[(
+                "Package: app\nVersion: 1\nArchitecture: all\nDepends: bash\n",
+                &[("./usr/bin/app", b"data")],
+            )],
+        );
+        // Alpine has a `bash`, which must not satisfy a Debian dependency
+        let mirror = dir.join("alpine");
+        for (repo, index) in [
+            ("main", "P:bash\nV:5.2-r0\nA:x86_64\n\n"),
+            ("community", ""),
+        ] {
+            let arch_dir = mirror.join("edge").join(repo).join("x86_64");
+            std::fs::create_dir_all(&arch_dir).unwrap();
+            let tarball = tar_gz(&[("APKINDEX", index.as_bytes())]);
+            std::fs::write(arch_dir.join("APKINDEX.tar.gz"), tarball).unwrap();
+        }
+        pm.repos.alpine =
+            alpine::AlpineRepository::new(&format!("file://{}", mirror.display()), "edge");
+        pm.repos.alpine.sync().unwrap();
+        assert!(pm.repos.alpine.get("bash").is_some());
+
+        let result = pm.install(&["app"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(result, Err(PkgError::PackageNotFound(name)) if name == "bash"));
+    }
+
+    #[test]
+    fn path_hooks_see_the_files_of_repository_packages() {
+        let dir = std::env::temp_dir().join(format!("rpm-next-hooks-{}", std::process::id()));
+        let mut pm = apt_manager(
+            &dir,
+            &[(
+                "Package: app\nVersion: 1\nArchitecture: all\n",
+                &[("./usr/bin/app", b"data")],
+            )],
+        );
+        // The index entry lists no files; only the archive matches
+        let hook = "[Trigger]\nOperation = Install\nType = Path\nTarget = usr/bin/*\n\n\
+                    [Action]\nWhen = PreTransaction\nExec = /nonexistent\nAbortOnFail\n";
+        let hook_dir = pm.config.root.join("etc/rpm-next/hooks");
+        std::fs::create_dir_all(&hook_dir).unwrap();
+        std::fs::write(hook_dir.join("app.hook"), hook).unwrap();
+
+        let result = pm.install(&["app"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(result, Err(PkgError::ScriptError(_))));
+        assert!(!pm.database.is_installed("app"));
+    }
+
+    #[test]
+    fn hooks_follow_the_policy_of_the_package_shipping_them() {
+        let dir = std::env::temp_dir().join(format!("rpm-next-hook-policy-{}", std::process::id()));
+        let hook = b"[Trigger]\nOperation = Install\nType = Path\nTarget = usr/bin/*\n\n\
+                     [Action]\nWhen = PreTransaction\nExec = /nonexistent\nAbortOnFail\n";
+        let mut pm = apt_manager(
+            &dir,
+            &[
+                (
+                    "Package: guard\nVersion: 1\nArchitecture: all\n",
+                    &[("./usr/share/libalpm/hooks/guard.hook", hook)],
+                ),
+                (
+                    "Package: app\nVersion: 1\nArchitecture: all\n",
+                    &[("./usr/bin/app", b"data")],
+                ),
+            ],
+        );
+        pm.install(&["guard"]).unwrap();
+
+        let run = pm.install(&["app"]);
+        pm.config
+            .source_script_policies
+            .insert(RepositorySource::Apt, ScriptPolicy::Skip);
+        let skip = pm.install(&["app"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(run, Err(PkgError::ScriptError(_))));
+        assert!(skip.is_ok());
+    }
+
+    #[test]
+    fn script_policy_follows_the_package_source() {
+        let dir = std::env::temp_dir().join(format!("rpm-next-policy-{}", std::process::id()));
+        let mut config = PkgConfig {
+            root: dir.join("root"),
+            db_dir: dir.join("db"),
+            script_policy: ScriptPolicy::Log,
+            ..PkgConfig::default()
+        };
+        config
+            .source_script_policies
+            .insert(RepositorySource::Apt, ScriptPolicy::Skip);
+        let pm = RpmNext::new(config).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        let deb = deb::parse_control("Package: foo\nVersion: 1\n").unwrap();
+        let rpm = PackageInfo {
+            format: PackageFormat::Rpm,
+            ..deb.clone()
+        };
+        assert_eq!(pm.script_policy(&deb), ScriptPolicy::Skip);
+        assert_eq!(pm.script_policy(&rpm), ScriptPolicy::Log);
+    }
 }
@@ -0,0 +1,676 @@
+//! Windows MSI Adapter
+//!
+//! Inspects Windows Installer databases (.msi): an OLE Compound File whose
+//! streams hold a shared string pool and column-major tables. Reads the
+//! Property table and resolves the File/Component/Directory tables into
+//! the list of files the package would install.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::archive;
+use crate::reader::PackageReader;
+use crate::{FileEntry, PackageFormat, PackageInfo, PkgError};
+
+/// Set in the string pool codepage when string references are 3 bytes wide
+const LONG_STRING_REFS: u32 = 0x8000_0000;
+
+/// Largest database stream we are willing to load
+const MAX_STREAM_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Deepest Directory table nesting we follow
+const MAX_DIRECTORY_DEPTH: usize = 64;
+
+/// Summary information property holding `platform;languages`
+const PID_TEMPLATE: u32 = 7;
+const VT_LPSTR: u32 = 30;
+
+/// `_Columns` type bits
+mod column_type {
+    pub const SIZE_MASK: u16 = 0x00ff;
+    pub const VALID: u16 = 0x0100;
+    pub const STRING: u16 = 0x0800;
+    pub const NULLABLE: u16 = 0x1000;
+}
+
+/// Metadata of a local MSI package
+#[derive(Debug, Clone, Default)]
+pub struct MsiPackage {
+    pub product_name: String,
+    pub product_version: String,
+    pub manufacturer: String,
+    pub product_code: String,
+    pub upgrade_code: String,
+    /// Platform from the summary information (x86, x64, arm64)
+    pub arch: String,
+    /// Language IDs from the summary information
+    pub languages: Vec<String>,
+    /// Full Property table
+    pub properties: BTreeMap<String, String>,
+    /// Files with their resolved install paths
+    pub files: Vec<MsiFile>,
+    pub size: u64,
+    /// SHA-256 of the whole file, hex
+    pub hash: String,
+}
+
+/// A row of the File table with its target path
+#[derive(Debug, Clone, Default)]
+pub struct MsiFile {
+    pub path: String,
+    pub size: u64,
+}
+
+/// Reader for Windows Installer packages
+pub struct MsiReader;
+
+impl PackageReader for MsiReader {
+    fn read(&self, path: &Path) -> Result<PackageInfo, PkgError> {
+        parse_msi(path)
+    }
+}
+
+/// Parse a Windows .msi
+pub fn parse_msi(path: &Path) -> Result<PackageInfo, PkgError> {
+    read_msi(path).map(PackageInfo::from)
+}
+
+/// Read the Property and File tables of a Windows .msi
+pub fn read_msi(path: &Path) -> Result<MsiPackage, PkgError> {
+    let size = std::fs::metadata(path).map_err(PkgError::IoError)?.len();
+    let mut db = Database::open(path)?;
+
+    let properties: BTreeMap<String, String> = db
+        .table("Property")?
+        .into_iter()
+        .filter_map(|row| Some((row.string("Property")?, row.string("Value")?)))
+        .collect();
+    let property = |name: &str| properties.get(name).cloned().unwrap_or_default();
+
+    let mut msi = MsiPackage {
+        product_name: property("ProductName"),
+        product_version: property("ProductVersion"),
+        manufacturer: property("Manufacturer"),
+        product_code: property("ProductCode"),
+        upgrade_code: property("UpgradeCode"),
+        size,
+        ..Default::default()
+    };
+    if msi.product_name.is_empty() {
+        return Err(PkgError::ParseError(
+            "Missing ProductName in Property table".to_string(),
+        ));
+    }
+
+    let template = db.template()?.unwrap_or_default();
+    let (platform, languages) = template.split_once(';').unwrap_or((&template, ""));
+    msi.arch = platform_arch(platform);
+    msi.languages = languages
+        .split(',')
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect();
+
+    msi.files = resolve_files(&mut db)?;
+    msi.properties = properties;
+    msi.hash = archive::sha256_file(path)?;
+
+    Ok(msi)
+}
+
+/// Map a summary information platform to a winget architecture
+fn platform_arch(platform: &str) -> String {
+    match platform.to_ascii_lowercase().as_str() {
+        // An empty platform means Intel
+        "" | "intel" => "x86".to_string(),
+        "x64" | "amd64" => "x64".to_string(),
+        "arm64" => "arm64".to_string(),
+        "arm" => "arm".to_string(),
+        "intel64" => "ia64".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Join the File, Component and Directory tables into install paths
+fn resolve_files(db: &mut Database) -> Result<Vec<MsiFile>, PkgError> {
+    if !db.has_table("File") {
+        return Ok(Vec::new());
+    }
+
+    let directories: HashMap<String, (Option<String>, String)> = db
+        .table("Directory")?
+        .into_iter()
+        .filter_map(|row| {
+            let id = row.string("Directory")?;
+            let parent = row.string("Directory_Parent").filter(|p| *p != id);
+            let name = target_name(&row.string("DefaultDir").unwrap_or_default());
+            Some((id, (parent, name)))
+        })
+        .collect();
+    let components: HashMap<String, String> = db
+        .table("Component")?
+        .into_iter()
+        .filter_map(|row| Some((row.string("Component")?, row.string("Directory_")?)))
+        .collect();
+
+    let mut files = Vec::new();
+    for row in db.table("File")? {
+        let name = target_name(&row.string("FileName").unwrap_or_default());
+        let dir = row
+            .string("Component_")
+            .and_then(|c| components.get(&c).cloned())
+            .unwrap_or_default();
+
+        let mut path = directory_path(&directories, &dir)?;
+        path.push_str(&name);
+        files.push(MsiFile {
+            path,
+            size: row.int("FileSize").unwrap_or(0).max(0) as u64,
+        });
+    }
+
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Install path of a directory, with a trailing slash
+///
+/// Standard folder properties resolve to their locations on 64-bit Windows.
+fn directory_path(
+    directories: &HashMap<String, (Option<String>, String)>,
+    id: &str,
+) -> Result<String, PkgError> {
+    let mut parts = Vec::new();
+    let mut current = Some(id.to_string());
+
+    while let Some(id) = current {
+        if parts.len() > MAX_DIRECTORY_DEPTH {
+            return Err(PkgError::ParseError(format!(
+                "Directory table loop at {}",
+                id
+            )));
+        }
+        if let Some(folder) = system_folder(&id) {
+            parts.push(folder.to_string());
+            break;
+        }
+        let Some((parent, name)) = directories.get(&id) else {
+            break;
+        };
+        // TARGETDIR maps to the root; `.` adds no component
+        if id != "TARGETDIR" && name != "." && !name.is_empty() {
+            parts.push(name.clone());
+        }
+        current = parent.clone();
+    }
+
+    let mut path = String::from("/");
+    for part in parts.iter().rev() {
+        path.push_str(part);
+        path.push('/');
+    }
+    Ok(path)
+}
+
+fn system_folder(id: &str) -> Option<&'static str> {
+    Some(match id {
+        "ProgramFiles64Folder" => "Program Files",
+        "ProgramFilesFolder" => "Program Files (x86)",
+        "CommonFiles64Folder" => "Program Files/Common Files",
+        "CommonFilesFolder" => "Program Files (x86)/Common Files",
+        "WindowsFolder" => "Windows",
+        "System64Folder" => "Windows/System32",
+        "SystemFolder" => "Windows/SysWOW64",
+        "FontsFolder" => "Windows/Fonts",
+        "AppDataFolder" => "Users/Default/AppData/Roaming",
+        "LocalAppDataFolder" => "Users/Default/AppData/Local",
+        "PersonalFolder" => "Users/Default/Documents",
+        "DesktopFolder" => "Users/Default/Desktop",
+        "StartMenuFolder" => "ProgramData/Microsoft/Windows/Start Menu",
+        "ProgramMenuFolder" => "ProgramData/Microsoft/Windows/Start Menu/Programs",
+        "StartupFolder" => "ProgramData/Microsoft/Windows/Start Menu/Programs/Startup",
+        "CommonAppDataFolder" => "ProgramData",
+        "TempFolder" => "Windows/Temp",
+        _ => return None,
+    })
+}
+
+/// Long target name from a `target:source` pair of `short|long` names
+fn target_name(value: &str) -> String {
+    let target = value.split(':').next().unwrap_or(value);
+    target.rsplit('|').next().unwrap_or(target).to_string()
+}
+
+/// A decoded table cell
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    Int(i32),
+    Str(String),
+}
+
+/// A table row keyed by column name
+struct Row(HashMap<String, Value>);
+
+impl Row {
+    fn string(&self, column: &str) -> Option<String> {
+        match self.0.get(column)? {
+            Value::Str(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    fn int(&self, column: &str) -> Option<i32> {
+        match self.0.get(column)? {
+            Value::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+struct Column {
+    name: String,
+    kind: u16,
+}
+
+/// An open MSI database
+struct Database {
+    cfb: cfb::CompoundFile<File>,
+    /// String pool; index 0 is the null string
+    strings: Vec<String>,
+    /// Width of a string reference in table data (2 or 3 bytes)
+    string_ref_size: usize,
+    /// Column definitions per table, in column order
+    columns: HashMap<String, Vec<Column>>,
+}
+
+impl Database {
+    fn open(path: &Path) -> Result<Self, PkgError> {
+        let cfb = cfb::open(path)
+            .map_err(|e| PkgError::ParseError(format!("Not an OLE compound file: {}", e)))?;
+        let mut db = Self {
+            cfb,
+            strings: Vec::new(),
+            string_ref_size: 2,
+            columns: HashMap::new(),
+        };
+        db.read_string_pool()?;
+        db.read_columns()?;
+        Ok(db)
+    }
+
+    /// Load `_StringPool` (lengths and refcounts) and `_StringData`
+    fn read_string_pool(&mut self) -> Result<(), PkgError> {
+        let pool = self.read_stream(&table_stream("_StringPool"))?;
+        let data = self.read_stream(&table_stream("_StringData"))?;
+        if pool.len() < 4 {
+            return Err(PkgError::ParseError("Truncated _StringPool".to_string()));
+        }
+
+        let codepage = u32::from_le_bytes(pool[0..4].try_into().unwrap());
+        if codepage & LONG_STRING_REFS != 0 {
+            self.string_ref_size = 3;
+        }
+
+        self.strings = parse_string_pool(&pool[4..], &data)?;
+        Ok(())
+    }
+
+    /// Load the column definitions from `_Columns`
+    fn read_columns(&mut self) -> Result<(), PkgError> {
+        // `_Columns` describes itself with a fixed layout
+        let string = column_type::VALID | column_type::STRING | 64;
+        let int = column_type::VALID | 2;
+        let schema = [
+            ("Table", string),
+            ("Number", int),
+            ("Name", string),
+            ("Type", int),
+        ];
+        let schema = schema
+            .iter()
+            .map(|(name, kind)| Column {
+                name: name.to_string(),
+                kind: *kind,
+            })
+            .collect::<Vec<_>>();
+
+        let mut definitions: BTreeMap<(String, i32), Column> = BTreeMap::new();
+        for row in self.read_table("_Columns", &schema)? {
+            let (Some(table), Some(number), Some(name), Some(kind)) = (
+                row.string("Table"),
+                row.int("Number"),
+                row.string("Name"),
+                row.int("Type"),
+            ) else {
+                continue;
+            };
+            definitions.insert(
+                (table, number),
+                Column {
+                    name,
+                    kind: kind as u16,
+                },
+            );
+        }
+
+        for ((table, _), column) in definitions {
+            self.columns.entry(table).or_default().push(column);
+        }
+        Ok(())
+    }
+
+    fn has_table(&self, name: &str) -> bool {
+        self.columns.contains_key(name)
+    }
+
+    /// Rows of a table; empty when the database does not define it
+    fn table(&mut self, name: &str) -> Result<Vec<Row>, PkgError> {
+        let Some(columns) = self.columns.remove(name) else {
+            return Ok(Vec::new());
+        };
+        let rows = self.read_table(name, &columns);
+        self.columns.insert(name.to_string(), columns);
+        rows
+    }
+
+    /// Decode a column-major table stream
+    fn read_table(&mut self, name: &str, columns: &[Column]) -> Result<Vec<Row>, PkgError> {
+        let stream = table_stream(name);
+        // Tables without rows have no stream
+        if !self.cfb.is_stream(&stream) {
+            return Ok(Vec::new());
+        }
+        let data = self.read_stream(&stream)?;
+
+        let widths: Vec<usize> = columns.iter().map(|c| self.column_width(c.kind)).collect();
+        let row_size: usize = widths.iter().sum();
+        if row_size == 0 || data.len() % row_size != 0 {
+            return Err(PkgError::ParseError(format!(
+                "Table {} has a partial row",
+                name
+            )));
+        }
+        let count = data.len() / row_size;
+
+        let mut rows: Vec<Row> = (0..count).map(|_| Row(HashMap::new())).collect();
+        let mut base = 0;
+        for (column, width) in columns.iter().zip(&widths) {
+            for (i, row) in rows.iter_mut().enumerate() {
+                let cell = &data[base + i * width..base + (i + 1) * width];
+                row.0
+                    .insert(column.name.clone(), self.decode_cell(column.kind, cell)?);
+            }
+            base += count * width;
+        }
+
+        Ok(rows)
+    }
+
+    fn column_width(&self, kind: u16) -> usize {
+        if is_binary(kind) {
+            2
+        } else if kind & column_type::STRING != 0 {
+            self.string_ref_size
+        } else if kind & column_type::SIZE_MASK <= 2 {
+            2
+        } else {
+            4
+        }
+    }
+
+    fn decode_cell(&self, kind: u16, cell: &[u8]) -> Result<Value, PkgError> {
+        let raw = cell
+            .iter()
+            .rev()
+            .fold(0u32, |acc, byte| (acc << 8) | *byte as u32);
+        if raw == 0 || is_binary(kind) {
+            return Ok(Value::Null);
+        }
+
+        if kind & column_type::STRING != 0 {
+            let s = self.strings.get(raw as usize).ok_or_else(|| {
+                PkgError::ParseError(format!("String reference {} out of range", raw))
+            })?;
+            return Ok(Value::Str(s.clone()));
+        }
+
+        // Integers are stored offset by half their range so that 0 means null
+        Ok(Value::Int(match cell.len() {
+            2 => (raw as u16 ^ 0x8000) as i16 as i32,
+            _ => (raw ^ 0x8000_0000) as i32,
+        }))
+    }
+
+    /// `Template` property of the summary information stream
+    fn template(&mut self) -> Result<Option<String>, PkgError> {
+        let stream = "/\u{5}SummaryInformation";
+        if !self.cfb.is_stream(stream) {
+            return Ok(None);
+        }
+        let data = self.read_stream(stream)?;
+
+        let u32_at = |offset: usize| -> Option<u32> {
+            Some(u32::from_le_bytes(
+                data.get(offset..offset + 4)?.try_into().ok()?,
+            ))
+        };
+
+        // Header (28 bytes), then FMTID and offset of the first section
+        let Some(section) = u32_at(28 + 16).map(|o| o as usize) else {
+            return Ok(None);
+        };
+        let count = u32_at(section + 4).unwrap_or(0) as usize;
+        for i in 0..count.min(64) {
+            let entry = section + 8 + i * 8;
+            let (Some(pid), Some(offset)) = (u32_at(entry), u32_at(entry + 4)) else {
+                break;
+            };
+            let offset = section + offset as usize;
+            if pid != PID_TEMPLATE || u32_at(offset) != Some(VT_LPSTR) {
+                continue;
+            }
+            let len = u32_at(offset + 4).unwrap_or(0) as usize;
+            let Some(bytes) = data.get(offset + 8..offset + 8 + len) else {
+                break;
+            };
+            let bytes = bytes.split(|b| *b == 0).next().unwrap_or_default();
+            return Ok(Some(decode_string(bytes)));
+        }
+
+        Ok(None)
+    }
+
+    fn read_stream(&mut self, name: &str) -> Result<Vec<u8>, PkgError> {
+        let stream = self.cfb.open_stream(name).map_err(|e| {
+            PkgError::ParseError(format!("Missing MSI stream {}: {}", stream_label(name), e))
+        })?;
+        if stream.len() > MAX_STREAM_SIZE {
+            return Err(PkgError::ParseError(format!(
+                "MSI stream {} is too large",
+                stream_label(name)
+            )));
+        }
+
+        let mut data = Vec::new();
+        stream
+            .take(MAX_STREAM_SIZE)
+            .read_to_end(&mut data)
+            .map_err(PkgError::IoError)?;
+        Ok(data)
+    }
+}
+
+/// Binary columns hold stream references, not strings
+fn is_binary(kind: u16) -> bool {
+    kind & !column_type::NULLABLE == column_type::VALID | column_type::STRING
+}
+
+/// Decode the `_StringPool` entries after the codepage against `_StringData`
+///
+/// String ids start at 1, so index 0 is an empty string.
+fn parse_string_pool(entries: &[u8], data: &[u8]) -> Result<Vec<String>, PkgError> {
+    let mut strings = vec![String::new()];
+    let mut offset = 0usize;
+    let mut entries = entries.chunks_exact(4);
+    while let Some(entry) = entries.next() {
+        let mut length = u16::from_le_bytes([entry[0], entry[1]]) as usize;
+        let refcount = u16::from_le_bytes([entry[2], entry[3]]);
+        // Strings over 64 KiB: a zero length with the real refcount, then
+        // an entry holding the low and high 16 bits of the length
+        if length == 0 && refcount != 0 {
+            let next = entries
+                .next()
+                .ok_or_else(|| PkgError::ParseError("Truncated _StringPool".to_string()))?;
+            let low = u16::from_le_bytes([next[0], next[1]]) as usize;
+            let high = u16::from_le_bytes([next[2], next[3]]) as usize;
+            length = (high << 16) | low;
+        }
+
+        let bytes = data
+            .get(offset..offset + length)
+            .ok_or_else(|| PkgError::ParseError("String pool overruns _StringData".to_string()))?;
+        strings.push(decode_string(bytes));
+        offset += length;
+    }
+    Ok(strings)
+}
+
+/// Strings are in the database codepage; fall back to Latin-1 when not UTF-8
+fn decode_string(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => s.to_string(),
+        Err(_) => bytes.iter().map(|b| *b as char).collect(),
+    }
+}
+
+/// Compound file path of a table stream
+///
+/// Table names are packed two characters per UTF-16 code unit from a
+/// 64-symbol alphabet and prefixed with U+4840.
+fn table_stream(name: &str) -> String {
+    fn symbol(c: char) -> Option<u32> {
+        Some(match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'A'..='Z' => c as u32 - 'A' as u32 + 10,
+            'a'..='z' => c as u32 - 'a' as u32 + 36,
+            '.' => 62,
+            '_' => 63,
+            _ => return None,
+        })
+    }
+
+    let mut encoded = String::from("/\u{4840}");
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        let packed = match (symbol(c), chars.peek().copied().and_then(symbol)) {
+            (Some(first), Some(second)) => {
+                chars.next();
+                0x3800 + first + (second << 6)
+            }
+            (Some(first), None) => 0x4800 + first,
+            (None, _) => c as u32,
+        };
+        encoded.push(char::from_u32(packed).unwrap_or(c));
+    }
+    encoded
+}
+
+/// Readable name of a packed stream path, for error messages
+fn stream_label(path: &str) -> String {
+    const ALPHABET: &[u8; 64] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz._";
+
+    let mut label = String::new();
+    for c in path.trim_start_matches('/').chars() {
+        let code = c as u32;
+        match code {
+            0x3800..0x4800 => {
+                label.push(ALPHABET[((code - 0x3800) & 0x3f) as usize] as char);
+                label.push(ALPHABET[((code - 0x3800) >> 6) as usize] as char);
+            }
+            0x4800..0x4840 => label.push(ALPHABET[(code - 0x4800) as usize] as char),
+            0x4840 => {}
+            0x00..0x20 => {}
+            _ => label.push(c),
+        }
+    }
+    label
+}
+
+impl From<MsiPackage> for PackageInfo {
+    fn from(msi: MsiPackage) -> Self {
+        let property = |name: &str| msi.properties.get(name).cloned().unwrap_or_default();
+        let description = property("ARPCOMMENTS");
+        let homepage = property("ARPURLINFOABOUT");
+
+        PackageInfo {
+            name: msi.product_name,
+            version: msi.product_version,
+            release: 1,
+            arch: msi.arch,
+            format: PackageFormat::Msi,
+            description,
+            maintainer: msi.manufacturer,
+            license: String::new(),
+            homepage,
+            size: msi.size,
+            installed_size: msi.files.iter().map(|f| f.size).sum(),
+            dependencies: Vec::new(),
+            conflicts: Vec::new(),
+            provides: Vec::new(),
+            replaces: Vec::new(),
+            files: msi.files.iter().map(|f| f.path.clone()).collect(),
+            file_entries: msi
+                .files
+                .into_iter()
+                .map(|f| FileEntry {
+                    path: f.path,
+                    mode: 0o100644,
+                    size: f.size,
+                    ..Default::default()
+                })
+                .collect(),
+            checksum: msi.hash,
+            scripts: Vec::new(),
+            triggers: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(length: u16, refcount: u16) -> Vec<u8> {
+        [length.to_le_bytes(), refcount.to_le_bytes()].concat()
+    }
+
+    #[test]
+    fn string_pool_long_string_takes_its_length_from_the_next_entry() {
+        let long = "x".repeat(0x1_0005);
+        let data = format!("ab{}c", long);
+        let pool = [
+            entry(2, 1),
+            // Zero length with the refcount, then low and high length words
+            entry(0, 3),
+            entry(0x0005, 0x0001),
+            entry(0, 0),
+            entry(1, 1),
+        ]
+        .concat();
+
+        let strings = parse_string_pool(&pool, data.as_bytes()).unwrap();
+        assert_eq!(strings.len(), 5);
+        assert_eq!(strings[1], "ab");
+        assert_eq!(strings[2], long);
+        assert_eq!(strings[3], "");
+        assert_eq!(strings[4], "c");
+    }
+
+    #[test]
+    fn string_pool_overrun_is_an_error() {
+        assert!(parse_string_pool(&entry(4, 1), b"ab").is_err());
+    }
+}
@@ -0,0 +1,555 @@
+//! Windows MSIX Adapter
+//!
+//! Inspects MSIX/APPX packages: a ZIP archive with an `AppxManifest.xml`
+//! describing the package identity and an `AppxBlockMap.xml` listing a hash
+//! for every 64 KiB block of every payload file.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use base64::Engine;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use crate::archive;
+use crate::reader::PackageReader;
+use crate::{
+    ConstraintOp, Dependency, FileEntry, PackageFormat, PackageInfo, PkgError, VersionConstraint,
+};
+
+/// Largest manifest or block map we are willing to parse
+const MAX_XML_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Uncompressed size covered by one block map hash
+const BLOCK_SIZE: usize = 64 * 1024;
+
+const MANIFEST: &str = "AppxManifest.xml";
+const BLOCK_MAP: &str = "AppxBlockMap.xml";
+
+/// Container members that are not part of the payload
+const FOOTPRINT_FILES: &[&str] = &[
+    BLOCK_MAP,
+    "AppxSignature.p7x",
+    "AppxMetadata/CodeIntegrity.cat",
+    "[Content_Types].xml",
+];
+
+/// Base32 alphabet of publisher IDs
+const PUBLISHER_ID_ALPHABET: &[u8; 32] = b"0123456789abcdefghjkmnpqrstvwxyz";
+
+/// Metadata of a local MSIX/APPX package
+#[derive(Debug, Clone, Default)]
+pub struct MsixPackage {
+    /// Identity Name
+    pub name: String,
+    pub version: String,
+    /// Publisher distinguished name (`CN=...`)
+    pub publisher: String,
+    /// ProcessorArchitecture (x86, x64, arm64, neutral)
+    pub arch: String,
+    pub display_name: String,
+    pub publisher_display_name: String,
+    pub description: String,
+    /// PackageDependency entries
+    pub dependencies: Vec<MsixDependency>,
+    /// TargetDeviceFamily entries
+    pub device_families: Vec<MsixDependency>,
+    pub applications: Vec<MsixApplication>,
+    /// Payload files (`\`-separated, as in the block map) and sizes
+    pub files: Vec<(String, u64)>,
+    pub size: u64,
+    /// SHA-256 of the whole file, hex
+    pub hash: String,
+}
+
+/// A dependency on another package or a device family
+#[derive(Debug, Clone, Default)]
+pub struct MsixDependency {
+    pub name: String,
+    pub min_version: String,
+    pub publisher: String,
+}
+
+/// An application entry point
+#[derive(Debug, Clone, Default)]
+pub struct MsixApplication {
+    pub id: String,
+    pub executable: String,
+    pub entry_point: String,
+}
+
+impl MsixPackage {
+    /// Publisher ID: base32 of the first 64 bits of SHA-256 over the
+    /// UTF-16LE publisher name
+    pub fn publisher_id(&self) -> String {
+        let utf16: Vec<u8> = self
+            .publisher
+            .encode_utf16()
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect();
+        let digest = Sha256::digest(&utf16);
+        // 64 bits padded with a zero bit to 13 groups of 5
+        let bits = u64::from_be_bytes(digest[..8].try_into().unwrap()) as u128;
+        let bits = bits << 1;
+
+        (0..13)
+            .rev()
+            .map(|i| PUBLISHER_ID_ALPHABET[((bits >> (i * 5)) & 0x1f) as usize] as char)
+            .collect()
+    }
+
+    /// `Name_PublisherId`, as used by winget and the Windows app model
+    pub fn family_name(&self) -> String {
+        format!("{}_{}", self.name, self.publisher_id())
+    }
+
+    /// `Name_Version_Arch__PublisherId`, the install directory name
+    pub fn full_name(&self) -> String {
+        format!(
+            "{}_{}_{}__{}",
+            self.name,
+            self.version,
+            self.arch,
+            self.publisher_id()
+        )
+    }
+}
+
+/// Reader for MSIX/APPX packages
+pub struct MsixReader;
+
+impl PackageReader for MsixReader {
+    fn read(&self, path: &Path) -> Result<PackageInfo, PkgError> {
+        parse_msix(path)
+    }
+}
+
+/// Parse a Windows .msix/.appx
+pub fn parse_msix(path: &Path) -> Result<PackageInfo, PkgError> {
+    read_msix(path).map(PackageInfo::from)
+}
+
+/// Read the manifest of a Windows .msix/.appx and check its block map
+pub fn read_msix(path: &Path) -> Result<MsixPackage, PkgError> {
+    let file = File::open(path).map_err(PkgError::IoError)?;
+    let size = file.metadata().map_err(PkgError::IoError)?.len();
+    let mut zip = zip::ZipArchive::new(file).map_err(zip_error)?;
+
+    let manifest = read_xml(&mut zip, MANIFEST)?;
+    let mut msix = parse_manifest(&manifest)?;
+
+    let block_map = read_xml(&mut zip, BLOCK_MAP)?;
+    msix.files = verify_block_map(&mut zip, &block_map)?;
+
+    msix.size = size;
+    msix.hash = archive::sha256_file(path)?;
+    Ok(msix)
+}
+
+fn zip_error(e: zip::result::ZipError) -> PkgError {
+    PkgError::ExtractionError(format!("Invalid MSIX archive: {}", e))
+}
+
+fn xml_error(name: &str, e: roxmltree::Error) -> PkgError {
+    PkgError::ParseError(format!("Invalid {}: {}", name, e))
+}
+
+fn read_xml(zip: &mut zip::ZipArchive<File>, name: &str) -> Result<String, PkgError> {
+    let entry = zip.by_name(name).map_err(zip_error)?;
+    if entry.size() > MAX_XML_SIZE {
+        return Err(PkgError::ExtractionError(format!("{} is too large", name)));
+    }
+
+    let mut data = Vec::new();
+    entry
+        .take(MAX_XML_SIZE)
+        .read_to_end(&mut data)
+        .map_err(PkgError::IoError)?;
+    // Manifests are UTF-8, with or without a byte order mark
+    let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(&data);
+    String::from_utf8(data.to_vec())
+        .map_err(|_| PkgError::ParseError(format!("{} is not UTF-8", name)))
+}
+
+/// Parse `AppxManifest.xml`
+///
+/// Elements are matched by local name so that every manifest schema
+/// version (foundation, uap, desktop, ...) is accepted.
+pub fn parse_manifest(content: &str) -> Result<MsixPackage, PkgError> {
+    let doc = roxmltree::Document::parse(content).map_err(|e| xml_error(MANIFEST, e))?;
+    let root = doc.root_element();
+    if root.tag_name().name() != "Package" {
+        return Err(PkgError::ParseError(format!(
+            "{} root is <{}>, not <Package>",
+            MANIFEST,
+            root.tag_name().name()
+        )));
+    }
+
+    let attribute = |node: roxmltree::Node<'_, '_>, name: &str| {
+        node.attribute(name).unwrap_or_default().to_string()
+    };
+    let dependency = |node: roxmltree::Node<'_, '_>| MsixDependency {
+        name: attribute(node, "Name"),
+        min_version: attribute(node, "MinVersion"),
+        publisher: attribute(node, "Publisher"),
+    };
+
+    let identity = child(root, "Identity")
+        .ok_or_else(|| PkgError::ParseError(format!("{} has no <Identity>", MANIFEST)))?;
+    let mut msix = MsixPackage {
+        name: attribute(identity, "Name"),
+        version: attribute(identity, "Version"),
+        publisher: attribute(identity, "Publisher"),
+        arch: identity
+            .attribute("ProcessorArchitecture")
+            .unwrap_or("neutral")
+            .to_string(),
+        ..Default::default()
+    };
+    if msix.name.is_empty() || msix.version.is_empty() {
+        return Err(PkgError::ParseError(
+            "Identity is missing Name or Version".to_string(),
+        ));
+    }
+
+    if let Some(properties) = child(root, "Properties") {
+        let text = |name: &str| {
+            child(properties, name)
+                .and_then(|n| n.text())
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        };
+        msix.display_name = text("DisplayName");
+        msix.publisher_display_name = text("PublisherDisplayName");
+        msix.description = text("Description");
+    }
+
+    if let Some(dependencies) = child(root, "Dependencies") {
+        for node in dependencies.children().filter(|n| n.is_element()) {
+            match node.tag_name().name() {
+                "PackageDependency" => msix.dependencies.push(dependency(node)),
+                "TargetDeviceFamily" => msix.device_families.push(dependency(node)),
+                _ => {}
+            }
+        }
+    }
+
+    if let Some(applications) = child(root, "Applications") {
+        msix.applications = applications
+            .children()
+            .filter(|n| n.is_element() && n.tag_name().name() == "Application")
+            .map(|node| MsixApplication {
+                id: attribute(node, "Id"),
+                executable: attribute(node, "Executable"),
+                entry_point: attribute(node, "EntryPoint"),
+            })
+            .collect();
+    }
+
+    Ok(msix)
+}
+
+/// First child element with the given local name
+fn child<'a, 'input>(
+    parent: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    parent
+        .children()
+        .find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+/// Check every payload file against `AppxBlockMap.xml`
+///
+/// Each listed file must exist with the recorded size and matching block
+/// hashes, and every payload member of the archive must be listed.
+/// Returns the files and their sizes.
+fn verify_block_map(
+    zip: &mut zip::ZipArchive<File>,
+    content: &str,
+) -> Result<Vec<(String, u64)>, PkgError> {
+    let doc = roxmltree::Document::parse(content).map_err(|e| xml_error(BLOCK_MAP, e))?;
+    let root = doc.root_element();
+    let method = root.attribute("HashMethod").unwrap_or_default();
+    let hash: fn(&[u8]) -> Vec<u8> = match method.rsplit('#').next().unwrap_or_default() {
+        "sha256" => |data| Sha256::digest(data).to_vec(),
+        "sha384" => |data| Sha384::digest(data).to_vec(),
+        "sha512" => |data| Sha512::digest(data).to_vec(),
+        _ => {
+            return Err(PkgError::ParseError(format!(
+                "Unsupported block map hash method {}",
+                method
+            )));
+        }
+    };
+
+    // Archive member names are percent-encoded with `/` separators
+    let mut members: HashMap<String, usize> = HashMap::new();
+    for i in 0..zip.len() {
+        let entry = zip.by_index_raw(i).map_err(zip_error)?;
+        if entry.is_dir() || FOOTPRINT_FILES.contains(&entry.name()) {
+            continue;
+        }
+        members.insert(percent_decode(entry.name()).replace('/', "\\"), i);
+    }
+
+    let mut files = Vec::new();
+    for node in root
+        .children()
+        .filter(|n| n.is_element() && n.tag_name().name() == "File")
+    {
+        let name = node.attribute("Name").unwrap_or_default().to_string();
+        let size: u64 = node
+            .attribute("Size")
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| PkgError::ParseError(format!("Block map entry {} has no Size", name)))?;
+        let index = members.remove(&name).ok_or_else(|| {
+            PkgError::ExtractionError(format!("{} is listed in the block map but missing", name))
+        })?;
+
+        let expected: Vec<&str> = node
+            .children()
+            .filter(|n| n.is_element() && n.tag_name().name() == "Block")
+            .map(|n| n.attribute("Hash").unwrap_or_default())
+            .collect();
+
+        let mut entry = zip.by_index(index).map_err(zip_error)?;
+        if entry.size() != size {
+            return Err(PkgError::ExtractionError(format!(
+                "Size mismatch for {}: block map says {}, archive has {}",
+                name,
+                size,
+                entry.size()
+            )));
+        }
+        if expected.len() as u64 != size.div_ceil(BLOCK_SIZE as u64) {
+            return Err(PkgError::ExtractionError(format!(
+                "Block count mismatch for {}",
+                name
+            )));
+        }
+
+        let mut block = vec![0u8; BLOCK_SIZE];
+        for expected in expected {
+            let len = read_block(&mut entry, &mut block)?;
+            let actual = base64::engine::general_purpose::STANDARD.encode(hash(&block[..len]));
+            if actual != expected {
+                return Err(PkgError::ExtractionError(format!(
+                    "Block hash mismatch for {}",
+                    name
+                )));
+            }
+        }
+
+        files.push((name, size));
+    }
+
+    if let Some(unlisted) = members.keys().next() {
+        return Err(PkgError::ExtractionError(format!(
+            "{} is not listed in the block map",
+            unlisted
+        )));
+    }
+
+    Ok(files)
+}
+
+/// Fill `buf` from `reader`, short only at end of input
+fn read_block(reader: &mut impl Read, buf: &mut [u8]) -> Result<usize, PkgError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = reader.read(&mut buf[filled..]).map_err(PkgError::IoError)?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
+
+/// Decode `%XX` escapes in an archive member name
+fn percent_decode(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+impl From<MsixPackage> for PackageInfo {
+    fn from(msix: MsixPackage) -> Self {
+        // Installed like Windows does: one directory per package full name
+        let root = format!("/Program Files/WindowsApps/{}", msix.full_name());
+        let file_entries: Vec<FileEntry> = msix
+            .files
+            .iter()
+            .map(|(name, size)| FileEntry {
+                path: format!("{}/{}", root, name.replace('\\', "/")),
+                mode: 0o100644,
+                size: *size,
+                ..Default::default()
+            })
+            .collect();
+
+        PackageInfo {
+            name: msix.name,
+            version: msix.version,
+            release: 1,
+            arch: msix.arch,
+            format: PackageFormat::Msix,
+            description: msix.description,
+            maintainer: if msix.publisher_display_name.is_empty() {
+                msix.publisher
+            } else {
+                msix.publisher_display_name
+            },
+            license: String::new(),
+            homepage: String::new(),
+            size: msix.size,
+            installed_size: msix.files.iter().map(|(_, size)| size).sum(),
+            dependencies: msix
+                .dependencies
+                .into_iter()
+                .map(|dep| {
+                    let constraint = (!dep.min_version.is_empty()).then_some(VersionConstraint {
+                        operator: ConstraintOp::Ge,
+                        version: dep.min_version,
+                    });
+                    Dependency::new(dep.name, constraint)
+                })
+                .collect(),
+            conflicts: Vec::new(),
+            provides: Vec::new(),
+            replaces: Vec::new(),
+            files: file_entries.iter().map(|e| e.path.clone()).collect(),
+            file_entries,
+            checksum: msix.hash,
+            scripts: Vec::new(),
+            triggers: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Run `verify_block_map` over an archive of `members` and a block map
+    /// listing `listed` (block map name, content)
+    fn check(
+        name: &str,
+        members: &[(&str, &[u8])],
+        listed: &[(&str, &[u8])],
+    ) -> Result<Vec<(String, u64)>, PkgError> {
+        let path = std::env::temp_dir().join(format!(
+            "rpm-next-msix-{}-{}.msix",
+            name,
+            std::process::id()
+        ));
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        for (member, data) in members {
+            writer
+                .start_file(*member, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut block_map =
+            String::from(r#"<BlockMap HashMethod="http://www.w3.org/2001/04/xmlenc#sha256">"#);
+        for (file, data) in listed {
+            block_map.push_str(&format!(r#"<File Name="{}" Size="{}">"#, file, data.len()));
+            for block in data.chunks(BLOCK_SIZE) {
+                let hash = base64::engine::general_purpose::STANDARD.encode(Sha256::digest(block));
+                block_map.push_str(&format!(r#"<Block Hash="{}"/>"#, hash));
+            }
+            block_map.push_str("</File>");
+        }
+        block_map.push_str("</BlockMap>");
+
+        let mut zip = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let result = verify_block_map(&mut zip, &block_map);
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    const FOOTPRINT: &[(&str, &[u8])] = &[
+        (BLOCK_MAP, b"<BlockMap/>"),
+        ("AppxSignature.p7x", b"PKCX"),
+        ("AppxMetadata/CodeIntegrity.cat", b"catalog"),
+        ("[Content_Types].xml", b"<Types/>"),
+    ];
+
+    #[test]
+    fn block_map_covers_exactly_the_payload() {
+        let large = vec![7u8; BLOCK_SIZE + 100];
+        let payload: &[(&str, &[u8])] =
+            &[(MANIFEST, b"<Package/>"), ("Assets/Logo%20Big.png", &large)];
+        let members = [FOOTPRINT, payload].concat();
+        let listed: &[(&str, &[u8])] =
+            &[(MANIFEST, b"<Package/>"), ("Assets\\Logo Big.png", &large)];
+
+        let files = check("good", &members, listed).unwrap();
+        assert_eq!(
+            files,
+            [
+                (MANIFEST.to_string(), 10),
+                ("Assets\\Logo Big.png".to_string(), large.len() as u64)
+            ]
+        );
+
+        let mut tampered = large.clone();
+        tampered[BLOCK_SIZE + 1] = 0;
+        let tampered_listed: &[(&str, &[u8])] = &[
+            (MANIFEST, b"<Package/>"),
+            ("Assets\\Logo Big.png", &tampered),
+        ];
+        let err = check("tampered", &members, tampered_listed).unwrap_err();
+        assert!(
+            matches!(err, PkgError::ExtractionError(ref e) if e.contains("Block hash mismatch"))
+        );
+
+        let missing_listed: &[(&str, &[u8])] = &[
+            (MANIFEST, b"<Package/>"),
+            ("Assets\\Logo Big.png", &large),
+            ("App.exe", b"MZ"),
+        ];
+        let err = check("missing", &members, missing_listed).unwrap_err();
+        assert!(matches!(err, PkgError::ExtractionError(ref e) if e.contains("missing")));
+
+        let err = check("unlisted", &members, &listed[..1]).unwrap_err();
+        assert!(
+            matches!(err, PkgError::ExtractionError(ref e) if e.contains("not listed in the block map"))
+        );
+    }
+
+    #[test]
+    fn publisher_id_matches_windows() {
+        let msix = MsixPackage {
+            publisher:
+                "CN=Microsoft Corporation, O=Microsoft Corporation, L=Redmond, S=Washington, C=US"
+                    .to_string(),
+            ..Default::default()
+        };
+        assert_eq!(msix.publisher_id(), "8wekyb3d8bbwe");
+    }
+}
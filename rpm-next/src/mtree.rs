@@ -0,0 +1,291 @@
+//! MTREE Parser
+//!
+//! Reads the `.MTREE` manifest shipped in native and pacman packages
+//! (libarchive's mtree format, usually gzip-compressed).
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use crate::{FileEntry, PkgError};
+
+/// Parse an mtree manifest into per-file entries
+///
+/// Accepts gzip-compressed or plain input. Paths are returned rooted
+/// (`/usr/bin/foo`); the `.` entry itself is skipped.
+pub fn parse_mtree(data: &[u8]) -> Result<Vec<FileEntry>, PkgError> {
+    let mut content = String::new();
+    if data.starts_with(&[0x1f, 0x8b]) {
+        flate2::read::MultiGzDecoder::new(data)
+            .read_to_string(&mut content)
+            .map_err(PkgError::IoError)?;
+    } else {
+        content = String::from_utf8_lossy(data).into_owned();
+    }
+
+    let mut defaults: HashMap<String, String> = HashMap::new();
+    let mut cwd: Vec<String> = Vec::new();
+    let mut entries = Vec::new();
+
+    for line in logical_lines(&content) {
+        let mut words = line.split_whitespace();
+        let Some(first) = words.next() else {
+            continue;
+        };
+
+        match first {
+            "/set" => {
+                for (key, value) in words.filter_map(keyword) {
+                    defaults.insert(key.to_string(), value.to_string());
+                }
+                continue;
+            }
+            "/unset" => {
+                for key in words {
+                    if key == "all" {
+                        defaults.clear();
+                    } else {
+                        defaults.remove(key);
+                    }
+                }
+                continue;
+            }
+            ".." => {
+                cwd.pop();
+                continue;
+            }
+            _ => {}
+        }
+
+        let mut keywords = defaults.clone();
+        for (key, value) in words.filter_map(keyword) {
+            keywords.insert(key.to_string(), value.to_string());
+        }
+
+        let name = unescape(first)?;
+        // Names without a slash are relative to the current directory
+        let path = if name.contains('/') {
+            name.trim_start_matches("./")
+                .trim_start_matches('/')
+                .to_string()
+        } else {
+            let mut parts = cwd.clone();
+            parts.push(name.clone());
+            parts.join("/")
+        };
+        let path = path.trim_end_matches('/').to_string();
+
+        let entry = file_entry(&path, &keywords)?;
+        if !name.contains('/') && entry.is_dir() && name != "." {
+            cwd.push(name);
+        }
+        if path.is_empty() || path == "." {
+            continue;
+        }
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// Build a [`FileEntry`] from the keywords in effect for a path
+fn file_entry(path: &str, keywords: &HashMap<String, String>) -> Result<FileEntry, PkgError> {
+    let get = |key: &str| keywords.get(key).map(|s| s.as_str());
+    let number = |key: &str| -> Result<u64, PkgError> {
+        match get(key) {
+            Some(value) => value.parse().map_err(|_| {
+                PkgError::ParseError(format!("Invalid mtree {} for {}: {}", key, path, value))
+            }),
+            None => Ok(0),
+        }
+    };
+
+    let file_type = match get("type").unwrap_or("file") {
+        "file" => 0o100000,
+        "dir" => 0o040000,
+        "link" => 0o120000,
+        "block" => 0o060000,
+        "char" => 0o020000,
+        "fifo" => 0o010000,
+        "socket" => 0o140000,
+        other => {
+            return Err(PkgError::ParseError(format!(
+                "Unknown mtree type {} for {}",
+                other, path
+            )));
+        }
+    };
+    let perms = match get("mode") {
+        Some(mode) => u32::from_str_radix(mode, 8).map_err(|_| {
+            PkgError::ParseError(format!("Invalid mtree mode for {}: {}", path, mode))
+        })?,
+        None => 0,
+    };
+
+    // `time` is seconds with an optional fractional part
+    let mtime = match get("time") {
+        Some(time) => time.split('.').next().unwrap_or("0").parse().map_err(|_| {
+            PkgError::ParseError(format!("Invalid mtree time for {}: {}", path, time))
+        })?,
+        None => 0,
+    };
+
+    let owner = |name: &str, id: &str| {
+        get(name)
+            .or_else(|| get(id))
+            .map(|s| s.to_string())
+            .unwrap_or_default()
+    };
+
+    Ok(FileEntry {
+        path: format!("/{}", path),
+        mode: file_type | (perms & 0o7777),
+        size: number("size")?,
+        mtime,
+        digest: get("sha256digest")
+            .or_else(|| get("sha256"))
+            .unwrap_or_default()
+            .to_string(),
+        user: owner("uname", "uid"),
+        group: owner("gname", "gid"),
+        link_target: get("link").map(unescape).transpose()?.unwrap_or_default(),
+        flags: 0,
+        xattrs: Vec::new(),
+    })
+}
+
+/// Join backslash-continued lines and drop comments and blank lines
+fn logical_lines(content: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for line in content.lines() {
+        if let Some(stripped) = line.strip_suffix('\\') {
+            current.push_str(stripped);
+            current.push(' ');
+            continue;
+        }
+        current.push_str(line);
+        let line = std::mem::take(&mut current);
+        let trimmed = line.trim();
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            lines.push(trimmed.to_string());
+        }
+    }
+
+    lines
+}
+
+fn keyword(word: &str) -> Option<(&str, &str)> {
+    word.split_once('=')
+}
+
+/// Decode `\ooo` octal escapes (e.g. `\040` for a space)
+fn unescape(s: &str) -> Result<String, PkgError> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            let octal = bytes
+                .get(i + 1..i + 4)
+                .and_then(|d| std::str::from_utf8(d).ok())
+                .and_then(|d| u8::from_str_radix(d, 8).ok());
+            match octal {
+                Some(byte) => {
+                    out.push(byte);
+                    i += 4;
+                    continue;
+                }
+                None => {
+                    return Err(PkgError::ParseError(format!(
+                        "Invalid mtree escape in {}",
+                        s
+                    )));
+                }
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8(out).map_err(|_| PkgError::ParseError(format!("Invalid mtree path {}", s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MTREE: &str = "#mtree
+/set type=file uid=0 gid=0 mode=644
+./.BUILDINFO time=1700000000.5 size=10 sha256digest=abc
+./usr time=1700000000.0 mode=755 type=dir
+./usr/bin time=1700000000.0 mode=755 type=dir
+./usr/bin/tool time=1700000000.0 mode=4755 size=4 \\
+    sha256digest=def
+./usr/bin/alias time=1700000000.0 mode=777 type=link link=tool
+/unset uid
+./usr/share/My\\040File time=1 uname=alpm
+";
+
+    #[test]
+    fn entries_take_set_defaults_and_their_own_keywords() {
+        let entries = parse_mtree(MTREE.as_bytes()).unwrap();
+        let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "/.BUILDINFO",
+                "/usr",
+                "/usr/bin",
+                "/usr/bin/tool",
+                "/usr/bin/alias",
+                "/usr/share/My File"
+            ]
+        );
+
+        assert_eq!(entries[0].mode, 0o100644);
+        assert_eq!(entries[0].mtime, 1_700_000_000);
+        assert_eq!(entries[0].size, 10);
+        assert_eq!(entries[0].digest, "abc");
+        assert_eq!(entries[0].user, "0");
+        assert!(entries[1].is_dir());
+        assert_eq!(entries[3].mode, 0o104755);
+        assert_eq!(entries[3].digest, "def");
+        assert_eq!(entries[4].mode, 0o120777);
+        assert_eq!(entries[4].link_target, "tool");
+        assert_eq!(entries[5].user, "alpm");
+        assert_eq!(entries[5].group, "0");
+    }
+
+    #[test]
+    fn relative_names_follow_the_current_directory() {
+        let mtree = "etc type=dir mode=755
+    pacman.conf mode=644 size=1
+    ..
+root.txt mode=600
+";
+        let entries = parse_mtree(mtree.as_bytes()).unwrap();
+        let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["/etc", "/etc/pacman.conf", "/root.txt"]);
+        assert_eq!(entries[2].mode, 0o100600);
+    }
+
+    #[test]
+    fn gzip_input_is_decompressed() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(MTREE.as_bytes()).unwrap();
+        let gz = encoder.finish().unwrap();
+        assert_eq!(parse_mtree(&gz).unwrap().len(), 6);
+    }
+
+    #[test]
+    fn invalid_keywords_are_errors() {
+        assert!(parse_mtree(b"./a type=door").is_err());
+        assert!(parse_mtree(b"./a mode=999").is_err());
+        assert!(parse_mtree(b"./a size=big").is_err());
+        assert!(parse_mtree(b"./a\\9").is_err());
+    }
+}
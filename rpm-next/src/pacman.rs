@@ -110,9 +110,9 @@ fn apply_field(pkg: &mut PacmanPackage, field: &str, values: &[String]) {
 }
 
 /// Parse a pacman dependency string like "glibc>=2.17"
-fn parse_pacman_dep(s: &str) -> Dependency {
-    // Remove description after colon
-    let s = s.split(':').next().unwrap_or(s).trim();
+pub fn parse_pacman_dep(s: &str) -> Dependency {
+    // Remove description after colon (but keep epochs like "1:2.0")
+    let s = s.split_once(": ").map_or(s, |(dep, _)| dep).trim();
 
     // Check for version constraint
     if let Some(idx) = s.find(|c| c == '>' || c == '<' || c == '=') {
@@ -120,15 +120,9 @@ fn parse_pacman_dep(s: &str) -> Dependency {
         let constraint_str = &s[idx..];
 
         let constraint = parse_pacman_version_constraint(constraint_str);
-        Dependency {
-            name,
-            version_constraint: constraint,
-        }
+        Dependency::new(name, constraint)
     } else {
-        Dependency {
-            name: s.to_string(),
-            version_constraint: None,
-        }
+        Dependency::new(s, None)
     }
 }
 
@@ -174,7 +168,10 @@ impl From<PacmanPackage> for PackageInfo {
             provides: pac.provides,
             replaces: pac.replaces,
             files: Vec::new(),
+            file_entries: Vec::new(),
             checksum: pac.sha256sum,
+            scripts: Vec::new(),
+            triggers: Vec::new(),
         }
     }
 }
@@ -241,6 +238,11 @@ impl PacmanRepository {
         self.packages.get(name)?.last()
     }
 
+    /// Every synced package version
+    pub fn packages(&self) -> impl Iterator<Item = &PacmanPackage> {
+        self.packages.values().flatten()
+    }
+
     /// Get download URL for a package
     pub fn get_download_url(&self, repo: &str, pkg: &PacmanPackage) -> String {
         format!("{}/{}/os/{}/{}", self.mirror, repo, pkg.arch, pkg.filename)
@@ -2,66 +2,263 @@
 //!
 //! Handles Redox native package format (.pkg.tar.zst)
 
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
 use std::path::Path;
 
-use crate::{Dependency, PackageFormat, PackageInfo, PkgError};
+use sha2::{Digest, Sha256};
+
+use crate::archive::{self, Compression};
+use crate::extract::{self, Extractor};
+use crate::mtree;
+use crate::pacman::parse_pacman_dep;
+use crate::reader::PackageReader;
+use crate::scriptlet::{ScriptPhase, Scriptlet};
+use crate::{Dependency, PackageFormat, PackageInfo, PkgError, file_flags};
+
+/// Contents of a native package archive
+pub struct NativePackage {
+    pub info: PackageInfo,
+    pub pkginfo: PkgInfo,
+}
+
+/// `.INSTALL` functions and when they run
+const INSTALL_FUNCTIONS: [(&str, ScriptPhase); 6] = [
+    ("pre_install", ScriptPhase::PreInstall),
+    ("post_install", ScriptPhase::PostInstall),
+    ("pre_upgrade", ScriptPhase::PreUpgrade),
+    ("post_upgrade", ScriptPhase::PostUpgrade),
+    ("pre_remove", ScriptPhase::PreRemove),
+    ("post_remove", ScriptPhase::PostRemove),
+];
+
+/// Reader for native packages
+pub struct PkgReader;
+
+impl PackageReader for PkgReader {
+    fn read(&self, path: &Path) -> Result<PackageInfo, PkgError> {
+        parse_pkg(path)
+    }
+
+    fn extract(&self, path: &Path, extractor: &mut Extractor) -> Result<(), PkgError> {
+        extract_pkg(path, extractor)
+    }
+}
 
 /// Parse a native .pkg.tar.zst package
 pub fn parse_pkg(path: &Path) -> Result<PackageInfo, PkgError> {
+    read_pkg(path).map(|pkg| pkg.info)
+}
+
+/// Read the metadata members and file list of a native package
+pub fn read_pkg(path: &Path) -> Result<NativePackage, PkgError> {
     // Native format: tar archive compressed with zstd
     // Contains:
     // - .PKGINFO (metadata)
     // - .INSTALL (optional install script)
+    // - .MTREE (per-file metadata)
+    // - .BUILDINFO (build environment)
     // - files...
 
-    let name = path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("unknown")
-        .to_string();
-
-    Ok(PackageInfo {
-        name,
-        version: "1.0.0".to_string(),
-        release: 1,
-        arch: "x86_64".to_string(),
-        format: PackageFormat::Native,
-        description: String::new(),
-        maintainer: String::new(),
-        license: String::new(),
-        homepage: String::new(),
-        size: 0,
-        installed_size: 0,
-        dependencies: Vec::new(),
-        conflicts: Vec::new(),
-        provides: Vec::new(),
-        replaces: Vec::new(),
-        files: Vec::new(),
-        checksum: String::new(),
-    })
+    let file = File::open(path).map_err(PkgError::IoError)?;
+    let size = file.metadata().map_err(PkgError::IoError)?.len();
+    let mut archive = tar::Archive::new(open_payload(path, file)?);
+
+    let mut pkginfo = None;
+    let mut install = None;
+    let mut mtree = None;
+    let mut buildinfo = BTreeMap::new();
+    let mut files = Vec::new();
+    let mut entries = Vec::new();
+
+    for entry in archive.entries().map_err(PkgError::IoError)? {
+        let mut entry = entry.map_err(PkgError::IoError)?;
+        let name =
+            archive::normalize_path(&entry.path().map_err(PkgError::IoError)?.to_string_lossy());
+
+        match name.as_str() {
+            "/.PKGINFO" => pkginfo = Some(read_string(&mut entry)?),
+            "/.INSTALL" => install = Some(read_string(&mut entry)?),
+            "/.BUILDINFO" => buildinfo = parse_buildinfo(&read_string(&mut entry)?),
+            "/.MTREE" => {
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data).map_err(PkgError::IoError)?;
+                mtree = Some(data);
+            }
+            _ if is_metadata(&name) => {}
+            _ => {
+                // Only needed without .MTREE, which comes before the payload
+                let mut file = archive::tar_file_entry(&entry)?;
+                // The `./` member
+                if file.path.is_empty() {
+                    continue;
+                }
+                if mtree.is_none() && entry.header().entry_type().is_file() {
+                    let mut hasher = Sha256::new();
+                    std::io::copy(&mut entry, &mut hasher).map_err(PkgError::IoError)?;
+                    file.digest = format!("{:x}", hasher.finalize());
+                }
+                if !entry.header().entry_type().is_dir() {
+                    files.push(name);
+                }
+                entries.push(file);
+            }
+        }
+    }
+
+    let pkginfo =
+        pkginfo.ok_or_else(|| PkgError::ExtractionError("Missing .PKGINFO".to_string()))?;
+    let pkginfo = parse_pkginfo(&pkginfo)?;
+    if pkginfo.pkgname.is_empty() {
+        return Err(PkgError::ParseError(
+            "Missing pkgname in .PKGINFO".to_string(),
+        ));
+    }
+
+    let mut info = PackageInfo::from(pkginfo.clone());
+    if info.maintainer.is_empty()
+        && let Some(packager) = buildinfo.get("packager").and_then(|v| v.first())
+    {
+        info.maintainer = packager.clone();
+    }
+    info.size = size;
+    info.files = files;
+    // .MTREE has digests; without it, describe the members themselves
+    info.file_entries = match &mtree {
+        Some(data) => mtree::parse_mtree(data)?
+            .into_iter()
+            .filter(|entry| !is_metadata(&entry.path))
+            .collect(),
+        None => entries,
+    };
+    for entry in &mut info.file_entries {
+        if pkginfo
+            .backup
+            .iter()
+            .any(|b| entry.path.trim_start_matches('/') == b)
+        {
+            entry.flags |= file_flags::CONFIG | file_flags::NOREPLACE;
+        }
+    }
+    info.scripts = install.as_deref().map(install_scripts).unwrap_or_default();
+    info.checksum = archive::sha256_file(path)?;
+
+    Ok(NativePackage { info, pkginfo })
+}
+
+/// Extract the payload of a native package
+///
+/// Metadata members (`.PKGINFO` and friends) are not installed.
+pub fn extract_pkg(path: &Path, extractor: &mut Extractor) -> Result<(), PkgError> {
+    let file = File::open(path).map_err(PkgError::IoError)?;
+    extract::unpack_tar(extractor, open_payload(path, file)?, is_metadata)
+}
+
+/// Open the tar stream, picking the decoder from the leading bytes
+fn open_payload(path: &Path, file: File) -> Result<Box<dyn Read>, PkgError> {
+    let mut reader = BufReader::new(file);
+    let magic = reader.fill_buf().map_err(PkgError::IoError)?;
+    Compression::from_magic(magic)
+        .ok_or_else(|| {
+            PkgError::ExtractionError(format!("Unsupported compression for {}", path.display()))
+        })?
+        .decoder(reader)
+}
+
+/// Scripts for the functions a `.INSTALL` defines
+///
+/// Each script holds the whole file and calls one function with the
+/// script's arguments, as pacman does. `.INSTALL` files are bash.
+fn install_scripts(install: &str) -> Vec<Scriptlet> {
+    INSTALL_FUNCTIONS
+        .iter()
+        .filter(|(function, _)| {
+            install.lines().any(|line| {
+                let line = line.trim_start();
+                // `name() {`, `function name() {` or `function name {`
+                match line.strip_prefix("function ") {
+                    Some(line) => line
+                        .trim_start()
+                        .strip_prefix(function)
+                        .is_some_and(|rest| {
+                            rest.is_empty() || rest.starts_with(['(', '{', ' ', '\t'])
+                        }),
+                    None => line
+                        .strip_prefix(function)
+                        .is_some_and(|rest| rest.trim_start().starts_with("()")),
+                }
+            })
+        })
+        .map(|&(function, phase)| Scriptlet {
+            phase,
+            interpreter: vec!["/bin/bash".to_string()],
+            body: format!("{}\n{} \"$@\"\n", install, function),
+        })
+        .collect()
+}
+
+/// Top-level dot files hold package metadata, not installable content
+fn is_metadata(name: &str) -> bool {
+    name.strip_prefix("/.")
+        .is_some_and(|rest| !rest.is_empty() && !rest.contains('/'))
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String, PkgError> {
+    let mut content = String::new();
+    reader
+        .read_to_string(&mut content)
+        .map_err(PkgError::IoError)?;
+    Ok(content)
+}
+
+/// Parse .BUILDINFO content (`key = value`, keys may repeat)
+pub fn parse_buildinfo(content: &str) -> BTreeMap<String, Vec<String>> {
+    let mut values: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            values
+                .entry(key.trim().to_string())
+                .or_default()
+                .push(value.trim().to_string());
+        }
+    }
+    values
+}
+
+/// Parsed .PKGINFO
+#[derive(Debug, Clone, Default)]
+pub struct PkgInfo {
+    pub pkgname: String,
+    pub pkgbase: String,
+    pub pkgver: String,
+    pub pkgdesc: String,
+    pub url: String,
+    pub builddate: u64,
+    pub packager: String,
+    pub size: u64,
+    pub arch: String,
+    pub licenses: Vec<String>,
+    pub groups: Vec<String>,
+    pub depends: Vec<Dependency>,
+    /// Optional dependencies as written (`name: reason`)
+    pub optdepends: Vec<String>,
+    pub makedepends: Vec<Dependency>,
+    pub checkdepends: Vec<Dependency>,
+    pub conflicts: Vec<String>,
+    pub provides: Vec<String>,
+    pub replaces: Vec<String>,
+    /// Config files (relative paths) to preserve on upgrade
+    pub backup: Vec<String>,
 }
 
 /// Parse .PKGINFO content
-pub fn parse_pkginfo(content: &str) -> Result<PackageInfo, PkgError> {
-    let mut info = PackageInfo {
-        name: String::new(),
-        version: String::new(),
-        release: 1,
-        arch: String::new(),
-        format: PackageFormat::Native,
-        description: String::new(),
-        maintainer: String::new(),
-        license: String::new(),
-        homepage: String::new(),
-        size: 0,
-        installed_size: 0,
-        dependencies: Vec::new(),
-        conflicts: Vec::new(),
-        provides: Vec::new(),
-        replaces: Vec::new(),
-        files: Vec::new(),
-        checksum: String::new(),
-    };
+pub fn parse_pkginfo(content: &str) -> Result<PkgInfo, PkgError> {
+    let mut info = PkgInfo::default();
 
     for line in content.lines() {
         let line = line.trim();
@@ -69,31 +266,124 @@ pub fn parse_pkginfo(content: &str) -> Result<PackageInfo, PkgError> {
             continue;
         }
 
-        if let Some((key, value)) = line.split_once('=') {
-            let key = key.trim();
-            let value = value.trim();
-
-            match key {
-                "pkgname" => info.name = value.to_string(),
-                "pkgver" => info.version = value.to_string(),
-                "pkgdesc" => info.description = value.to_string(),
-                "url" => info.homepage = value.to_string(),
-                "size" => info.installed_size = value.parse().unwrap_or(0),
-                "arch" => info.arch = value.to_string(),
-                "license" => info.license = value.to_string(),
-                "depend" => {
-                    info.dependencies.push(Dependency {
-                        name: value.to_string(),
-                        version_constraint: None,
-                    });
-                }
-                "conflict" => info.conflicts.push(value.to_string()),
-                "provides" => info.provides.push(value.to_string()),
-                "replaces" => info.replaces.push(value.to_string()),
-                _ => {}
-            }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+
+        match key.trim() {
+            "pkgname" => info.pkgname = value.to_string(),
+            "pkgbase" => info.pkgbase = value.to_string(),
+            "pkgver" => info.pkgver = value.to_string(),
+            "pkgdesc" => info.pkgdesc = value.to_string(),
+            "url" => info.url = value.to_string(),
+            "builddate" => info.builddate = value.parse().unwrap_or(0),
+            "packager" => info.packager = value.to_string(),
+            "size" => info.size = value.parse().unwrap_or(0),
+            "arch" => info.arch = value.to_string(),
+            "license" => info.licenses.push(value.to_string()),
+            "group" => info.groups.push(value.to_string()),
+            "depend" => info.depends.push(parse_pacman_dep(value)),
+            "optdepend" => info.optdepends.push(value.to_string()),
+            "makedepend" => info.makedepends.push(parse_pacman_dep(value)),
+            "checkdepend" => info.checkdepends.push(parse_pacman_dep(value)),
+            "conflict" => info.conflicts.push(value.to_string()),
+            "provides" => info.provides.push(value.to_string()),
+            "replaces" => info.replaces.push(value.to_string()),
+            "backup" => info.backup.push(value.to_string()),
+            _ => {}
         }
     }
 
+    if info.pkgbase.is_empty() {
+        info.pkgbase = info.pkgname.clone();
+    }
+
     Ok(info)
 }
+
+impl From<PkgInfo> for PackageInfo {
+    fn from(pkg: PkgInfo) -> Self {
+        PackageInfo {
+            name: pkg.pkgname,
+            version: pkg.pkgver,
+            release: 1,
+            arch: pkg.arch,
+            format: PackageFormat::Native,
+            description: pkg.pkgdesc,
+            maintainer: pkg.packager,
+            license: pkg.licenses.join(", "),
+            homepage: pkg.url,
+            size: 0,
+            installed_size: pkg.size,
+            dependencies: pkg.depends,
+            conflicts: pkg.conflicts,
+            provides: pkg.provides,
+            replaces: pkg.replaces,
+            files: Vec::new(),
+            file_entries: Vec::new(),
+            checksum: String::new(),
+            scripts: Vec::new(),
+            triggers: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A gzipped package of `(path, contents)` members; a path ending in
+    /// `/` is a directory
+    fn package(dir: &Path, members: &[(&str, &[u8])]) -> std::path::PathBuf {
+        let gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(gz);
+        for (path, data) in members {
+            let mut header = tar::Header::new_ustar();
+            match path.ends_with('/') {
+                true => header.set_entry_type(tar::EntryType::Directory),
+                false => header.set_entry_type(tar::EntryType::Regular),
+            }
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_uid(0);
+            header.set_gid(0);
+            header.set_mtime(0);
+            // Written by hand: `set_path` refuses `./`
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        let path = dir.join("foo-1-1-any.pkg.tar.gz");
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(&path, builder.into_inner().unwrap().finish().unwrap()).unwrap();
+        path
+    }
+
+    #[test]
+    fn backup_files_are_flagged_without_an_mtree() {
+        let dir = std::env::temp_dir().join(format!("rpm-next-pkg-{}", std::process::id()));
+        let pkginfo = b"pkgname = foo\npkgver = 1-1\narch = any\nbackup = etc/foo.conf\n";
+        let path = package(
+            &dir,
+            &[
+                ("./", b""),
+                (".PKGINFO", pkginfo),
+                ("etc/", b""),
+                ("etc/foo.conf", b"setting=1\n"),
+            ],
+        );
+        let pkg = read_pkg(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let info = pkg.unwrap().info;
+        assert!(info.file_entries.iter().all(|e| !e.path.is_empty()));
+        let conf = info
+            .file_entries
+            .iter()
+            .find(|e| e.path == "/etc/foo.conf")
+            .unwrap();
+        assert_eq!(conf.flags, file_flags::CONFIG | file_flags::NOREPLACE);
+        assert_eq!(info.files, ["/etc/foo.conf"]);
+    }
+}
@@ -7,7 +7,9 @@
 //! For legal use only with properly licensed apps.
 
 use std::collections::HashMap;
+use std::path::Path;
 
+use crate::android;
 use crate::{Dependency, PackageFormat, PackageInfo, PkgError, Repository};
 
 /// Play Store API endpoints
@@ -118,6 +120,8 @@ pub struct FDroidPackage {
     pub target_sdk: u32,
     pub native_code: Vec<String>,
     pub permissions: Vec<String>,
+    /// SHA-256 fingerprint of the signing certificate
+    pub signer: String,
 }
 
 /// Parse F-Droid index.json
@@ -158,7 +162,10 @@ impl From<FDroidApp> for PackageInfo {
             provides: Vec::new(),
             replaces: Vec::new(),
             files: Vec::new(),
+            file_entries: Vec::new(),
             checksum: latest.map(|p| p.hash.clone()).unwrap_or_default(),
+            scripts: Vec::new(),
+            triggers: Vec::new(),
         }
     }
 }
@@ -182,7 +189,10 @@ impl From<PlayStoreApp> for PackageInfo {
             provides: Vec::new(),
             replaces: Vec::new(),
             files: Vec::new(),
+            file_entries: Vec::new(),
             checksum: String::new(),
+            scripts: Vec::new(),
+            triggers: Vec::new(),
         }
     }
 }
@@ -239,6 +249,11 @@ impl PlayStoreRepository {
         self.apps.get(package_name)
     }
 
+    /// Every synced app
+    pub fn apps(&self) -> impl Iterator<Item = &FDroidApp> {
+        self.apps.values()
+    }
+
     /// Get download URL for an app
     pub fn get_download_url(&self, app: &FDroidApp) -> Option<String> {
         app.packages
@@ -246,6 +261,49 @@ impl PlayStoreRepository {
             .map(|pkg| format!("{}/{}", self.fdroid_url, pkg.apk_name))
     }
 
+    /// Check a local APK against the repository's package name, hash and signer
+    pub fn verify_apk(&self, app: &FDroidApp, path: &Path) -> Result<FDroidPackage, PkgError> {
+        let apk = android::read_android(path)?;
+        if apk.package != app.package_name {
+            return Err(PkgError::DownloadError(format!(
+                "Package name mismatch: expected {}, got {}",
+                app.package_name, apk.package
+            )));
+        }
+        let local = FDroidPackage::from(apk);
+
+        let expected = app
+            .packages
+            .iter()
+            .find(|p| p.version_code == local.version_code)
+            .ok_or_else(|| {
+                PkgError::PackageNotFound(format!(
+                    "{} version code {}",
+                    app.package_name, local.version_code
+                ))
+            })?;
+
+        if !expected.hash.is_empty() && !expected.hash.eq_ignore_ascii_case(&local.hash) {
+            return Err(PkgError::DownloadError(format!(
+                "Hash mismatch for {}: expected {}, got {}",
+                app.package_name, expected.hash, local.hash
+            )));
+        }
+        // v1-only (JAR-signed) APKs have no v2/v3 block to take the signer from;
+        // the index hash still pins those
+        if !expected.signer.is_empty()
+            && !local.signer.is_empty()
+            && !expected.signer.eq_ignore_ascii_case(&local.signer)
+        {
+            return Err(PkgError::DownloadError(format!(
+                "Signer mismatch for {}",
+                app.package_name
+            )));
+        }
+
+        Ok(local)
+    }
+
     /// Search by category
     pub fn search_by_category(&self, category: AppCategory) -> Vec<&FDroidApp> {
         let cat_str = format!("{:?}", category);
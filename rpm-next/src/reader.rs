@@ -0,0 +1,209 @@
+//! Package Readers
+//!
+//! Common interface over the format adapters, and detection of a package
+//! file's format from its leading bytes rather than its extension.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::archive::Compression;
+use crate::extract::Extractor;
+use crate::{PackageFormat, PackageInfo, PkgError};
+use crate::{android, apk, deb, msi, msix, pkg, rpm};
+
+const RPM_LEAD_MAGIC: &[u8; 4] = &[0xed, 0xab, 0xee, 0xdb];
+const OLE_MAGIC: &[u8; 8] = &[0xd0, 0xcf, 0x11, 0xe0, 0xa1, 0xb1, 0x1a, 0xe1];
+const ZIP_MAGIC: &[u8; 4] = b"PK\x03\x04";
+
+/// A package format adapter
+pub trait PackageReader {
+    /// Read package metadata and file list
+    fn read(&self, path: &Path) -> Result<PackageInfo, PkgError>;
+
+    /// Install the package payload through `extractor`
+    fn extract(&self, _path: &Path, _extractor: &mut Extractor) -> Result<(), PkgError> {
+        Err(PkgError::UnsupportedFormat)
+    }
+}
+
+/// Adapter for a package format
+pub fn reader_for(format: PackageFormat) -> &'static dyn PackageReader {
+    match format {
+        PackageFormat::Native => &pkg::PkgReader,
+        PackageFormat::Deb => &deb::DebReader,
+        PackageFormat::Rpm => &rpm::RpmReader,
+        PackageFormat::Apk => &apk::ApkReader,
+        PackageFormat::Msi => &msi::MsiReader,
+        PackageFormat::Msix => &msix::MsixReader,
+        PackageFormat::Android => &android::AndroidReader,
+    }
+}
+
+/// Read a package file of any supported format
+pub fn read_package(path: &Path) -> Result<PackageInfo, PkgError> {
+    reader_for(detect_format(path)?).read(path)
+}
+
+/// Identify a package file by its magic bytes
+///
+/// gzip files are Alpine packages when they hold several gzip segments
+/// (signature/control, then data), native packages otherwise; ZIP files are
+/// told apart by their manifest.
+pub fn detect_format(path: &Path) -> Result<PackageFormat, PkgError> {
+    let file = File::open(path).map_err(PkgError::IoError)?;
+    let mut reader = BufReader::new(file);
+    let magic = reader.fill_buf().map_err(PkgError::IoError)?;
+
+    if magic.starts_with(deb::AR_MAGIC) {
+        return Ok(PackageFormat::Deb);
+    }
+    if magic.starts_with(RPM_LEAD_MAGIC) {
+        return Ok(PackageFormat::Rpm);
+    }
+    if magic.starts_with(OLE_MAGIC) {
+        return Ok(PackageFormat::Msi);
+    }
+    if magic.starts_with(ZIP_MAGIC) {
+        return detect_zip(path);
+    }
+
+    match Compression::from_magic(magic) {
+        Some(Compression::Gzip) => detect_gzip(reader),
+        Some(_) => Ok(PackageFormat::Native),
+        None => Err(PkgError::UnsupportedFormat),
+    }
+}
+
+fn detect_zip(path: &Path) -> Result<PackageFormat, PkgError> {
+    let file = File::open(path).map_err(PkgError::IoError)?;
+    let zip = zip::ZipArchive::new(file)
+        .map_err(|e| PkgError::ExtractionError(format!("Invalid ZIP archive: {}", e)))?;
+
+    for name in zip.file_names() {
+        match name {
+            "AndroidManifest.xml" => return Ok(PackageFormat::Android),
+            "AppxManifest.xml" => return Ok(PackageFormat::Msix),
+            _ => {}
+        }
+    }
+    Err(PkgError::UnsupportedFormat)
+}
+
+/// Tell an Alpine package from a gzip-compressed native package
+///
+/// Alpine segments only hold dot files and are followed by another gzip
+/// member; a native tarball reaches its payload within the first member.
+fn detect_gzip(mut reader: BufReader<File>) -> Result<PackageFormat, PkgError> {
+    {
+        let mut archive = tar::Archive::new(flate2::bufread::GzDecoder::new(&mut reader));
+        for entry in archive.entries().map_err(PkgError::IoError)? {
+            let entry = entry.map_err(PkgError::IoError)?;
+            let name = entry
+                .path()
+                .map_err(PkgError::IoError)?
+                .to_string_lossy()
+                .into_owned();
+            if name.starts_with(".SIGN.") {
+                return Ok(PackageFormat::Apk);
+            }
+            let name = name.trim_start_matches("./");
+            if !name.is_empty() && !name.starts_with('.') {
+                return Ok(PackageFormat::Native);
+            }
+        }
+        let mut rest = archive.into_inner();
+        std::io::copy(&mut rest, &mut std::io::sink()).map_err(PkgError::IoError)?;
+    }
+
+    let more = !reader.fill_buf().map_err(PkgError::IoError)?.is_empty();
+    Ok(if more {
+        PackageFormat::Apk
+    } else {
+        PackageFormat::Native
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// A gzip segment holding a tar of `files`
+    fn tar_gz(files: &[&str]) -> Vec<u8> {
+        let gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(gz);
+        for path in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(1);
+            header.set_mode(0o644);
+            header.set_uid(0);
+            header.set_gid(0);
+            header.set_mtime(0);
+            builder.append_data(&mut header, path, &b"x"[..]).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn zip(members: &[&str]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for member in members {
+            writer
+                .start_file(*member, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(b"x").unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn detect(name: &str, data: &[u8]) -> Result<PackageFormat, PkgError> {
+        let path =
+            std::env::temp_dir().join(format!("rpm-next-detect-{}-{}", name, std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        let result = detect_format(&path);
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn formats_are_detected_by_content() {
+        let signed_apk = [
+            tar_gz(&[".SIGN.RSA.alpine.rsa.pub"]),
+            tar_gz(&[".PKGINFO"]),
+            tar_gz(&["usr/bin/foo"]),
+        ]
+        .concat();
+        let unsigned_apk = [tar_gz(&[".PKGINFO"]), tar_gz(&["usr/bin/foo"])].concat();
+        let native = tar_gz(&[".PKGINFO", ".MTREE", "usr/bin/foo"]);
+        // A metapackage: nothing but metadata, in a single gzip member
+        let dot_files_only = tar_gz(&[".PKGINFO", ".BUILDINFO"]);
+        let deb = [deb::AR_MAGIC.as_slice(), b"debian-binary   "].concat();
+        let rpm_lead = [RPM_LEAD_MAGIC.as_slice(), &[0; 92]].concat();
+
+        for (name, data, format) in [
+            ("signed.apk", signed_apk, PackageFormat::Apk),
+            ("unsigned.apk", unsigned_apk, PackageFormat::Apk),
+            ("native.pkg.tar.gz", native, PackageFormat::Native),
+            ("meta.pkg.tar.gz", dot_files_only, PackageFormat::Native),
+            ("app.deb", deb, PackageFormat::Deb),
+            ("app.rpm", rpm_lead, PackageFormat::Rpm),
+            (
+                "app.android.apk",
+                zip(&["AndroidManifest.xml"]),
+                PackageFormat::Android,
+            ),
+            ("app.msix", zip(&["AppxManifest.xml"]), PackageFormat::Msix),
+        ] {
+            assert_eq!(detect(name, &data).unwrap(), format, "{}", name);
+        }
+
+        assert!(matches!(
+            detect("other.zip", &zip(&["hello.txt"])),
+            Err(PkgError::UnsupportedFormat)
+        ));
+        assert!(matches!(
+            detect("empty", b""),
+            Err(PkgError::UnsupportedFormat)
+        ));
+    }
+}
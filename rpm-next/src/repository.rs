@@ -7,6 +7,28 @@ use std::path::{Path, PathBuf};
 
 use crate::{PackageFormat, PackageInfo, PkgError, Repository};
 
+/// Fetch a URL into memory (`http(s)://` or `file://`)
+pub fn fetch(url: &str) -> Result<Vec<u8>, PkgError> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return std::fs::read(path).map_err(|e| PkgError::DownloadError(format!("{}: {}", url, e)));
+    }
+
+    let response = reqwest::blocking::get(url)
+        .map_err(|e| PkgError::NetworkError(format!("{}: {}", url, e)))?;
+    if !response.status().is_success() {
+        return Err(PkgError::DownloadError(format!(
+            "{}: HTTP {}",
+            url,
+            response.status()
+        )));
+    }
+
+    response
+        .bytes()
+        .map(|body| body.to_vec())
+        .map_err(|e| PkgError::NetworkError(format!("{}: {}", url, e)))
+}
+
 /// Repository cache
 pub struct RepositoryCache {
     /// Cache directory
@@ -4,12 +4,18 @@
 
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
-use crate::{ConstraintOp, Dependency, PackageInfo, PkgError, VersionConstraint};
+use crate::{
+    ConstraintOp, Dependency, PackageFormat, PackageInfo, PkgError, RichDependency,
+    VersionConstraint, apk, deb, rpm,
+};
 
 /// Resolver state
 pub struct Resolver {
     /// Known packages (name -> versions)
     packages: HashMap<String, Vec<PackageInfo>>,
+    /// Provided names (virtual packages, sonames) -> (name, version) of the
+    /// packages providing them
+    providers: HashMap<String, Vec<(String, String)>>,
     /// Installed packages
     installed: HashMap<String, PackageInfo>,
     /// Resolution result
@@ -20,6 +26,7 @@ impl Resolver {
     pub fn new() -> Self {
         Self {
             packages: HashMap::new(),
+            providers: HashMap::new(),
             installed: HashMap::new(),
             solution: Vec::new(),
         }
@@ -28,6 +35,13 @@ impl Resolver {
     /// Add available packages
     pub fn add_available(&mut self, packages: Vec<PackageInfo>) {
         for pkg in packages {
+            for provide in &pkg.provides {
+                let (name, _) = parse_provide(provide);
+                self.providers
+                    .entry(name.to_string())
+                    .or_default()
+                    .push((pkg.name.clone(), pkg.version.clone()));
+            }
             self.packages
                 .entry(pkg.name.clone())
                 .or_insert_with(Vec::new)
@@ -45,31 +59,71 @@ impl Resolver {
     /// Resolve dependencies for requested packages
     pub fn resolve(&mut self, requests: &[&str]) -> Result<Vec<PackageInfo>, PkgError> {
         self.solution.clear();
-        let mut to_install: Vec<String> = requests.iter().map(|s| s.to_string()).collect();
-        let mut seen: HashSet<String> = HashSet::new();
-
-        while let Some(name) = to_install.pop() {
-            if seen.contains(&name) {
-                continue;
-            }
-            seen.insert(name.clone());
+        // Versions picked so far, which boolean conditions are checked against
+        let mut selected: HashMap<String, PackageInfo> = HashMap::new();
+        let mut to_install: Vec<PackageInfo> = Vec::new();
 
+        for name in requests {
             // Check if already installed
-            if self.installed.contains_key(&name) {
+            if self.installed.contains_key(*name) || selected.contains_key(*name) {
                 continue;
             }
+            let pkg = self.find_best_version(&Dependency::new(*name, None))?;
+            selected.insert(pkg.name.clone(), pkg.clone());
+            to_install.push(pkg);
+        }
+
+        loop {
+            while let Some(pkg) = to_install.pop() {
+                // Add dependencies to queue
+                for dep in &pkg.dependencies {
+                    let required = match &dep.rich {
+                        // Boolean dependencies are evaluated against the current selection
+                        Some(rich) => {
+                            let mut required = Vec::new();
+                            self.rich_requirements(rich, &selected, &mut required)?;
+                            required
+                        }
+                        None => {
+                            // Any alternative already installed or selected satisfies the dependency
+                            if dep
+                                .choices()
+                                .any(|d| self.present(&selected).any(|pkg| self.satisfies(pkg, d)))
+                            {
+                                continue;
+                            }
 
-            // Find best version
-            let pkg = self.find_best_version(&name)?;
+                            // Otherwise take the first alternative available
+                            match dep.choices().find_map(|d| self.find_best_version(d).ok()) {
+                                Some(pkg) => vec![pkg],
+                                None => vec![self.find_best_version(dep)?],
+                            }
+                        }
+                    };
 
-            // Add dependencies to queue
-            for dep in &pkg.dependencies {
-                if !seen.contains(&dep.name) && !self.installed.contains_key(&dep.name) {
-                    to_install.push(dep.name.clone());
+                    for required in required {
+                        select(&pkg, required, &mut selected, &mut to_install)?;
+                    }
                 }
+
+                self.solution.push(pkg);
             }
 
-            self.solution.push(pkg);
+            // A condition may have come true through a package selected after
+            // its dependency was evaluated, so boolean dependencies are checked
+            // again until the selection stops changing
+            for pkg in &self.solution {
+                for rich in pkg.dependencies.iter().filter_map(|d| d.rich.as_deref()) {
+                    let mut required = Vec::new();
+                    self.rich_requirements(rich, &selected, &mut required)?;
+                    for required in required {
+                        select(pkg, required, &mut selected, &mut to_install)?;
+                    }
+                }
+            }
+            if to_install.is_empty() {
+                break;
+            }
         }
 
         // Sort by dependencies (topological sort)
@@ -78,19 +132,229 @@ impl Resolver {
         Ok(self.solution.clone())
     }
 
-    /// Find the best version of a package
-    fn find_best_version(&self, name: &str) -> Result<PackageInfo, PkgError> {
-        let versions = self
-            .packages
-            .get(name)
-            .ok_or_else(|| PkgError::PackageNotFound(name.to_string()))?;
+    /// Collect the package versions a boolean dependency needs installed
+    ///
+    /// Fails when no choice of available packages satisfies the expression.
+    fn rich_requirements(
+        &self,
+        rich: &RichDependency,
+        selected: &HashMap<String, PackageInfo>,
+        out: &mut Vec<PackageInfo>,
+    ) -> Result<(), PkgError> {
+        match rich {
+            RichDependency::And(operands) => {
+                for operand in operands {
+                    self.rich_requirements(operand, selected, out)?;
+                }
+                Ok(())
+            }
+            RichDependency::Or(operands) => {
+                if operands.iter().any(|op| self.rich_present(op, selected)) {
+                    return Ok(());
+                }
+                for operand in operands {
+                    let mut trial = Vec::new();
+                    if self
+                        .rich_requirements(operand, selected, &mut trial)
+                        .is_ok()
+                    {
+                        out.extend(trial);
+                        return Ok(());
+                    }
+                }
+                Err(PkgError::DependencyError(
+                    "No alternative of a boolean dependency is available".to_string(),
+                ))
+            }
+            RichDependency::If {
+                then,
+                condition,
+                otherwise,
+            } => {
+                if self.rich_present(condition, selected) {
+                    self.rich_requirements(then, selected, out)
+                } else if let Some(otherwise) = otherwise {
+                    self.rich_requirements(otherwise, selected, out)
+                } else {
+                    Ok(())
+                }
+            }
+            RichDependency::Unless {
+                then,
+                condition,
+                otherwise,
+            } => {
+                if !self.rich_present(condition, selected) {
+                    self.rich_requirements(then, selected, out)
+                } else if let Some(otherwise) = otherwise {
+                    self.rich_requirements(otherwise, selected, out)
+                } else {
+                    Ok(())
+                }
+            }
+            // A single package version must match the whole term
+            RichDependency::Atom(_) | RichDependency::With(_) | RichDependency::Without(..) => {
+                if self.rich_present(rich, selected) {
+                    return Ok(());
+                }
+                let best = self
+                    .packages
+                    .values()
+                    .flatten()
+                    .filter(|pkg| self.package_matches(pkg, rich))
+                    .max_by(|a, b| self.compare_versions(a.format, &a.version, &b.version));
+                match best {
+                    Some(pkg) => {
+                        out.push(pkg.clone());
+                        Ok(())
+                    }
+                    None => {
+                        let mut atoms = Vec::new();
+                        rich_atoms(rich, &mut atoms);
+                        let atoms: Vec<String> = atoms.iter().map(|d| d.to_string()).collect();
+                        Err(PkgError::DependencyError(format!(
+                            "Nothing provides {}",
+                            atoms.join(" with ")
+                        )))
+                    }
+                }
+            }
+        }
+    }
 
-        // Return highest version
-        versions
-            .iter()
-            .max_by(|a, b| self.compare_versions(&a.version, &b.version))
-            .cloned()
-            .ok_or_else(|| PkgError::PackageNotFound(name.to_string()))
+    /// Whether a boolean dependency already holds without installing anything
+    fn rich_present(&self, rich: &RichDependency, selected: &HashMap<String, PackageInfo>) -> bool {
+        match rich {
+            RichDependency::And(operands) => {
+                operands.iter().all(|op| self.rich_present(op, selected))
+            }
+            RichDependency::Or(operands) => {
+                operands.iter().any(|op| self.rich_present(op, selected))
+            }
+            RichDependency::If {
+                then,
+                condition,
+                otherwise,
+            } => {
+                if self.rich_present(condition, selected) {
+                    self.rich_present(then, selected)
+                } else {
+                    otherwise
+                        .as_ref()
+                        .is_none_or(|o| self.rich_present(o, selected))
+                }
+            }
+            RichDependency::Unless {
+                then,
+                condition,
+                otherwise,
+            } => {
+                if !self.rich_present(condition, selected) {
+                    self.rich_present(then, selected)
+                } else {
+                    otherwise
+                        .as_ref()
+                        .is_none_or(|o| self.rich_present(o, selected))
+                }
+            }
+            // An installed or selected version, preferring the selected one
+            // since it replaces the installed one
+            RichDependency::Atom(_) | RichDependency::With(_) | RichDependency::Without(..) => self
+                .present(selected)
+                .any(|pkg| self.package_matches(pkg, rich)),
+        }
+    }
+
+    /// Whether one package version matches an atom, or all sides of a
+    /// `with`/`without` term
+    fn package_matches(&self, pkg: &PackageInfo, rich: &RichDependency) -> bool {
+        match rich {
+            RichDependency::Atom(dep) => self.satisfies(pkg, dep),
+            RichDependency::With(operands) => {
+                operands.iter().all(|op| self.package_matches(pkg, op))
+            }
+            RichDependency::Without(left, right) => {
+                self.package_matches(pkg, left) && !self.package_matches(pkg, right)
+            }
+            // Only simple dependencies may be combined with `with` and `without`
+            _ => false,
+        }
+    }
+
+    /// Packages that will be there after the transaction: the selected
+    /// versions, and installed packages not being replaced
+    fn present<'a>(
+        &'a self,
+        selected: &'a HashMap<String, PackageInfo>,
+    ) -> impl Iterator<Item = &'a PackageInfo> {
+        selected.values().chain(
+            self.installed
+                .values()
+                .filter(|pkg| !selected.contains_key(&pkg.name)),
+        )
+    }
+
+    /// Whether a package satisfies a simple dependency, by its name, its
+    /// provides or, for a path, its files
+    fn satisfies(&self, pkg: &PackageInfo, dep: &Dependency) -> bool {
+        if pkg.name == dep.name {
+            return dep
+                .version_constraint
+                .as_ref()
+                .is_none_or(|c| self.version_satisfies(pkg, c));
+        }
+        if dep.name.starts_with('/') && pkg.files.contains(&dep.name) {
+            return true;
+        }
+        pkg.provides.iter().any(|provide| {
+            let (name, version) = parse_provide(provide);
+            name == dep.name
+                && match (&dep.version_constraint, version) {
+                    (None, _) => true,
+                    (Some(c), Some(version)) => self.constraint_holds(pkg.format, version, c),
+                    // rpm lets an unversioned provide meet any version;
+                    // dpkg, apk and pacman do not
+                    (Some(_), None) => pkg.format == PackageFormat::Rpm,
+                }
+        })
+    }
+
+    /// Find the best package for a simple dependency: the highest matching
+    /// version of the package itself, or else of a package providing it
+    fn find_best_version(&self, dep: &Dependency) -> Result<PackageInfo, PkgError> {
+        let best = |candidates: Vec<&PackageInfo>| {
+            candidates
+                .into_iter()
+                .filter(|pkg| self.satisfies(pkg, dep))
+                .max_by(|a, b| self.compare_versions(a.format, &a.version, &b.version))
+                .cloned()
+        };
+        let named = self.packages.get(&dep.name).into_iter().flatten().collect();
+        let providers = self
+            .providers
+            .get(&dep.name)
+            .into_iter()
+            .flatten()
+            .filter_map(|(name, version)| {
+                self.packages
+                    .get(name)?
+                    .iter()
+                    .find(|pkg| pkg.version == *version)
+            })
+            .collect();
+
+        match best(named).or_else(|| best(providers)) {
+            Some(pkg) => Ok(pkg),
+            None if self.packages.contains_key(&dep.name)
+                || self.providers.contains_key(&dep.name) =>
+            {
+                Err(PkgError::DependencyError(format!(
+                    "Nothing provides {}",
+                    dep
+                )))
+            }
+            None => Err(PkgError::PackageNotFound(dep.name.clone())),
+        }
     }
 
     /// Find version satisfying constraint
@@ -105,7 +369,7 @@ impl Resolver {
             .ok_or_else(|| PkgError::PackageNotFound(name.to_string()))?;
 
         for pkg in versions {
-            if self.version_satisfies(&pkg.version, constraint) {
+            if self.version_satisfies(pkg, constraint) {
                 return Ok(pkg.clone());
             }
         }
@@ -116,9 +380,18 @@ impl Resolver {
         )))
     }
 
-    /// Check if version satisfies constraint
-    fn version_satisfies(&self, version: &str, constraint: &VersionConstraint) -> bool {
-        let cmp = self.compare_versions(version, &constraint.version);
+    /// Check if a package's version satisfies constraint
+    fn version_satisfies(&self, pkg: &PackageInfo, constraint: &VersionConstraint) -> bool {
+        self.constraint_holds(pkg.format, &pkg.version, constraint)
+    }
+
+    fn constraint_holds(
+        &self,
+        format: PackageFormat,
+        version: &str,
+        constraint: &VersionConstraint,
+    ) -> bool {
+        let cmp = self.compare_versions(format, version, &constraint.version);
         match constraint.operator {
             ConstraintOp::Eq => cmp == std::cmp::Ordering::Equal,
             ConstraintOp::Lt => cmp == std::cmp::Ordering::Less,
@@ -128,8 +401,16 @@ impl Resolver {
         }
     }
 
-    /// Compare two version strings
-    fn compare_versions(&self, a: &str, b: &str) -> std::cmp::Ordering {
+    /// Compare two version strings the way the format's own tools do
+    fn compare_versions(&self, format: PackageFormat, a: &str, b: &str) -> std::cmp::Ordering {
+        match format {
+            PackageFormat::Deb => return deb::compare_versions(a, b),
+            PackageFormat::Apk => return apk::compare_versions(a, b),
+            // pacman's vercmp is rpm's
+            PackageFormat::Rpm | PackageFormat::Native => return rpm::compare_versions(a, b),
+            PackageFormat::Android | PackageFormat::Msi | PackageFormat::Msix => {}
+        }
+
         let parse = |s: &str| -> Vec<u32> {
             s.split(|c: char| !c.is_ascii_digit())
                 .filter_map(|p| p.parse().ok())
@@ -152,20 +433,31 @@ impl Resolver {
     /// Topological sort of solution by dependencies
     fn topological_sort(&mut self) {
         let mut result = Vec::new();
-        let mut satisfied: HashSet<String> = self.installed.keys().cloned().collect();
+        let mut satisfied: HashSet<String> =
+            self.installed.values().flat_map(provided_names).collect();
         let mut remaining: Vec<PackageInfo> = self.solution.drain(..).collect();
 
         while !remaining.is_empty() {
             let mut made_progress = false;
+            let pending: HashSet<String> = remaining.iter().flat_map(provided_names).collect();
 
             remaining.retain(|pkg| {
-                let deps_satisfied = pkg
-                    .dependencies
-                    .iter()
-                    .all(|dep| satisfied.contains(&dep.name));
+                let deps_satisfied = pkg.dependencies.iter().all(|dep| match &dep.rich {
+                    // Wait for every referenced package that is part of this solution
+                    Some(rich) => {
+                        let mut atoms = Vec::new();
+                        rich_atoms(rich, &mut atoms);
+                        atoms
+                            .iter()
+                            .all(|a| !pending.contains(&a.name) || satisfied.contains(&a.name))
+                    }
+                    None => dep
+                        .choices()
+                        .any(|d| !pending.contains(&d.name) || satisfied.contains(&d.name)),
+                });
 
                 if deps_satisfied {
-                    satisfied.insert(pkg.name.clone());
+                    satisfied.extend(provided_names(pkg));
                     result.push(pkg.clone());
                     made_progress = true;
                     false
@@ -184,8 +476,298 @@ impl Resolver {
     }
 }
 
+/// Add a package version `pkg` needs to the selection, unless it is
+/// already there; another version being selected is a conflict
+fn select(
+    pkg: &PackageInfo,
+    required: PackageInfo,
+    selected: &mut HashMap<String, PackageInfo>,
+    to_install: &mut Vec<PackageInfo>,
+) -> Result<(), PkgError> {
+    match selected.get(&required.name) {
+        Some(chosen) if chosen.version != required.version => {
+            Err(PkgError::DependencyError(format!(
+                "{} needs {} {}, but {} is already selected",
+                pkg.name, required.name, required.version, chosen.version
+            )))
+        }
+        Some(_) => Ok(()),
+        None => {
+            selected.insert(required.name.clone(), required.clone());
+            to_install.push(required);
+            Ok(())
+        }
+    }
+}
+
+/// Split a provide into its name and version: `foo (= 1.0)` (Debian),
+/// `foo = 1.0` (RPM) or `foo=1.0` (Alpine, pacman)
+fn parse_provide(provide: &str) -> (&str, Option<&str>) {
+    let (name, version) = match provide.split_once(" (") {
+        Some((name, rest)) => (name, rest.trim_end_matches(')').trim_start_matches('=')),
+        None => match provide.split_once('=') {
+            Some((name, version)) => (name, version),
+            None => (provide, ""),
+        },
+    };
+    let version = version.trim();
+    (name.trim(), (!version.is_empty()).then_some(version))
+}
+
+/// The package's own name and every name it provides
+fn provided_names(pkg: &PackageInfo) -> impl Iterator<Item = String> + '_ {
+    std::iter::once(pkg.name.clone()).chain(
+        pkg.provides
+            .iter()
+            .map(|provide| parse_provide(provide).0.to_string()),
+    )
+}
+
+/// Simple dependencies referenced anywhere in a boolean expression
+fn rich_atoms<'a>(rich: &'a RichDependency, out: &mut Vec<&'a Dependency>) {
+    match rich {
+        RichDependency::Atom(dep) => out.push(dep),
+        RichDependency::And(operands)
+        | RichDependency::Or(operands)
+        | RichDependency::With(operands) => {
+            for operand in operands {
+                rich_atoms(operand, out);
+            }
+        }
+        RichDependency::If {
+            then,
+            condition,
+            otherwise,
+        }
+        | RichDependency::Unless {
+            then,
+            condition,
+            otherwise,
+        } => {
+            rich_atoms(then, out);
+            rich_atoms(condition, out);
+            if let Some(otherwise) = otherwise {
+                rich_atoms(otherwise, out);
+            }
+        }
+        RichDependency::Without(left, right) => {
+            rich_atoms(left, out);
+            rich_atoms(right, out);
+        }
+    }
+}
+
 impl Default for Resolver {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpm;
+
+    fn package(name: &str, version: &str, requires: &[&str]) -> PackageInfo {
+        let mut pkg =
+            crate::deb::parse_control(&format!("Package: {}\nVersion: {}\n", name, version))
+                .unwrap();
+        pkg.dependencies = requires
+            .iter()
+            .map(|r| rpm::dependency(r.to_string(), None))
+            .collect();
+        pkg
+    }
+
+    fn resolve(available: Vec<PackageInfo>, installed: Vec<PackageInfo>) -> Vec<(String, String)> {
+        let mut resolver = Resolver::new();
+        resolver.add_available(available);
+        resolver.set_installed(installed);
+        let mut solution: Vec<_> = resolver
+            .resolve(&["app"])
+            .unwrap()
+            .into_iter()
+            .map(|p| (p.name, p.version))
+            .collect();
+        solution.sort();
+        solution
+    }
+
+    fn pair(name: &str, version: &str) -> (String, String) {
+        (name.to_string(), version.to_string())
+    }
+
+    #[test]
+    fn with_picks_one_version_matching_every_operand() {
+        let available = vec![
+            package("app", "1", &["(lib >= 1 with lib < 2)"]),
+            package("lib", "1.5", &[]),
+            package("lib", "3.0", &[]),
+        ];
+        assert_eq!(
+            resolve(available, Vec::new()),
+            [pair("app", "1"), pair("lib", "1.5")]
+        );
+    }
+
+    #[test]
+    fn with_fails_without_a_single_matching_version() {
+        let mut resolver = Resolver::new();
+        resolver.add_available(vec![
+            package("app", "1", &["(lib >= 2 with lib < 2)"]),
+            package("lib", "1", &[]),
+            package("lib", "3", &[]),
+        ]);
+        assert!(resolver.resolve(&["app"]).is_err());
+    }
+
+    #[test]
+    fn without_excludes_versions_matching_the_right_side() {
+        let available = vec![
+            package("app", "1", &["(lib without lib = 3.0)"]),
+            package("lib", "2.0", &[]),
+            package("lib", "3.0", &[]),
+        ];
+        assert_eq!(
+            resolve(available, Vec::new()),
+            [pair("app", "1"), pair("lib", "2.0")]
+        );
+    }
+
+    #[test]
+    fn installed_version_must_meet_the_constraint() {
+        let available = vec![
+            package("app", "1", &["(lib >= 2 if app)"]),
+            package("lib", "2", &[]),
+        ];
+        let installed = vec![package("lib", "1", &[])];
+        assert_eq!(
+            resolve(available, installed),
+            [pair("app", "1"), pair("lib", "2")]
+        );
+    }
+
+    #[test]
+    fn condition_selected_later_still_pulls_in_its_dependency() {
+        // `bar` only comes in through `tool`, after `(foo if bar)` was seen
+        let available = vec![
+            package("app", "1", &["(foo if bar)", "tool"]),
+            package("tool", "1", &["bar"]),
+            package("bar", "1", &[]),
+            package("foo", "1", &[]),
+        ];
+        assert_eq!(
+            resolve(available, Vec::new()),
+            [
+                pair("app", "1"),
+                pair("bar", "1"),
+                pair("foo", "1"),
+                pair("tool", "1")
+            ]
+        );
+    }
+
+    #[test]
+    fn selected_version_is_checked_against_conditions() {
+        // `tool` 1 is selected first, so `lib` is not needed for `tool >= 2`
+        let available = vec![
+            package("app", "1", &["tool", "(lib if tool >= 2)"]),
+            package("tool", "1", &[]),
+            package("lib", "1", &[]),
+        ];
+        assert_eq!(
+            resolve(available, Vec::new()),
+            [pair("app", "1"), pair("tool", "1")]
+        );
+    }
+
+    #[test]
+    fn versions_are_ordered_by_the_package_format() {
+        // By digits alone 1.0~rc1 would be 1.0.1
+        let available = vec![
+            package("app", "1", &["lib"]),
+            package("lib", "1.0", &[]),
+            package("lib", "1.0~rc1", &[]),
+        ];
+        assert_eq!(
+            resolve(available, Vec::new()),
+            [pair("app", "1"), pair("lib", "1.0")]
+        );
+    }
+
+    fn providing(name: &str, version: &str, provides: &[&str]) -> PackageInfo {
+        let mut pkg = package(name, version, &[]);
+        pkg.provides = provides.iter().map(|p| p.to_string()).collect();
+        pkg
+    }
+
+    fn requiring(name: &str, dep: &str, constraint: Option<(ConstraintOp, &str)>) -> PackageInfo {
+        let mut pkg = package(name, "1", &[]);
+        let constraint = constraint.map(|(operator, version)| VersionConstraint {
+            operator,
+            version: version.to_string(),
+        });
+        pkg.dependencies = vec![Dependency::new(dep, constraint)];
+        pkg
+    }
+
+    #[test]
+    fn virtual_dependencies_are_met_by_providers() {
+        let available = vec![
+            requiring("app", "mail-transport-agent", None),
+            providing(
+                "postfix",
+                "3.7",
+                &["mail-transport-agent", "postfix-api (= 3)"],
+            ),
+        ];
+        assert_eq!(
+            resolve(available.clone(), Vec::new()),
+            [pair("app", "1"), pair("postfix", "3.7")]
+        );
+
+        // Already there through an installed provider
+        let installed = vec![providing("exim4", "4.96", &["mail-transport-agent"])];
+        assert_eq!(resolve(available, installed), [pair("app", "1")]);
+    }
+
+    #[test]
+    fn versioned_dependencies_need_a_provide_of_that_version() {
+        let available = vec![
+            requiring("app", "api", Some((ConstraintOp::Ge, "2"))),
+            providing("old", "9", &["api (= 1)"]),
+            providing("new", "1", &["api (= 2)"]),
+            providing("unversioned", "9", &["api"]),
+        ];
+        assert_eq!(
+            resolve(available, Vec::new()),
+            [pair("app", "1"), pair("new", "1")]
+        );
+
+        let mut resolver = Resolver::new();
+        resolver.add_available(vec![
+            requiring("app", "api", Some((ConstraintOp::Ge, "2"))),
+            providing("old", "9", &["api (= 1)"]),
+        ]);
+        assert!(matches!(
+            resolver.resolve(&["app"]),
+            Err(PkgError::DependencyError(_))
+        ));
+    }
+
+    #[test]
+    fn providers_are_ordered_before_their_dependents() {
+        let mut resolver = Resolver::new();
+        resolver.add_available(vec![
+            requiring("app", "so:libfoo.so.1", None),
+            providing("libfoo", "1.0", &["so:libfoo.so.1=1.0"]),
+        ]);
+        let order: Vec<String> = resolver
+            .resolve(&["app"])
+            .unwrap()
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(order, ["libfoo", "app"]);
+    }
+}
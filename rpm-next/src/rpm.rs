@@ -2,9 +2,84 @@
 //!
 //! Handles Red Hat Package Manager format (.rpm)
 
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
 use std::path::Path;
 
-use crate::{Dependency, PackageFormat, PackageInfo, PkgError};
+use sha2::{Digest, Sha256};
+
+use crate::archive::{self, Compression};
+use crate::cpio::{CpioHeader, CpioReader};
+use crate::extract::{Attributes, Extractor};
+use crate::reader::PackageReader;
+use crate::scriptlet::{ScriptPhase, Scriptlet};
+use crate::{
+    ConstraintOp, Dependency, FileEntry, PackageFormat, PackageInfo, PkgError, RichDependency,
+    VersionConstraint,
+};
+
+/// Extended attribute holding file capabilities
+const CAPABILITY_XATTR: &str = "security.capability";
+
+const VFS_CAP_REVISION_2: u32 = 0x0200_0000;
+
+/// Linux capability names, indexed by capability number
+const CAPABILITIES: [&str; 41] = [
+    "chown",
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "fsetid",
+    "kill",
+    "setgid",
+    "setuid",
+    "setpcap",
+    "linux_immutable",
+    "net_bind_service",
+    "net_broadcast",
+    "net_admin",
+    "net_raw",
+    "ipc_lock",
+    "ipc_owner",
+    "sys_module",
+    "sys_rawio",
+    "sys_chroot",
+    "sys_ptrace",
+    "sys_pacct",
+    "sys_admin",
+    "sys_boot",
+    "sys_nice",
+    "sys_resource",
+    "sys_time",
+    "sys_tty_config",
+    "mknod",
+    "lease",
+    "audit_write",
+    "audit_control",
+    "setfcap",
+    "mac_override",
+    "mac_admin",
+    "syslog",
+    "wake_alarm",
+    "block_suspend",
+    "audit_read",
+    "perfmon",
+    "bpf",
+    "checkpoint_restore",
+];
+
+/// Lead magic (`edabeedb`)
+pub const LEAD_MAGIC: [u8; 4] = [0xed, 0xab, 0xee, 0xdb];
+/// Header structure magic
+pub const HEADER_MAGIC: [u8; 4] = [0x8e, 0xad, 0xe8, 0x01];
+/// Size of the obsolete lead
+pub const LEAD_SIZE: usize = 96;
+
+/// Upper bounds matching rpm's own header sanity checks
+const MAX_INDEX_ENTRIES: usize = 0xffff;
+const MAX_DATA_SIZE: usize = 256 * 1024 * 1024;
 
 /// RPM header tags
 pub mod tag {
@@ -15,16 +90,150 @@ pub mod tag {
     pub const DESCRIPTION: u32 = 1005;
     pub const SIZE: u32 = 1009;
     pub const LICENSE: u32 = 1014;
+    pub const PACKAGER: u32 = 1015;
     pub const GROUP: u32 = 1016;
     pub const URL: u32 = 1020;
     pub const ARCH: u32 = 1022;
+    pub const PREIN: u32 = 1023;
+    pub const POSTIN: u32 = 1024;
+    pub const PREUN: u32 = 1025;
+    pub const POSTUN: u32 = 1026;
     pub const FILENAMES: u32 = 1027;
+    pub const FILESIZES: u32 = 1028;
+    pub const FILEMODES: u32 = 1030;
+    pub const FILEMTIMES: u32 = 1034;
+    pub const FILEDIGESTS: u32 = 1035;
+    pub const FILELINKTOS: u32 = 1036;
+    pub const FILEFLAGS: u32 = 1037;
+    pub const FILEUSERNAME: u32 = 1039;
+    pub const FILEGROUPNAME: u32 = 1040;
+    pub const PROVIDES_NAME: u32 = 1047;
+    pub const REQUIRES_FLAGS: u32 = 1048;
     pub const REQUIRES_NAME: u32 = 1049;
     pub const REQUIRES_VERSION: u32 = 1050;
-    pub const REQUIRES_FLAGS: u32 = 1048;
+    pub const CONFLICTS_FLAGS: u32 = 1053;
     pub const CONFLICTS_NAME: u32 = 1054;
-    pub const PROVIDES_NAME: u32 = 1047;
+    pub const CONFLICTS_VERSION: u32 = 1055;
+    pub const PREINPROG: u32 = 1085;
+    pub const POSTINPROG: u32 = 1086;
+    pub const PREUNPROG: u32 = 1087;
+    pub const POSTUNPROG: u32 = 1088;
     pub const OBSOLETES_NAME: u32 = 1090;
+    pub const PROVIDES_FLAGS: u32 = 1112;
+    pub const PROVIDES_VERSION: u32 = 1113;
+    pub const OBSOLETES_FLAGS: u32 = 1114;
+    pub const OBSOLETES_VERSION: u32 = 1115;
+    pub const DIRINDEXES: u32 = 1116;
+    pub const BASENAMES: u32 = 1117;
+    pub const DIRNAMES: u32 = 1118;
+    pub const PAYLOADFORMAT: u32 = 1124;
+    pub const PAYLOADCOMPRESSOR: u32 = 1125;
+    pub const LONGFILESIZES: u32 = 5008;
+    pub const LONGSIZE: u32 = 5009;
+    pub const FILECAPS: u32 = 5010;
+    pub const FILEDIGESTALGO: u32 = 5011;
+}
+
+/// Signature header tags
+pub mod signature_tag {
+    /// Hex SHA-256 of the main header
+    pub const SHA256: u32 = 273;
+}
+
+/// Header entry data types
+pub mod entry_type {
+    pub const NULL: u32 = 0;
+    pub const CHAR: u32 = 1;
+    pub const INT8: u32 = 2;
+    pub const INT16: u32 = 3;
+    pub const INT32: u32 = 4;
+    pub const INT64: u32 = 5;
+    pub const STRING: u32 = 6;
+    pub const BIN: u32 = 7;
+    pub const STRING_ARRAY: u32 = 8;
+    pub const I18NSTRING: u32 = 9;
+}
+
+/// FILEDIGESTALGO value for SHA-256 (MD5 when the tag is absent)
+const DIGEST_ALGO_SHA256: u64 = 8;
+
+/// Dependency sense flags
+pub mod sense {
+    pub const LESS: u32 = 0x02;
+    pub const GREATER: u32 = 0x04;
+    pub const EQUAL: u32 = 0x08;
+    pub const RPMLIB: u32 = 1 << 24;
+}
+
+/// A parsed .rpm file, positioned at the payload
+pub struct RpmFile<R: Read> {
+    pub header: Header,
+    /// Reader positioned at the start of the (compressed) payload
+    pub payload: R,
+}
+
+/// Read lead, signature and main header from an .rpm stream
+///
+/// The main header is checked against the signature header's SHA-256
+/// digest, when it has one.
+pub fn read_rpm<R: Read>(mut reader: R) -> Result<RpmFile<R>, PkgError> {
+    let mut lead = [0u8; LEAD_SIZE];
+    reader
+        .read_exact(&mut lead)
+        .map_err(|_| PkgError::ExtractionError("File too short for RPM lead".to_string()))?;
+    if lead[0..4] != LEAD_MAGIC {
+        return Err(PkgError::ExtractionError(
+            "Invalid RPM lead magic".to_string(),
+        ));
+    }
+
+    // Signature type 5 means a header-structured signature
+    let signature_type = u16::from_be_bytes([lead[78], lead[79]]);
+    if signature_type != 5 {
+        return Err(PkgError::ExtractionError(format!(
+            "Unsupported RPM signature type {}",
+            signature_type
+        )));
+    }
+
+    let signature = read_header(&mut reader)?;
+
+    // The signature header is padded to an 8-byte boundary
+    let padding = (8 - signature.raw_size() % 8) % 8;
+    let mut pad = [0u8; 8];
+    reader
+        .read_exact(&mut pad[..padding])
+        .map_err(|_| PkgError::ExtractionError("Truncated signature padding".to_string()))?;
+
+    let data = read_header_data(&mut reader)?;
+    if let Some(expected) = signature.get_string(signature_tag::SHA256)? {
+        let actual = format!("{:x}", Sha256::digest(&data));
+        if !actual.eq_ignore_ascii_case(&expected) {
+            return Err(PkgError::ExtractionError(format!(
+                "Header SHA256 mismatch (expected {}, got {})",
+                expected, actual
+            )));
+        }
+    }
+    let header = parse_header(&data)?;
+
+    Ok(RpmFile {
+        header,
+        payload: reader,
+    })
+}
+
+/// Reader for .rpm packages
+pub struct RpmReader;
+
+impl PackageReader for RpmReader {
+    fn read(&self, path: &Path) -> Result<PackageInfo, PkgError> {
+        parse_rpm(path)
+    }
+
+    fn extract(&self, path: &Path, extractor: &mut Extractor) -> Result<(), PkgError> {
+        extract_rpm(path, extractor)
+    }
 }
 
 /// Parse an .rpm package
@@ -35,36 +244,383 @@ pub fn parse_rpm(path: &Path) -> Result<PackageInfo, PkgError> {
     // - Header (metadata)
     // - Payload (cpio archive, usually compressed)
 
-    let file = std::fs::File::open(path).map_err(|e| PkgError::IoError(e))?;
+    let file = std::fs::File::open(path).map_err(PkgError::IoError)?;
+    let size = file.metadata().map_err(PkgError::IoError)?.len();
+
+    let rpm = read_rpm(BufReader::new(file))?;
+    let mut info = package_info(&rpm.header)?;
+    info.size = size;
+    info.checksum = archive::sha256_file(path)?;
+
+    Ok(info)
+}
+
+/// Extract the payload of an .rpm package
+pub fn extract_rpm(path: &Path, extractor: &mut Extractor) -> Result<(), PkgError> {
+    let file = File::open(path).map_err(PkgError::IoError)?;
+    let rpm = read_rpm(BufReader::new(file))?;
+
+    if let Some(format) = rpm.header.get_string(tag::PAYLOADFORMAT)?
+        && format != "cpio"
+    {
+        return Err(PkgError::ExtractionError(format!(
+            "Unsupported payload format {}",
+            format
+        )));
+    }
+
+    let compressor = rpm.header.get_string(tag::PAYLOADCOMPRESSOR)?;
+    let compression = Compression::from_rpm_compressor(compressor.as_deref()).ok_or_else(|| {
+        PkgError::ExtractionError(format!(
+            "Unsupported payload compressor {}",
+            compressor.unwrap_or_default()
+        ))
+    })?;
+
+    // Owner names and capabilities live in the header, not the payload
+    let recorded: HashMap<String, FileEntry> = file_entries(&rpm.header)?
+        .into_iter()
+        .map(|entry| (entry.path.clone(), entry))
+        .collect();
+
+    unpack_cpio(extractor, compression.decoder(rpm.payload)?, &recorded)
+}
+
+/// Extract a cpio payload, with owner names and capabilities from the
+/// header's file list
+fn unpack_cpio<R: Read>(
+    extractor: &mut Extractor,
+    reader: R,
+    recorded: &HashMap<String, FileEntry>,
+) -> Result<(), PkgError> {
+    let mut cpio = CpioReader::new(reader);
+
+    // Hardlinked files share (dev, ino); only one member carries the data
+    let mut pending_links: HashMap<(u32, u32, u32), Vec<(String, Attributes)>> = HashMap::new();
+    let mut written_links: HashMap<(u32, u32, u32), String> = HashMap::new();
+
+    while let Some(entry) = cpio.next_entry()? {
+        let link_key = (entry.dev_major, entry.dev_minor, entry.ino);
+        let attrs = cpio_attributes(&entry, recorded.get(&archive::normalize_path(&entry.name)));
+
+        if entry.is_dir() {
+            extractor.create_dir(&entry.name, &attrs)?;
+        } else if entry.is_symlink() {
+            let mut link = String::new();
+            (&mut cpio)
+                .take(4096)
+                .read_to_string(&mut link)
+                .map_err(PkgError::IoError)?;
+            extractor.symlink(&entry.name, &link, &attrs)?;
+        } else if entry.is_file() {
+            if entry.nlink > 1 && entry.file_size == 0 {
+                if let Some(source) = written_links.get(&link_key) {
+                    extractor.hard_link(&entry.name, source)?;
+                } else {
+                    pending_links
+                        .entry(link_key)
+                        .or_default()
+                        .push((entry.name, attrs));
+                }
+                continue;
+            }
+
+            extractor.write_file(&entry.name, &mut cpio, &attrs)?;
+
+            if entry.nlink > 1 {
+                for (link, _) in pending_links.remove(&link_key).unwrap_or_default() {
+                    extractor.hard_link(&link, &entry.name)?;
+                }
+                written_links.insert(link_key, entry.name);
+            }
+        }
+        // Device nodes and FIFOs are not created
+    }
+
+    // Links whose data member never appeared are empty files
+    for (_, links) in pending_links {
+        let mut links = links.into_iter();
+        if let Some((first, attrs)) = links.next() {
+            extractor.write_file(&first, &mut std::io::empty(), &attrs)?;
+            for (link, _) in links {
+                extractor.hard_link(&link, &first)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Attributes of a payload member, with owner names and capabilities from
+/// the header when it lists the file
+fn cpio_attributes(entry: &CpioHeader, recorded: Option<&FileEntry>) -> Attributes {
+    Attributes {
+        mode: entry.mode & 0o7777,
+        user: recorded.map(|r| r.user.clone()).unwrap_or_default(),
+        uid: entry.uid,
+        group: recorded.map(|r| r.group.clone()).unwrap_or_default(),
+        gid: entry.gid,
+        mtime: entry.mtime as u64,
+        xattrs: recorded.map(|r| r.xattrs.clone()).unwrap_or_default(),
+    }
+}
+
+/// Build package metadata from a main header
+pub fn package_info(header: &Header) -> Result<PackageInfo, PkgError> {
+    let name = header
+        .get_string(tag::NAME)?
+        .ok_or_else(|| PkgError::ExtractionError("RPM header has no name".to_string()))?;
+    let version = header.get_string(tag::VERSION)?.unwrap_or_default();
+    let release = header.get_string(tag::RELEASE)?.unwrap_or_default();
+
+    let description = match header.get_string(tag::DESCRIPTION)? {
+        Some(desc) if !desc.is_empty() => desc,
+        _ => header.get_string(tag::SUMMARY)?.unwrap_or_default(),
+    };
 
-    // For now, return a stub - real implementation would parse RPM headers
-    let name = path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("unknown")
-        .to_string();
+    let installed_size = match header.get_u64_array(tag::LONGSIZE)?.first() {
+        Some(size) => *size,
+        None => header
+            .get_u64_array(tag::SIZE)?
+            .first()
+            .copied()
+            .unwrap_or(0),
+    };
+
+    let dependencies = header
+        .dependencies(
+            tag::REQUIRES_NAME,
+            tag::REQUIRES_FLAGS,
+            tag::REQUIRES_VERSION,
+        )?
+        .into_iter()
+        // rpmlib(...) requirements describe rpm features, not packages
+        .filter(|(_, flags)| flags & sense::RPMLIB == 0)
+        .map(|(dep, _)| dep)
+        .filter(|dep| !dep.name.starts_with("rpmlib("))
+        .collect();
+
+    let file_entries = file_entries(header)?;
 
     Ok(PackageInfo {
         name,
-        version: "1.0.0".to_string(),
-        release: 1,
-        arch: "x86_64".to_string(),
+        version,
+        release: release
+            .split('.')
+            .next()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(1),
+        arch: header.get_string(tag::ARCH)?.unwrap_or_default(),
         format: PackageFormat::Rpm,
-        description: String::new(),
-        maintainer: String::new(),
-        license: String::new(),
-        homepage: String::new(),
+        description,
+        maintainer: header.get_string(tag::PACKAGER)?.unwrap_or_default(),
+        license: header.get_string(tag::LICENSE)?.unwrap_or_default(),
+        homepage: header.get_string(tag::URL)?.unwrap_or_default(),
         size: 0,
-        installed_size: 0,
-        dependencies: Vec::new(),
-        conflicts: Vec::new(),
-        provides: Vec::new(),
-        replaces: Vec::new(),
-        files: Vec::new(),
+        installed_size,
+        dependencies,
+        conflicts: header.relations(
+            tag::CONFLICTS_NAME,
+            tag::CONFLICTS_FLAGS,
+            tag::CONFLICTS_VERSION,
+        )?,
+        provides: header.relations(
+            tag::PROVIDES_NAME,
+            tag::PROVIDES_FLAGS,
+            tag::PROVIDES_VERSION,
+        )?,
+        replaces: header.relations(
+            tag::OBSOLETES_NAME,
+            tag::OBSOLETES_FLAGS,
+            tag::OBSOLETES_VERSION,
+        )?,
+        files: file_entries
+            .iter()
+            .filter(|e| !e.is_dir())
+            .map(|e| e.path.clone())
+            .collect(),
+        file_entries,
         checksum: String::new(),
+        scripts: scripts(header)?,
+        triggers: Vec::new(),
     })
 }
 
+/// `%pre`, `%post`, `%preun` and `%postun`, with their interpreters
+///
+/// A script given only as `-p <program>` has no body.
+pub fn scripts(header: &Header) -> Result<Vec<Scriptlet>, PkgError> {
+    let tags = [
+        (tag::PREIN, tag::PREINPROG, ScriptPhase::PreInstall),
+        (tag::POSTIN, tag::POSTINPROG, ScriptPhase::PostInstall),
+        (tag::PREUN, tag::PREUNPROG, ScriptPhase::PreRemove),
+        (tag::POSTUN, tag::POSTUNPROG, ScriptPhase::PostRemove),
+    ];
+
+    let mut scripts = Vec::new();
+    for (script_tag, prog_tag, phase) in tags {
+        let body = header.get_string(script_tag)?;
+        let interpreter = header.get_string_array(prog_tag)?;
+        if body.is_none() && interpreter.is_empty() {
+            continue;
+        }
+        scripts.push(Scriptlet {
+            phase,
+            interpreter: if interpreter.is_empty() {
+                vec!["/bin/sh".to_string()]
+            } else {
+                interpreter
+            },
+            body: body.unwrap_or_default(),
+        });
+    }
+    Ok(scripts)
+}
+
+/// Rebuild per-file records from the file tag arrays
+///
+/// Paths come from DIRNAMES/BASENAMES/DIRINDEXES, falling back to the
+/// legacy FILENAMES tag.
+pub fn file_entries(header: &Header) -> Result<Vec<FileEntry>, PkgError> {
+    let basenames = header.get_string_array(tag::BASENAMES)?;
+    let paths = if basenames.is_empty() {
+        header.get_string_array(tag::FILENAMES)?
+    } else {
+        let dirnames = header.get_string_array(tag::DIRNAMES)?;
+        let dirindexes = header.get_u64_array(tag::DIRINDEXES)?;
+        if dirindexes.len() != basenames.len() {
+            return Err(PkgError::ExtractionError(
+                "DIRINDEXES and BASENAMES differ in length".to_string(),
+            ));
+        }
+
+        basenames
+            .iter()
+            .zip(&dirindexes)
+            .map(|(base, &idx)| {
+                let dir = dirnames.get(idx as usize).ok_or_else(|| {
+                    PkgError::ExtractionError(format!("Directory index {} out of range", idx))
+                })?;
+                Ok(format!("{}{}", dir, base))
+            })
+            .collect::<Result<Vec<_>, PkgError>>()?
+    };
+
+    let mut sizes = header.get_u64_array(tag::LONGFILESIZES)?;
+    if sizes.is_empty() {
+        sizes = header.get_u64_array(tag::FILESIZES)?;
+    }
+    let modes = header.get_u64_array(tag::FILEMODES)?;
+    let mtimes = header.get_u64_array(tag::FILEMTIMES)?;
+    let flags = header.get_u64_array(tag::FILEFLAGS)?;
+    let links = header.get_string_array(tag::FILELINKTOS)?;
+    let users = header.get_string_array(tag::FILEUSERNAME)?;
+    let groups = header.get_string_array(tag::FILEGROUPNAME)?;
+    let caps = header.get_string_array(tag::FILECAPS)?;
+
+    // Only SHA-256 digests are kept; older packages use MD5
+    let digest_algo = header
+        .get_u64_array(tag::FILEDIGESTALGO)?
+        .first()
+        .copied()
+        .unwrap_or(1);
+    let digests = if digest_algo == DIGEST_ALGO_SHA256 {
+        header.get_string_array(tag::FILEDIGESTS)?
+    } else {
+        Vec::new()
+    };
+
+    Ok(paths
+        .into_iter()
+        .enumerate()
+        .map(|(i, path)| FileEntry {
+            path,
+            mode: modes.get(i).copied().unwrap_or(0) as u32,
+            size: sizes.get(i).copied().unwrap_or(0),
+            mtime: mtimes.get(i).copied().unwrap_or(0),
+            digest: digests.get(i).cloned().unwrap_or_default(),
+            user: users.get(i).cloned().unwrap_or_default(),
+            group: groups.get(i).cloned().unwrap_or_default(),
+            link_target: links.get(i).cloned().unwrap_or_default(),
+            flags: flags.get(i).copied().unwrap_or(0) as u32,
+            xattrs: caps
+                .get(i)
+                .and_then(|caps| capability_xattr(caps))
+                .map(|value| vec![(CAPABILITY_XATTR.to_string(), value)])
+                .unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Encode a `cap_from_text(3)` string (`cap_net_raw=ep`) as the value of
+/// the `security.capability` attribute
+///
+/// Returns `None` for an empty set or unknown capability names.
+pub fn capability_xattr(text: &str) -> Option<Vec<u8>> {
+    let (mut effective, mut permitted, mut inheritable) = (0u64, 0u64, 0u64);
+
+    for clause in text.split_whitespace() {
+        let op = clause.find(['=', '+', '-'])?;
+        let (names, mut actions) = clause.split_at(op);
+
+        let mut caps = 0u64;
+        for name in names.split(',').filter(|n| !n.is_empty()) {
+            if name == "all" {
+                caps = (1 << CAPABILITIES.len()) - 1;
+            } else {
+                let name = name.strip_prefix("cap_")?;
+                let bit = CAPABILITIES.iter().position(|&c| c == name)?;
+                caps |= 1 << bit;
+            }
+        }
+        // `=ep` alone means every capability
+        if names.is_empty() {
+            caps = (1 << CAPABILITIES.len()) - 1;
+        }
+
+        while let Some(op) = actions.chars().next() {
+            let flags_end = actions[1..]
+                .find(['=', '+', '-'])
+                .map_or(actions.len(), |i| i + 1);
+            let flags = &actions[1..flags_end];
+            actions = &actions[flags_end..];
+
+            for set in [&mut effective, &mut permitted, &mut inheritable] {
+                if op == '=' {
+                    *set &= !caps;
+                }
+            }
+            for flag in flags.chars() {
+                let set = match flag {
+                    'e' => &mut effective,
+                    'p' => &mut permitted,
+                    'i' => &mut inheritable,
+                    _ => return None,
+                };
+                if op == '-' {
+                    *set &= !caps;
+                } else {
+                    *set |= caps;
+                }
+            }
+        }
+    }
+
+    if permitted | inheritable == 0 {
+        return None;
+    }
+
+    // struct vfs_cap_data, revision 2: magic, then permitted and
+    // inheritable for the low and high 32 capabilities
+    let magic = VFS_CAP_REVISION_2 | u32::from(effective != 0);
+    let mut value = magic.to_le_bytes().to_vec();
+    for half in [0, 32] {
+        value.extend_from_slice(&((permitted >> half) as u32).to_le_bytes());
+        value.extend_from_slice(&((inheritable >> half) as u32).to_le_bytes());
+    }
+    Some(value)
+}
+
 /// RPM header entry
 #[derive(Debug)]
 pub struct HeaderEntry {
@@ -74,15 +630,575 @@ pub struct HeaderEntry {
     pub count: u32,
 }
 
-/// Parse RPM header
-pub fn parse_header(data: &[u8]) -> Result<Vec<HeaderEntry>, PkgError> {
+/// Decoded header entry value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderValue {
+    Null,
+    Char(Vec<u8>),
+    Int8(Vec<u8>),
+    Int16(Vec<u16>),
+    Int32(Vec<u32>),
+    Int64(Vec<u64>),
+    String(String),
+    Bin(Vec<u8>),
+    StringArray(Vec<String>),
+    I18nString(Vec<String>),
+}
+
+impl HeaderValue {
+    /// Integer values widened to u64
+    pub fn as_u64s(&self) -> Option<Vec<u64>> {
+        match self {
+            Self::Char(v) | Self::Int8(v) => Some(v.iter().map(|&n| n as u64).collect()),
+            Self::Int16(v) => Some(v.iter().map(|&n| n as u64).collect()),
+            Self::Int32(v) => Some(v.iter().map(|&n| n as u64).collect()),
+            Self::Int64(v) => Some(v.clone()),
+            _ => None,
+        }
+    }
+
+    /// Name of the entry type, for error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Null => "NULL",
+            Self::Char(_) => "CHAR",
+            Self::Int8(_) => "INT8",
+            Self::Int16(_) => "INT16",
+            Self::Int32(_) => "INT32",
+            Self::Int64(_) => "INT64",
+            Self::String(_) => "STRING",
+            Self::Bin(_) => "BIN",
+            Self::StringArray(_) => "STRING_ARRAY",
+            Self::I18nString(_) => "I18NSTRING",
+        }
+    }
+}
+
+/// Parsed header structure: index entries plus their data store
+#[derive(Debug)]
+pub struct Header {
+    pub entries: Vec<HeaderEntry>,
+    pub store: Vec<u8>,
+}
+
+impl Header {
+    /// Size of the header structure on disk
+    pub fn raw_size(&self) -> usize {
+        16 + self.entries.len() * 16 + self.store.len()
+    }
+
+    /// Find the entry for a tag
+    pub fn entry(&self, tag: u32) -> Option<&HeaderEntry> {
+        self.entries.iter().find(|e| e.tag == tag)
+    }
+
+    /// Decode the value of a tag, if present
+    pub fn get(&self, tag: u32) -> Result<Option<HeaderValue>, PkgError> {
+        self.entry(tag).map(|entry| self.value(entry)).transpose()
+    }
+
+    /// Decode an entry's value from the data store
+    pub fn value(&self, entry: &HeaderEntry) -> Result<HeaderValue, PkgError> {
+        let tag = entry.tag;
+        let count = entry.count as usize;
+        let start = entry.offset as usize;
+        if start > self.store.len() {
+            return Err(out_of_bounds(tag));
+        }
+
+        // Fixed-width types: count * width bytes starting at offset
+        let fixed = |width: usize| -> Result<&[u8], PkgError> {
+            let end = count
+                .checked_mul(width)
+                .and_then(|len| start.checked_add(len))
+                .ok_or_else(|| out_of_bounds(tag))?;
+            self.store.get(start..end).ok_or_else(|| out_of_bounds(tag))
+        };
+
+        // NUL-terminated strings: count of them starting at offset
+        let strings = |count: usize| -> Result<Vec<String>, PkgError> {
+            // Every string takes at least one byte, so a larger count is corrupt
+            if count > self.store.len() - start {
+                return Err(out_of_bounds(tag));
+            }
+            let mut strings = Vec::with_capacity(count);
+            let mut pos = start;
+            for _ in 0..count {
+                let rest = self.store.get(pos..).ok_or_else(|| out_of_bounds(tag))?;
+                let len = rest
+                    .iter()
+                    .position(|&b| b == 0)
+                    .ok_or_else(|| out_of_bounds(tag))?;
+                strings.push(String::from_utf8_lossy(&rest[..len]).into_owned());
+                pos += len + 1;
+            }
+            Ok(strings)
+        };
+
+        Ok(match entry.entry_type {
+            entry_type::NULL => HeaderValue::Null,
+            entry_type::CHAR => HeaderValue::Char(fixed(1)?.to_vec()),
+            entry_type::INT8 => HeaderValue::Int8(fixed(1)?.to_vec()),
+            entry_type::INT16 => HeaderValue::Int16(
+                fixed(2)?
+                    .chunks_exact(2)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                    .collect(),
+            ),
+            entry_type::INT32 => HeaderValue::Int32(
+                fixed(4)?
+                    .chunks_exact(4)
+                    .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect(),
+            ),
+            entry_type::INT64 => HeaderValue::Int64(
+                fixed(8)?
+                    .chunks_exact(8)
+                    .map(|b| u64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+                    .collect(),
+            ),
+            entry_type::STRING => HeaderValue::String(strings(1)?.pop().unwrap_or_default()),
+            entry_type::BIN => HeaderValue::Bin(fixed(1)?.to_vec()),
+            entry_type::STRING_ARRAY => HeaderValue::StringArray(strings(count)?),
+            entry_type::I18NSTRING => HeaderValue::I18nString(strings(count)?),
+            other => {
+                return Err(PkgError::ExtractionError(format!(
+                    "Tag {} has unknown type {}",
+                    tag, other
+                )));
+            }
+        })
+    }
+
+    /// Get a string tag (STRING, or the first I18NSTRING/STRING_ARRAY element)
+    pub fn get_string(&self, tag: u32) -> Result<Option<String>, PkgError> {
+        Ok(self.get_string_array(tag)?.into_iter().next())
+    }
+
+    /// Get a string array tag
+    pub fn get_string_array(&self, tag: u32) -> Result<Vec<String>, PkgError> {
+        match self.get(tag)? {
+            None => Ok(Vec::new()),
+            Some(HeaderValue::String(s)) => Ok(vec![s]),
+            Some(HeaderValue::StringArray(v)) | Some(HeaderValue::I18nString(v)) => Ok(v),
+            Some(other) => Err(type_mismatch(tag, &other, "a string")),
+        }
+    }
+
+    /// Get an integer tag (INT8/16/32/64) widened to u64
+    pub fn get_u64_array(&self, tag: u32) -> Result<Vec<u64>, PkgError> {
+        match self.get(tag)? {
+            None => Ok(Vec::new()),
+            Some(value) => value
+                .as_u64s()
+                .ok_or_else(|| type_mismatch(tag, &value, "an integer")),
+        }
+    }
+
+    /// Zip a name/flags/version tag triple into dependencies with their raw flags
+    pub fn dependencies(
+        &self,
+        name_tag: u32,
+        flags_tag: u32,
+        version_tag: u32,
+    ) -> Result<Vec<(Dependency, u32)>, PkgError> {
+        let names = self.get_string_array(name_tag)?;
+        let flags = self.get_u64_array(flags_tag)?;
+        let versions = self.get_string_array(version_tag)?;
+
+        Ok(names
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let flags = flags.get(i).copied().unwrap_or(0) as u32;
+                let constraint = versions
+                    .get(i)
+                    .filter(|v| !v.is_empty())
+                    .and_then(|v| constraint_from_flags(flags, v));
+                (dependency(name, constraint), flags)
+            })
+            .collect())
+    }
+
+    /// Relations rendered the way `rpm -q --provides` shows them
+    fn relations(
+        &self,
+        name_tag: u32,
+        flags_tag: u32,
+        version_tag: u32,
+    ) -> Result<Vec<String>, PkgError> {
+        Ok(self
+            .dependencies(name_tag, flags_tag, version_tag)?
+            .into_iter()
+            .map(|(dep, _)| match dep.version_constraint {
+                Some(c) => format!("{} {}", dep.name, c),
+                None => dep.name,
+            })
+            .collect())
+    }
+}
+
+/// Map RPMSENSE comparison bits to a version constraint
+fn constraint_from_flags(flags: u32, version: &str) -> Option<VersionConstraint> {
+    let less = flags & sense::LESS != 0;
+    let greater = flags & sense::GREATER != 0;
+    let equal = flags & sense::EQUAL != 0;
+
+    let operator = match (less, greater, equal) {
+        (true, false, true) => ConstraintOp::Le,
+        (true, false, false) => ConstraintOp::Lt,
+        (false, true, true) => ConstraintOp::Ge,
+        (false, true, false) => ConstraintOp::Gt,
+        (false, false, true) => ConstraintOp::Eq,
+        _ => return None,
+    };
+
+    Some(VersionConstraint {
+        operator,
+        version: version.to_string(),
+    })
+}
+
+/// Compare two RPM versions (`[epoch:]version[-release]`) the way rpm
+/// does; releases only count when both sides have one, as in a
+/// `Requires: foo >= 1.2` against `foo-1.2-3`
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn split(evr: &str) -> (u64, &str, Option<&str>) {
+        let (epoch, rest) = match evr.split_once(':') {
+            Some((epoch, rest)) => (epoch.parse().unwrap_or(0), rest),
+            None => (0, evr),
+        };
+        match rest.rsplit_once('-') {
+            Some((version, release)) => (epoch, version, Some(release)),
+            None => (epoch, rest, None),
+        }
+    }
+
+    let (epoch_a, version_a, release_a) = split(a);
+    let (epoch_b, version_b, release_b) = split(b);
+    epoch_a
+        .cmp(&epoch_b)
+        .then_with(|| vercmp(version_a, version_b))
+        .then_with(|| match (release_a, release_b) {
+            (Some(a), Some(b)) => vercmp(a, b),
+            _ => Ordering::Equal,
+        })
+}
+
+/// rpm's `rpmvercmp`: alphanumeric segments, numbers above letters and
+/// compared by value; `~` sorts before the end, `^` after it but before
+/// any further segment
+fn vercmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    let separator = |c: &u8| !c.is_ascii_alphanumeric() && *c != b'~' && *c != b'^';
+
+    while !a.is_empty() || !b.is_empty() {
+        while a.first().is_some_and(separator) {
+            a = &a[1..];
+        }
+        while b.first().is_some_and(separator) {
+            b = &b[1..];
+        }
+
+        let (first_a, first_b) = (a.first().copied(), b.first().copied());
+        if first_a == Some(b'~') || first_b == Some(b'~') {
+            if first_a != Some(b'~') {
+                return Ordering::Greater;
+            }
+            if first_b != Some(b'~') {
+                return Ordering::Less;
+            }
+            (a, b) = (&a[1..], &b[1..]);
+            continue;
+        }
+        if first_a == Some(b'^') || first_b == Some(b'^') {
+            if a.is_empty() {
+                return Ordering::Less;
+            }
+            if b.is_empty() {
+                return Ordering::Greater;
+            }
+            if first_a != Some(b'^') {
+                return Ordering::Greater;
+            }
+            if first_b != Some(b'^') {
+                return Ordering::Less;
+            }
+            (a, b) = (&a[1..], &b[1..]);
+            continue;
+        }
+        if a.is_empty() || b.is_empty() {
+            break;
+        }
+
+        let numeric = a[0].is_ascii_digit();
+        let segment = |s: &[u8]| {
+            s.iter()
+                .position(|c| match numeric {
+                    true => !c.is_ascii_digit(),
+                    false => !c.is_ascii_alphabetic(),
+                })
+                .unwrap_or(s.len())
+        };
+        let (segment_a, rest_a) = a.split_at(segment(a));
+        let (segment_b, rest_b) = b.split_at(segment(b));
+        // Segments of different kinds: the number is newer
+        if segment_b.is_empty() {
+            return match numeric {
+                true => Ordering::Greater,
+                false => Ordering::Less,
+            };
+        }
+
+        let order = match numeric {
+            true => {
+                let trim = |s: &[u8]| s.iter().take_while(|c| **c == b'0').count();
+                let (x, y) = (&segment_a[trim(segment_a)..], &segment_b[trim(segment_b)..]);
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            false => segment_a.cmp(segment_b),
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+        (a, b) = (rest_a, rest_b);
+    }
+
+    // Whichever has segments left is newer
+    b.is_empty().cmp(&a.is_empty())
+}
+
+/// Build a dependency, parsing `(...)` names as boolean expressions
+///
+/// Expressions that fail to parse are kept as plain names.
+pub fn dependency(name: String, constraint: Option<VersionConstraint>) -> Dependency {
+    let rich = if name.starts_with('(') {
+        parse_rich_dependency(&name).ok()
+    } else {
+        None
+    };
+
+    let mut dep = Dependency::new(name, constraint);
+    dep.rich = rich.map(Box::new);
+    dep
+}
+
+/// Parse an RPM boolean dependency like `(foo >= 1.0 with foo < 2.0)`
+pub fn parse_rich_dependency(expr: &str) -> Result<RichDependency, PkgError> {
+    let mut parser = RichParser {
+        input: expr,
+        pos: 0,
+    };
+    let dep = parser.expression()?;
+    parser.skip_whitespace();
+    if parser.pos != expr.len() {
+        return Err(parser.error("trailing input"));
+    }
+    Ok(dep)
+}
+
+struct RichParser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl RichParser<'_> {
+    fn rest(&self) -> &str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn error(&self, msg: &str) -> PkgError {
+        PkgError::ParseError(format!(
+            "Invalid rich dependency {:?} at {}: {}",
+            self.input, self.pos, msg
+        ))
+    }
+
+    /// `( operand [op operand]... )`
+    fn expression(&mut self) -> Result<RichDependency, PkgError> {
+        self.skip_whitespace();
+        if !self.rest().starts_with('(') {
+            return Err(self.error("expected '('"));
+        }
+        self.pos += 1;
+
+        let first = self.operand()?;
+        self.skip_whitespace();
+
+        let keyword = self.word();
+        let dep = match keyword.as_str() {
+            "" => first,
+            "and" | "or" | "with" => {
+                let mut operands = vec![first, self.operand()?];
+                loop {
+                    self.skip_whitespace();
+                    let save = self.pos;
+                    if self.word() == keyword {
+                        operands.push(self.operand()?);
+                    } else {
+                        self.pos = save;
+                        break;
+                    }
+                }
+                match keyword.as_str() {
+                    "and" => RichDependency::And(operands),
+                    "or" => RichDependency::Or(operands),
+                    _ => RichDependency::With(operands),
+                }
+            }
+            "without" => RichDependency::Without(Box::new(first), Box::new(self.operand()?)),
+            "if" | "unless" => {
+                let then = Box::new(first);
+                let condition = Box::new(self.operand()?);
+                self.skip_whitespace();
+                let save = self.pos;
+                let otherwise = if self.word() == "else" {
+                    Some(Box::new(self.operand()?))
+                } else {
+                    self.pos = save;
+                    None
+                };
+                if keyword == "if" {
+                    RichDependency::If {
+                        then,
+                        condition,
+                        otherwise,
+                    }
+                } else {
+                    RichDependency::Unless {
+                        then,
+                        condition,
+                        otherwise,
+                    }
+                }
+            }
+            other => return Err(self.error(&format!("unknown operator {:?}", other))),
+        };
+
+        self.skip_whitespace();
+        if !self.rest().starts_with(')') {
+            return Err(self.error("expected ')'"));
+        }
+        self.pos += 1;
+
+        Ok(dep)
+    }
+
+    /// A nested expression or a simple `name [op version]`
+    fn operand(&mut self) -> Result<RichDependency, PkgError> {
+        self.skip_whitespace();
+        if self.rest().starts_with('(') {
+            return self.expression();
+        }
+
+        let name = self.word();
+        if name.is_empty() {
+            return Err(self.error("expected a package name"));
+        }
+
+        self.skip_whitespace();
+        let op_len = self
+            .rest()
+            .find(|c: char| !matches!(c, '<' | '>' | '='))
+            .unwrap_or(self.rest().len());
+        let constraint = if op_len > 0 {
+            let operator = match &self.rest()[..op_len] {
+                "<" => ConstraintOp::Lt,
+                "<=" => ConstraintOp::Le,
+                "=" | "==" => ConstraintOp::Eq,
+                ">=" => ConstraintOp::Ge,
+                ">" => ConstraintOp::Gt,
+                other => return Err(self.error(&format!("unknown comparison {:?}", other))),
+            };
+            self.pos += op_len;
+            self.skip_whitespace();
+            let version = self.word();
+            if version.is_empty() {
+                return Err(self.error("expected a version"));
+            }
+            Some(VersionConstraint { operator, version })
+        } else {
+            None
+        };
+
+        Ok(RichDependency::Atom(Dependency::new(name, constraint)))
+    }
+
+    /// Read up to whitespace or an unbalanced `)`, so names like
+    /// `perl(Foo::Bar)` stay intact
+    fn word(&mut self) -> String {
+        let mut depth = 0usize;
+        let mut end = self.rest().len();
+        for (i, c) in self.rest().char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 => {
+                    end = i;
+                    break;
+                }
+                ')' => depth -= 1,
+                c if c.is_whitespace() => {
+                    end = i;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let word = self.rest()[..end].to_string();
+        self.pos += end;
+        word
+    }
+}
+
+fn type_mismatch(tag: u32, value: &HeaderValue, expected: &str) -> PkgError {
+    PkgError::ExtractionError(format!(
+        "Tag {} has type {}, expected {}",
+        tag,
+        value.type_name(),
+        expected
+    ))
+}
+
+fn out_of_bounds(tag: u32) -> PkgError {
+    PkgError::ExtractionError(format!("Tag {} data out of bounds", tag))
+}
+
+/// Read one header structure from a stream
+pub fn read_header<R: Read>(reader: &mut R) -> Result<Header, PkgError> {
+    parse_header(&read_header_data(reader)?)
+}
+
+/// Read the raw bytes of one header structure from a stream
+fn read_header_data<R: Read>(reader: &mut R) -> Result<Vec<u8>, PkgError> {
+    let mut intro = [0u8; 16];
+    reader
+        .read_exact(&mut intro)
+        .map_err(|_| PkgError::ExtractionError("Truncated RPM header".to_string()))?;
+
+    let (num_entries, data_size) = header_sizes(&intro)?;
+
+    let mut data = intro.to_vec();
+    data.resize(16 + num_entries * 16 + data_size, 0);
+    reader
+        .read_exact(&mut data[16..])
+        .map_err(|_| PkgError::ExtractionError("Truncated RPM header".to_string()))?;
+
+    Ok(data)
+}
+
+/// Validate magic and return (entry count, data store size)
+fn header_sizes(data: &[u8]) -> Result<(usize, usize), PkgError> {
     if data.len() < 16 {
         return Err(PkgError::ExtractionError("Header too short".to_string()));
     }
 
     // Check magic
-    let magic = &data[0..4];
-    if magic != [0x8e, 0xad, 0xe8, 0x01] {
+    if data[0..4] != HEADER_MAGIC {
         return Err(PkgError::ExtractionError(
             "Invalid header magic".to_string(),
         ));
@@ -91,47 +1207,259 @@ pub fn parse_header(data: &[u8]) -> Result<Vec<HeaderEntry>, PkgError> {
     let num_entries = u32::from_be_bytes([data[8], data[9], data[10], data[11]]) as usize;
     let data_size = u32::from_be_bytes([data[12], data[13], data[14], data[15]]) as usize;
 
-    let mut entries = Vec::with_capacity(num_entries);
+    if num_entries > MAX_INDEX_ENTRIES || data_size > MAX_DATA_SIZE {
+        return Err(PkgError::ExtractionError(format!(
+            "Header too large ({} entries, {} bytes)",
+            num_entries, data_size
+        )));
+    }
+
+    Ok((num_entries, data_size))
+}
+
+/// Parse RPM header
+pub fn parse_header(data: &[u8]) -> Result<Header, PkgError> {
+    let (num_entries, data_size) = header_sizes(data)?;
+
     let entry_start = 16;
+    let store_start = entry_start + num_entries * 16;
+    let store = data
+        .get(store_start..store_start + data_size)
+        .ok_or_else(|| PkgError::ExtractionError("Header data store truncated".to_string()))?;
+
+    let mut entries = Vec::with_capacity(num_entries);
 
     for i in 0..num_entries {
         let offset = entry_start + i * 16;
-        if offset + 16 > data.len() {
-            break;
-        }
+        let field = |n: usize| {
+            let at = offset + n * 4;
+            u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+        };
 
-        let tag = u32::from_be_bytes([
-            data[offset],
-            data[offset + 1],
-            data[offset + 2],
-            data[offset + 3],
-        ]);
-        let entry_type = u32::from_be_bytes([
-            data[offset + 4],
-            data[offset + 5],
-            data[offset + 6],
-            data[offset + 7],
-        ]);
-        let data_offset = u32::from_be_bytes([
-            data[offset + 8],
-            data[offset + 9],
-            data[offset + 10],
-            data[offset + 11],
-        ]);
-        let count = u32::from_be_bytes([
-            data[offset + 12],
-            data[offset + 13],
-            data[offset + 14],
-            data[offset + 15],
-        ]);
+        let data_offset = field(2);
+        if data_offset as usize > data_size {
+            return Err(PkgError::ExtractionError(format!(
+                "Header entry {} offset {} beyond data store",
+                i, data_offset
+            )));
+        }
 
         entries.push(HeaderEntry {
-            tag,
-            entry_type,
+            tag: field(0),
+            entry_type: field(1),
             offset: data_offset,
-            count,
+            count: field(3),
         });
     }
 
-    Ok(entries)
+    Ok(Header {
+        entries,
+        store: store.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpio::tests::{newc, trailer};
+
+    fn scratch(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rpm-next-rpm-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("root")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn payload_members_keep_mode_owner_mtime_and_hard_links() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = scratch("payload-metadata");
+        let root = dir.join("root");
+        std::fs::create_dir_all(root.join("etc")).unwrap();
+        std::fs::write(root.join("etc/passwd"), "builder:x:4321:4321::/:/bin/sh\n").unwrap();
+        std::fs::write(root.join("etc/group"), "builder:x:4321:\n").unwrap();
+
+        // Owner names come from the header, not the payload
+        let recorded: HashMap<String, FileEntry> = ["/usr/bin/tool", "/usr/bin/alias"]
+            .iter()
+            .map(|path| {
+                let entry = FileEntry {
+                    path: path.to_string(),
+                    user: "builder".to_string(),
+                    group: "builder".to_string(),
+                    ..Default::default()
+                };
+                (path.to_string(), entry)
+            })
+            .collect();
+        let payload = [
+            newc("./usr/bin/alias", 7, 0o104755, 2, 1_000_000, b""),
+            newc("./usr/bin/tool", 7, 0o104755, 2, 1_000_000, b"tool"),
+            trailer(),
+        ]
+        .concat();
+
+        let mut extractor = Extractor::new(&root).unwrap();
+        unpack_cpio(&mut extractor, &payload[..], &recorded).unwrap();
+        let entries = extractor.finish().unwrap();
+        let tool = std::fs::metadata(root.join("usr/bin/tool")).unwrap();
+        let alias = std::fs::metadata(root.join("usr/bin/alias")).unwrap();
+        // Ownership only changes when running as root
+        let privileged = std::fs::metadata(&dir).unwrap().uid() == 0;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(tool.mode() & 0o7777, 0o4755);
+        assert_eq!(tool.mtime(), 1_000_000);
+        if privileged {
+            assert_eq!((tool.uid(), tool.gid()), (4321, 4321));
+        }
+        assert_eq!(alias.ino(), tool.ino());
+        assert_eq!(entries.len(), 2);
+        for entry in &entries {
+            assert_eq!(entry.mode, 0o104755);
+            assert_eq!(
+                (entry.user.as_str(), entry.group.as_str()),
+                ("builder", "builder")
+            );
+            assert_eq!(entry.mtime, 1_000_000);
+            assert_eq!(entry.size, 4);
+        }
+    }
+
+    #[test]
+    fn hard_links_are_grouped_by_inode_wherever_the_data_is() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = scratch("hard-links");
+        let root = dir.join("root");
+        let payload = [
+            // Link before the data member
+            newc("./a1", 1, 0o100644, 2, 0, b""),
+            newc("./a2", 1, 0o100644, 2, 0, b"a"),
+            // Links after it
+            newc("./b1", 2, 0o100644, 3, 0, b"b"),
+            newc("./b2", 2, 0o100644, 3, 0, b""),
+            newc("./b3", 2, 0o100644, 3, 0, b""),
+            // No data member at all
+            newc("./c1", 3, 0o100644, 2, 0, b""),
+            newc("./c2", 3, 0o100644, 2, 0, b""),
+            // An empty file that is not a link
+            newc("./d", 4, 0o100644, 1, 0, b""),
+            trailer(),
+        ]
+        .concat();
+
+        let mut extractor = Extractor::new(&root).unwrap();
+        unpack_cpio(&mut extractor, &payload[..], &HashMap::new()).unwrap();
+        extractor.finish().unwrap();
+        let files: Vec<(u64, String)> = ["a1", "a2", "b1", "b2", "b3", "c1", "c2", "d"]
+            .iter()
+            .map(|name| {
+                let path = root.join(name);
+                let ino = std::fs::metadata(&path).unwrap().ino();
+                (ino, std::fs::read_to_string(&path).unwrap())
+            })
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let [a1, a2, b1, b2, b3, c1, c2, d] = &files[..] else {
+            unreachable!()
+        };
+        assert_eq!(a1, a2);
+        assert_eq!(a1.1, "a");
+        assert!(b1 == b2 && b2 == b3);
+        assert_eq!(b1.1, "b");
+        assert_eq!(c1, c2);
+        assert_eq!(c1.1, "");
+        assert_eq!(d.1, "");
+        let inodes: std::collections::HashSet<u64> = [a1.0, b1.0, c1.0, d.0].into();
+        assert_eq!(inodes.len(), 4);
+    }
+
+    #[test]
+    fn versions_compare_like_rpmvercmp() {
+        for (lower, higher) in [
+            ("1.0", "1.0.1"),
+            ("1.9", "1.10"),
+            ("1.0a", "1.0.1"),
+            ("a", "1"),
+            ("1.0~rc1", "1.0"),
+            ("1.0", "1.0^git1"),
+            ("1.0^git1", "1.0.1"),
+            ("1.0-1", "1.0-2"),
+            ("1.0-9.fc40", "1.0-10.fc40"),
+            ("2.0", "1:1.0"),
+        ] {
+            assert_eq!(
+                compare_versions(lower, higher),
+                Ordering::Less,
+                "{lower} < {higher}"
+            );
+            assert_eq!(compare_versions(higher, lower), Ordering::Greater);
+        }
+        assert_eq!(compare_versions("1.01", "1.1"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0_1", "1.0.1"), Ordering::Equal);
+        // Without a release, any release matches
+        assert_eq!(compare_versions("1.2", "1.2-3"), Ordering::Equal);
+    }
+
+    /// A header structure of `(tag, type, offset, count)` entries
+    fn header(entries: &[(u32, u32, u32, u32)], store: &[u8]) -> Vec<u8> {
+        let mut data = HEADER_MAGIC.to_vec();
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        data.extend_from_slice(&(store.len() as u32).to_be_bytes());
+        for &(tag, entry_type, offset, count) in entries {
+            for field in [tag, entry_type, offset, count] {
+                data.extend_from_slice(&field.to_be_bytes());
+            }
+        }
+        data.extend_from_slice(store);
+        data
+    }
+
+    fn extraction_error<T: std::fmt::Debug>(result: Result<T, PkgError>) -> bool {
+        matches!(result, Err(PkgError::ExtractionError(_)))
+    }
+
+    #[test]
+    fn corrupt_header_entries_are_extraction_errors() {
+        let good =
+            parse_header(&header(&[(tag::NAME, entry_type::STRING, 0, 1)], b"pkg\0")).unwrap();
+        assert_eq!(good.get_string(tag::NAME).unwrap().as_deref(), Some("pkg"));
+
+        // Offset beyond the data store
+        assert!(extraction_error(parse_header(&header(
+            &[(tag::NAME, entry_type::STRING, 5, 1)],
+            b"pkg\0"
+        ))));
+
+        // Count larger than the store
+        for entry_type in [entry_type::INT32, entry_type::STRING_ARRAY] {
+            let h = parse_header(&header(&[(tag::NAME, entry_type, 0, 100)], b"a\0b\0")).unwrap();
+            assert!(extraction_error(h.value(&h.entries[0])));
+        }
+
+        // STRING without a terminating NUL
+        let h = parse_header(&header(&[(tag::NAME, entry_type::STRING, 0, 1)], b"pkg")).unwrap();
+        assert!(extraction_error(h.value(&h.entries[0])));
+
+        // Unknown type
+        let h = parse_header(&header(&[(tag::NAME, 42, 0, 1)], b"pkg\0")).unwrap();
+        assert!(extraction_error(h.value(&h.entries[0])));
+
+        // Type mismatch
+        let h = parse_header(&header(
+            &[
+                (tag::NAME, entry_type::INT32, 0, 1),
+                (tag::SIZE, entry_type::STRING, 4, 1),
+            ],
+            b"\0\0\0\x01pkg\0",
+        ))
+        .unwrap();
+        assert!(extraction_error(h.get_string(tag::NAME)));
+        assert!(extraction_error(h.get_u64_array(tag::SIZE)));
+    }
 }
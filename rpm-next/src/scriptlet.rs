@@ -0,0 +1,347 @@
+//! Maintainer Scripts
+//!
+//! Install and removal logic carried by packages: Debian maintainer scripts,
+//! RPM `%pre`/`%post`/`%preun`/`%postun` and the functions of a native
+//! `.INSTALL`. Scripts are stored with the package and run chrooted into the
+//! install root, with the arguments each format defines.
+
+use std::fs::{DirBuilder, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::extract;
+use crate::{PackageFormat, PackageInfo, PkgError};
+
+/// `PATH` for scripts and hooks, which should not depend on the caller's environment
+const SCRIPT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Point in a package operation where a script runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScriptPhase {
+    PreInstall,
+    PostInstall,
+    /// Native packages only; Debian and RPM scripts tell upgrades by their
+    /// arguments
+    PreUpgrade,
+    PostUpgrade,
+    PreRemove,
+    PostRemove,
+}
+
+impl ScriptPhase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PreInstall => "pre-install",
+            Self::PostInstall => "post-install",
+            Self::PreUpgrade => "pre-upgrade",
+            Self::PostUpgrade => "post-upgrade",
+            Self::PreRemove => "pre-remove",
+            Self::PostRemove => "post-remove",
+        }
+    }
+
+    /// Whether a failure aborts the operation rather than being reported
+    fn is_pre(self) -> bool {
+        matches!(self, Self::PreInstall | Self::PreUpgrade | Self::PreRemove)
+    }
+}
+
+/// A maintainer script
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scriptlet {
+    pub phase: ScriptPhase,
+    /// Program and leading arguments the script file is passed to
+    pub interpreter: Vec<String>,
+    /// Script text; empty when the interpreter runs on its own
+    /// (`%post -p /sbin/ldconfig`)
+    pub body: String,
+}
+
+impl Scriptlet {
+    /// A script run by the interpreter on its `#!` line, or `/bin/sh`
+    pub fn from_script(phase: ScriptPhase, body: String) -> Self {
+        let interpreter = body
+            .lines()
+            .next()
+            .and_then(|line| line.strip_prefix("#!"))
+            .map(|line| {
+                line.split_whitespace()
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|interpreter| !interpreter.is_empty())
+            .unwrap_or_else(|| vec!["/bin/sh".to_string()]);
+        Self {
+            phase,
+            interpreter,
+            body,
+        }
+    }
+}
+
+/// What to do with maintainer scripts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScriptPolicy {
+    #[default]
+    Run,
+    /// Print scripts instead of running them, e.g. for untrusted sources
+    Log,
+    Skip,
+}
+
+impl ScriptPolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "run" => Some(Self::Run),
+            "log" => Some(Self::Log),
+            "skip" => Some(Self::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// Stage of installing a package, possibly over an older version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    BeforeUnpack,
+    AfterUnpack,
+    /// After the old version's leftover files are removed
+    AfterCleanup,
+}
+
+/// A script to run: whose, in which phase, and with which arguments
+#[derive(Debug)]
+pub struct Invocation<'a> {
+    pub pkg: &'a PackageInfo,
+    pub phase: ScriptPhase,
+    pub args: Vec<String>,
+}
+
+/// Scripts to run at `stage` of installing `new` over `old`, in order
+///
+/// Follows dpkg (old `prerm upgrade` before unpacking, old `postrm upgrade`
+/// after) and rpm (old `%preun`/`%postun` after the new `%post`).
+pub fn install_scripts<'a>(
+    stage: Stage,
+    new: &'a PackageInfo,
+    old: Option<&'a PackageInfo>,
+) -> Vec<Invocation<'a>> {
+    let (pre, post) = match (old, new.format) {
+        (Some(_), PackageFormat::Native) => (ScriptPhase::PreUpgrade, ScriptPhase::PostUpgrade),
+        _ => (ScriptPhase::PreInstall, ScriptPhase::PostInstall),
+    };
+    let old_format = old.map(|old| old.format);
+
+    let mut calls = Vec::new();
+    match stage {
+        Stage::BeforeUnpack => {
+            if let Some(old) = old
+                && old.format == PackageFormat::Deb
+            {
+                calls.push(invocation(old, ScriptPhase::PreRemove, Some(new)));
+            }
+            calls.push(invocation(new, pre, old));
+        }
+        Stage::AfterUnpack => {
+            if let Some(old) = old.filter(|_| old_format == Some(PackageFormat::Deb)) {
+                calls.push(invocation(old, ScriptPhase::PostRemove, Some(new)));
+            }
+            calls.push(invocation(new, post, old));
+            if let Some(old) = old.filter(|_| old_format == Some(PackageFormat::Rpm)) {
+                calls.push(invocation(old, ScriptPhase::PreRemove, Some(new)));
+            }
+        }
+        Stage::AfterCleanup => {
+            if let Some(old) = old.filter(|_| old_format == Some(PackageFormat::Rpm)) {
+                calls.push(invocation(old, ScriptPhase::PostRemove, Some(new)));
+            }
+        }
+    }
+    calls
+}
+
+/// Scripts to run before or after removing `pkg`
+pub fn remove_scripts(pkg: &PackageInfo, before: bool, purge: bool) -> Vec<Invocation<'_>> {
+    if before {
+        return vec![invocation(pkg, ScriptPhase::PreRemove, None)];
+    }
+
+    let mut calls = vec![invocation(pkg, ScriptPhase::PostRemove, None)];
+    if purge && pkg.format == PackageFormat::Deb {
+        calls.push(Invocation {
+            pkg,
+            phase: ScriptPhase::PostRemove,
+            args: vec!["purge".to_string()],
+        });
+    }
+    calls
+}
+
+/// Script of `pkg` for `phase`, with the arguments its format passes
+///
+/// `other` is the other side of an upgrade: the old version for the new
+/// package's scripts, and the new version for the old package's.
+fn invocation<'a>(
+    pkg: &'a PackageInfo,
+    phase: ScriptPhase,
+    other: Option<&PackageInfo>,
+) -> Invocation<'a> {
+    let other_version = other.map(|o| o.version.clone());
+    let args =
+        match pkg.format {
+            // preinst install|upgrade <old>, postinst configure [<old>],
+            // prerm/postrm remove|upgrade <new>
+            PackageFormat::Deb => {
+                let action = match (phase, &other_version) {
+                    (ScriptPhase::PostInstall, _) => "configure",
+                    (ScriptPhase::PreInstall, None) => "install",
+                    (_, None) => "remove",
+                    (_, Some(_)) => "upgrade",
+                };
+                std::iter::once(action.to_string())
+                    .chain(other_version)
+                    .collect()
+            }
+            // $1 is the number of versions installed once the operation is done
+            PackageFormat::Rpm => {
+                let count = match phase {
+                    ScriptPhase::PreRemove | ScriptPhase::PostRemove => other.is_some() as u32,
+                    _ => 1 + other.is_some() as u32,
+                };
+                vec![count.to_string()]
+            }
+            // pre_upgrade <new> <old>, everything else just <version>
+            _ => std::iter::once(pkg.version.clone())
+                .chain(other_version.filter(|_| {
+                    matches!(phase, ScriptPhase::PreUpgrade | ScriptPhase::PostUpgrade)
+                }))
+                .collect(),
+        };
+    Invocation { pkg, phase, args }
+}
+
+/// Run a script chrooted into `root`
+///
+/// A failing pre-phase script aborts the operation; other failures are
+/// only reported, as dpkg, rpm and pacman do.
+pub fn run(root: &Path, call: &Invocation, policy: ScriptPolicy) -> Result<(), PkgError> {
+    let Some(script) = call.pkg.scripts.iter().find(|s| s.phase == call.phase) else {
+        return Ok(());
+    };
+    let label = format!("{} {}", call.pkg.name, call.phase.name());
+
+    match policy {
+        ScriptPolicy::Run => {}
+        ScriptPolicy::Skip => return Ok(()),
+        ScriptPolicy::Log => {
+            eprintln!(
+                "Not running {} script ({} {}):\n{}",
+                label,
+                script.interpreter.join(" "),
+                call.args.join(" "),
+                script.body
+            );
+            return Ok(());
+        }
+    }
+
+    let Some(program) = script.interpreter.first().filter(|p| p.starts_with('/')) else {
+        // e.g. RPM's embedded `<lua>`
+        eprintln!(
+            "warning: {} script uses unsupported interpreter {}",
+            label,
+            script.interpreter.join(" ")
+        );
+        return Ok(());
+    };
+
+    // Written inside the root so the chrooted interpreter can read it
+    let script_file = match script.body.is_empty() {
+        true => None,
+        false => Some(write_script(root, call, &script.body)?),
+    };
+
+    let status = command(root, program)
+        .args(&script.interpreter[1..])
+        .args(script_file.as_ref().map(|(_, path)| path))
+        .args(&call.args)
+        .status();
+    if let Some((dir, _)) = script_file {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    let error = match status {
+        Ok(status) if status.success() => return Ok(()),
+        Ok(status) => format!("{} script failed: {}", label, status),
+        Err(e) => format!("{} script could not run: {}", label, e),
+    };
+    if call.phase.is_pre() {
+        return Err(PkgError::ScriptError(error));
+    }
+    eprintln!("warning: {}", error);
+    Ok(())
+}
+
+/// Write a script body to a new private directory under the root's `/tmp`
+///
+/// Returns the directory on the host and the script's path inside the root.
+/// Nothing existing is opened or followed, so a planted file or symlink
+/// cannot redirect the write.
+fn write_script(root: &Path, call: &Invocation, body: &str) -> Result<(PathBuf, String), PkgError> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let mut attempt = 0;
+    let dir = loop {
+        let name = format!(
+            "/tmp/rpm-next-{}-{}",
+            std::process::id(),
+            nanos.wrapping_add(attempt)
+        );
+        // Symlinks up to `/tmp` resolve inside the root; the new directory
+        // itself must not exist yet
+        let dir = extract::confined_path(root, &name)?;
+        if let Some(tmp) = dir.parent() {
+            std::fs::create_dir_all(tmp).map_err(PkgError::IoError)?;
+        }
+        match DirBuilder::new().mode(0o700).create(&dir) {
+            Ok(()) => break dir,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(e) => return Err(PkgError::IoError(e)),
+        }
+    };
+
+    let path = dir.join(format!("{}-{}", call.pkg.name, call.phase.name()));
+    let written = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o700)
+        .open(&path)
+        .and_then(|mut file| file.write_all(body.as_bytes()));
+    if let Err(e) = written {
+        let _ = std::fs::remove_dir_all(&dir);
+        return Err(PkgError::IoError(e));
+    }
+
+    let inside = Path::new("/").join(path.strip_prefix(root).unwrap_or(&path));
+    Ok((dir, inside.to_string_lossy().into_owned()))
+}
+
+/// Command running `program` chrooted into `root`, with a fixed `PATH`
+pub fn command(root: &Path, program: &str) -> Command {
+    let mut command = if root == Path::new("/") {
+        Command::new(program)
+    } else {
+        let mut command = Command::new("chroot");
+        command.arg(root).arg(program);
+        command
+    };
+    command.current_dir("/").env("PATH", SCRIPT_PATH);
+    command
+}
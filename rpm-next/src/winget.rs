@@ -6,8 +6,10 @@
 //! Repository: https://github.com/microsoft/winget-pkgs
 
 use std::collections::HashMap;
+use std::path::Path;
 
 use crate::{Dependency, PackageFormat, PackageInfo, PkgError, Repository};
+use crate::{archive, msi, msix};
 
 /// Winget manifest source URL
 pub const WINGET_MANIFEST_URL: &str = "https://cdn.winget.microsoft.com/cache";
@@ -56,6 +58,10 @@ pub struct WingetManifest {
     pub installer_type: InstallerType,
     pub installer_url: String,
     pub installer_sha256: String,
+    /// MSI ProductCode of the installer
+    pub product_code: String,
+    /// MSIX package family name (`Name_PublisherId`)
+    pub package_family_name: String,
     pub architecture: String,
     pub dependencies: Vec<String>,
 }
@@ -73,6 +79,8 @@ pub fn parse_manifest(yaml_content: &str) -> Result<WingetManifest, PkgError> {
         installer_type: InstallerType::Exe,
         installer_url: String::new(),
         installer_sha256: String::new(),
+        product_code: String::new(),
+        package_family_name: String::new(),
         architecture: "x64".to_string(),
         dependencies: Vec::new(),
     };
@@ -95,6 +103,8 @@ pub fn parse_manifest(yaml_content: &str) -> Result<WingetManifest, PkgError> {
                 "InstallerType" => manifest.installer_type = InstallerType::from_str(value),
                 "InstallerUrl" => manifest.installer_url = value.to_string(),
                 "InstallerSha256" | "Sha256" => manifest.installer_sha256 = value.to_string(),
+                "ProductCode" => manifest.product_code = value.to_string(),
+                "PackageFamilyName" => manifest.package_family_name = value.to_string(),
                 "Architecture" => manifest.architecture = value.to_string(),
                 _ => {}
             }
@@ -134,16 +144,16 @@ impl From<WingetManifest> for PackageInfo {
             dependencies: manifest
                 .dependencies
                 .into_iter()
-                .map(|name| Dependency {
-                    name,
-                    version_constraint: None,
-                })
+                .map(|name| Dependency::new(name, None))
                 .collect(),
             conflicts: Vec::new(),
             provides: Vec::new(),
             replaces: Vec::new(),
             files: Vec::new(),
+            file_entries: Vec::new(),
             checksum: manifest.installer_sha256,
+            scripts: Vec::new(),
+            triggers: Vec::new(),
         }
     }
 }
@@ -191,11 +201,68 @@ impl WingetRepository {
         self.cache.get(package_id)
     }
 
+    /// Every synced manifest
+    pub fn manifests(&self) -> impl Iterator<Item = &WingetManifest> {
+        self.cache.values()
+    }
+
     /// Get download URL for a package
     pub fn get_download_url(&self, manifest: &WingetManifest) -> String {
         manifest.installer_url.clone()
     }
 
+    /// Check a downloaded installer against its manifest
+    ///
+    /// MSI installers are also opened and their ProductCode compared; MSIX
+    /// installers have their block map checked and family name compared.
+    pub fn verify_installer(
+        &self,
+        manifest: &WingetManifest,
+        path: &Path,
+    ) -> Result<PackageInfo, PkgError> {
+        let actual = archive::sha256_file(path)?;
+        if !manifest.installer_sha256.is_empty()
+            && !manifest.installer_sha256.eq_ignore_ascii_case(&actual)
+        {
+            return Err(PkgError::DownloadError(format!(
+                "Checksum mismatch for {}: expected {}, got {}",
+                manifest.package_id, manifest.installer_sha256, actual
+            )));
+        }
+
+        if manifest.installer_type == InstallerType::Msix {
+            let installer = msix::read_msix(path)?;
+            let family_name = installer.family_name();
+            if !manifest.package_family_name.is_empty()
+                && !manifest
+                    .package_family_name
+                    .eq_ignore_ascii_case(&family_name)
+            {
+                return Err(PkgError::DownloadError(format!(
+                    "PackageFamilyName mismatch for {}: expected {}, got {}",
+                    manifest.package_id, manifest.package_family_name, family_name
+                )));
+            }
+            return Ok(PackageInfo::from(installer));
+        }
+        if manifest.installer_type != InstallerType::Msi {
+            return Ok(PackageInfo::from(manifest.clone()));
+        }
+
+        let installer = msi::read_msi(path)?;
+        if !manifest.product_code.is_empty()
+            && !manifest
+                .product_code
+                .eq_ignore_ascii_case(&installer.product_code)
+        {
+            return Err(PkgError::DownloadError(format!(
+                "ProductCode mismatch for {}: expected {}, got {}",
+                manifest.package_id, manifest.product_code, installer.product_code
+            )));
+        }
+        Ok(PackageInfo::from(installer))
+    }
+
     /// Fetch a single manifest from GitHub
     pub fn fetch_manifest(&mut self, package_id: &str) -> Result<WingetManifest, PkgError> {
         // Package ID format: Publisher.PackageName